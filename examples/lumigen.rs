@@ -1,6 +1,5 @@
 use aleph_alpha_api::{Client, CompletionRequest, LUMINOUS_BASE};
 use clap::Parser;
-use tokio;
 
 #[derive(Parser, Debug)]
 #[command(about = "Generate a prompt completion via the Aleph Alpha inference API")]
@@ -0,0 +1,162 @@
+//! Per-token surprisal analysis of a text, e.g. to find noisy passages in training data or
+//! hallucinated parts of a generated answer.
+//!
+//! The text is scored by echoing it with log probabilities enabled (see
+//! [`CompletionResponse::split_echo`](crate::CompletionResponse::split_echo)). The surprisal of a
+//! token is its negative log probability in nats.
+use super::client::Client;
+use super::completion::{CompletionRequest, LogProbsSetting, Modality, Prompt};
+use super::error::ApiError;
+use super::tokenization::TokenizationRequest;
+use std::ops::Range;
+
+/// Surprisal of a single token of the analyzed text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenSurprisal {
+    pub token: String,
+    /// Negative log probability of the token, `None` if the model could not score it (the very
+    /// first token of an analysis without prompt).
+    pub surprisal: Option<f64>,
+}
+
+/// Consecutive tokens with a surprisal above a threshold.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HighLossSpan {
+    /// Token indices of the span within [`SurprisalAnalysis::tokens`].
+    pub tokens: Range<usize>,
+    /// Concatenated tokens of the span.
+    pub text: String,
+    pub mean_surprisal: f64,
+    pub max_surprisal: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurprisalAnalysis {
+    /// model name and version (if any) of the used model for inference
+    pub model_version: String,
+    /// Tokens of the analyzed text, the prompt is not included.
+    pub tokens: Vec<TokenSurprisal>,
+}
+
+impl SurprisalAnalysis {
+    fn scored(&self) -> impl Iterator<Item = f64> + '_ {
+        self.tokens.iter().filter_map(|token| token.surprisal)
+    }
+
+    /// Sum of the surprisal of all scored tokens, i.e. the negative log likelihood of the text.
+    pub fn total_surprisal(&self) -> f64 {
+        self.scored().sum()
+    }
+
+    /// Mean surprisal per scored token, `NaN` if no token was scored.
+    pub fn mean_surprisal(&self) -> f64 {
+        self.total_surprisal() / self.scored().count() as f64
+    }
+
+    /// Standard deviation of the per token surprisal.
+    pub fn std_surprisal(&self) -> f64 {
+        let mean = self.mean_surprisal();
+        let count = self.scored().count() as f64;
+        (self.scored().map(|s| (s - mean).powi(2)).sum::<f64>() / count).sqrt()
+    }
+
+    /// Per token perplexity of the text.
+    pub fn perplexity(&self) -> f64 {
+        self.mean_surprisal().exp()
+    }
+
+    /// Spans of consecutive tokens whose surprisal exceeds `threshold` nats.
+    pub fn high_loss_spans(&self, threshold: f64) -> Vec<HighLossSpan> {
+        let mut spans = Vec::new();
+        let mut start = None;
+        for index in 0..=self.tokens.len() {
+            let above = self
+                .tokens
+                .get(index)
+                .and_then(|token| token.surprisal)
+                .is_some_and(|surprisal| surprisal > threshold);
+            match (above, start) {
+                (true, None) => start = Some(index),
+                (false, Some(begin)) => {
+                    spans.push(self.span(begin..index));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        spans
+    }
+
+    /// Spans of tokens with a surprisal more than `z` standard deviations above the mean.
+    pub fn outlier_spans(&self, z: f64) -> Vec<HighLossSpan> {
+        self.high_loss_spans(self.mean_surprisal() + z * self.std_surprisal())
+    }
+
+    fn span(&self, tokens: Range<usize>) -> HighLossSpan {
+        let slice = &self.tokens[tokens.clone()];
+        let surprisals = slice.iter().filter_map(|token| token.surprisal);
+        HighLossSpan {
+            text: slice.iter().map(|token| token.token.as_str()).collect(),
+            mean_surprisal: surprisals.clone().sum::<f64>() / slice.len() as f64,
+            max_surprisal: surprisals.fold(f64::NEG_INFINITY, f64::max),
+            tokens,
+        }
+    }
+}
+
+/// Computes the surprisal of each token of `text` given `prompt` as preceding context. The
+/// prompt may be empty.
+pub async fn analyze(
+    client: &Client,
+    model: &str,
+    prompt: &str,
+    text: &str,
+    nice: Option<bool>,
+) -> Result<SurprisalAnalysis, ApiError> {
+    let prompt_tokens = if prompt.is_empty() {
+        0
+    } else {
+        let tokenization = client
+            .tokenize(&TokenizationRequest {
+                model: model.to_owned(),
+                prompt: prompt.to_owned(),
+                tokens: false,
+                token_ids: true,
+            })
+            .await?;
+        tokenization.token_ids.map_or(0, |ids| ids.len())
+    };
+
+    let mut items = Vec::with_capacity(2);
+    if !prompt.is_empty() {
+        items.push(Modality::from_text(prompt, None));
+    }
+    items.push(Modality::from_text(text, None));
+    let req = CompletionRequest::new(model.to_owned(), Prompt::from_vec(items), 1)
+        .echo(true)
+        .tokens(true)
+        .log_probs(LogProbsSetting::Generated);
+
+    let response = client.completion(&req, nice).await?;
+    let split = response
+        .split_echo(&req)
+        .and_then(|splits| splits.into_iter().next())
+        .ok_or_else(|| {
+            ApiError::UnexpectedResponse("Completion does not contain the echoed tokens.".into())
+        })?;
+
+    let tokens = split
+        .prompt
+        .into_iter()
+        .skip(prompt_tokens)
+        .map(|scored| TokenSurprisal {
+            token: scored.token,
+            surprisal: scored.log_prob.map(|log_prob| -log_prob),
+        })
+        .collect();
+
+    Ok(SurprisalAnalysis {
+        model_version: response.model_version,
+        tokens,
+    })
+}
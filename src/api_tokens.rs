@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+/// Description and id of an API token. The token itself is only revealed once on creation.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiTokenMetadata {
+    /// A simple description that was supplied when creating the token.
+    pub description: String,
+
+    /// The token id, used to delete the token.
+    pub token_id: i32,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CreateApiTokenRequest {
+    /// A simple description to remember the token by.
+    pub description: String,
+}
+
+impl CreateApiTokenRequest {
+    pub fn new(description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CreateApiTokenResponse {
+    pub metadata: ApiTokenMetadata,
+
+    /// The API token that can be used in the Authorization header. It cannot be retrieved again,
+    /// so store it safely.
+    pub token: String,
+}
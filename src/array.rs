@@ -0,0 +1,36 @@
+//! Conversions of embeddings into [`ndarray`] matrices. Requires the `ndarray` feature.
+use super::embedding::{BatchSemanticEmbeddingResponse, EmbeddingResponse, Layer, Pooling};
+use ndarray::Array2;
+
+/// Stacks `rows` into a matrix, `None` if they differ in length.
+fn stack<'a>(rows: impl ExactSizeIterator<Item = &'a [f32]>) -> Option<Array2<f32>> {
+    let count = rows.len();
+    let mut data = Vec::new();
+    let mut dimension = None;
+    for row in rows {
+        if *dimension.get_or_insert(row.len()) != row.len() {
+            return None;
+        }
+        data.extend_from_slice(row);
+    }
+    Array2::from_shape_vec((count, dimension.unwrap_or(0)), data).ok()
+}
+
+impl BatchSemanticEmbeddingResponse {
+    /// Embeddings as matrix with one row per prompt. `None` if the embeddings differ in length.
+    pub fn to_array2(&self) -> Option<Array2<f32>> {
+        stack(self.embeddings.iter().map(Vec::as_slice))
+    }
+}
+
+impl EmbeddingResponse {
+    /// Embeddings of `layers` pooled with `pooling` as matrix with one row per layer, in the
+    /// order of `layers`, and one column per dimension. `None` if one of them was not returned.
+    pub fn layers_to_array2(&self, layers: &[Layer], pooling: Pooling) -> Option<Array2<f32>> {
+        let rows = layers
+            .iter()
+            .map(|layer| self.get(*layer, pooling))
+            .collect::<Option<Vec<_>>>()?;
+        stack(rows.into_iter())
+    }
+}
@@ -0,0 +1,154 @@
+use super::error::ApiError;
+use futures::stream::{self, StreamExt};
+use std::future::Future;
+use std::time::Duration;
+
+/// How often and how fast failed requests are repeated. Only transient errors (see
+/// [`ApiError::is_transient`]) are retried.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt.
+    pub max_retries: u32,
+
+    /// Delay before the first retry.
+    pub initial_backoff: Duration,
+
+    /// Upper bound for the delay between two attempts.
+    pub max_backoff: Duration,
+
+    /// Factor the delay grows with after each retry.
+    pub multiplier: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy which never retries.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before retry number `retry` (starting at 0). Delays too large to represent, e.g. after
+    /// many retries or with a non-finite multiplier, are capped at `max_backoff`.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let secs = self.initial_backoff.as_secs_f64() * self.multiplier.powf(retry.into());
+        Duration::try_from_secs_f64(secs)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+
+    /// Runs `operation` and repeats it with backoff as long as it fails with a transient error
+    /// and retries are left.
+    pub async fn run<T, F, Fut>(&self, mut operation: F) -> Result<T, ApiError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ApiError>>,
+    {
+        let mut retry = 0;
+        loop {
+            match operation().await {
+                Err(error) if error.is_transient() && retry < self.max_retries => {
+                    tokio::time::sleep(self.backoff(retry)).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Result of a batch run with retries. All indices refer to the position in the inputs.
+#[derive(Debug)]
+pub struct BatchOutcome<T> {
+    /// Successful results ordered by index, including those which needed retries.
+    pub succeeded: Vec<(usize, T)>,
+
+    /// Indices of all inputs which were retried at least once, whether they finally succeeded or
+    /// not, ordered by index.
+    pub retried: Vec<usize>,
+
+    /// Inputs which failed permanently, either with a non transient error or after all retries
+    /// were used up, ordered by index.
+    pub failed: Vec<(usize, ApiError)>,
+}
+
+impl<T> BatchOutcome<T> {
+    /// `true` if every input succeeded.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// One entry per input in input order, `None` for the failed ones.
+    pub fn into_results(self, len: usize) -> Vec<Option<T>> {
+        let mut results: Vec<Option<T>> = (0..len).map(|_| None).collect();
+        for (index, value) in self.succeeded {
+            results[index] = Some(value);
+        }
+        results
+    }
+}
+
+/// Runs `operation` for all `items` with at most `max_concurrency` calls in flight. Items failing
+/// with a transient error are collected and, after the backoff of `policy`, retried as a group
+/// until they succeed or the retries are used up.
+pub(crate) async fn run_batch<'a, I, T, F, Fut>(
+    items: &'a [I],
+    max_concurrency: usize,
+    policy: &RetryPolicy,
+    operation: F,
+) -> BatchOutcome<T>
+where
+    F: Fn(&'a I) -> Fut,
+    Fut: Future<Output = Result<T, ApiError>>,
+{
+    let mut outcome = BatchOutcome {
+        succeeded: Vec::new(),
+        retried: Vec::new(),
+        failed: Vec::new(),
+    };
+    let mut pending: Vec<usize> = (0..items.len()).collect();
+    let mut retry = 0;
+    while !pending.is_empty() {
+        let results: Vec<(usize, Result<T, ApiError>)> = stream::iter(pending)
+            .map(|index| {
+                let call = operation(&items[index]);
+                async move { (index, call.await) }
+            })
+            .buffered(max_concurrency.max(1))
+            .collect()
+            .await;
+
+        pending = Vec::new();
+        for (index, result) in results {
+            match result {
+                Ok(value) => outcome.succeeded.push((index, value)),
+                Err(error) if error.is_transient() && retry < policy.max_retries => {
+                    pending.push(index)
+                }
+                Err(error) => outcome.failed.push((index, error)),
+            }
+        }
+
+        if !pending.is_empty() {
+            outcome.retried.extend(&pending);
+            tokio::time::sleep(policy.backoff(retry)).await;
+            retry += 1;
+        }
+    }
+    outcome.retried.sort_unstable();
+    outcome.retried.dedup();
+    outcome.succeeded.sort_by_key(|(index, _)| *index);
+    outcome.failed.sort_by_key(|(index, _)| *index);
+    outcome
+}
@@ -0,0 +1,261 @@
+//! Latency and throughput benchmarks of completion requests, e.g. to compare `nice` with normal
+//! requests or an on-premise deployment with the SaaS API. Every [`BenchScenario`] fires a number
+//! of identical requests with fixed concurrency and reports latency percentiles, throughput and
+//! error rates as a [`BenchResult`].
+//!
+//! ```no_run
+//!use aleph_alpha_api::{bench::*, Client, LUMINOUS_BASE};
+//!
+//!async fn compare_nice(client: &Client) {
+//!    let normal = BenchScenario::new("normal", LUMINOUS_BASE, 256, 32).concurrency(8);
+//!    let nice = normal.clone().name("nice").nice(true);
+//!    for result in run_scenarios(client, &[normal, nice]).await {
+//!        println!(
+//!            "{}: p50 {:?}, p95 {:?}, {:.1} requests/s, {:.1}% errors",
+//!            result.scenario,
+//!            result.latency.p50,
+//!            result.latency.p95,
+//!            result.requests_per_second(),
+//!            100.0 * result.error_rate()
+//!        );
+//!    }
+//!}
+//! ```
+use super::client::Client;
+use super::completion::{CompletionRequest, Prompt};
+use super::error::ApiError;
+use futures::stream::{self, StreamExt};
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+/// A mix of requests to measure, see [`run_scenario`].
+#[derive(Debug, Clone)]
+pub struct BenchScenario {
+    pub name: String,
+    pub model: String,
+    pub prompt: Prompt,
+    pub maximum_tokens: u32,
+
+    /// Number of requests in flight at any time.
+    pub concurrency: usize,
+
+    /// Total number of requests.
+    pub requests: usize,
+
+    pub nice: Option<bool>,
+}
+
+impl BenchScenario {
+    /// 100 requests with a synthetic prompt of about `prompt_tokens` tokens, one at a time.
+    pub fn new(
+        name: impl Into<String>,
+        model: impl Into<String>,
+        prompt_tokens: usize,
+        maximum_tokens: u32,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            model: model.into(),
+            prompt: Prompt::from_text(" hello".repeat(prompt_tokens.max(1))),
+            maximum_tokens,
+            concurrency: 1,
+            requests: 100,
+            nice: None,
+        }
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Replaces the synthetic prompt.
+    pub fn prompt(mut self, prompt: Prompt) -> Self {
+        self.prompt = prompt;
+        self
+    }
+
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    pub fn requests(mut self, requests: usize) -> Self {
+        self.requests = requests;
+        self
+    }
+
+    pub fn nice(mut self, nice: bool) -> Self {
+        self.nice = Some(nice);
+        self
+    }
+
+    fn request(&self) -> CompletionRequest {
+        // Without a minimum, short completions would make the latency depend on the model's
+        // answer rather than on the scenario.
+        CompletionRequest::new(&self.model, self.prompt.clone(), self.maximum_tokens)
+            .minimum_tokens(self.maximum_tokens)
+    }
+}
+
+/// Latency distribution of the requests of a scenario. Percentiles use the nearest rank method.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencyStats {
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    pub fn from_latencies(latencies: &[Duration]) -> Self {
+        if latencies.is_empty() {
+            return Self::default();
+        }
+        let mut sorted = latencies.to_vec();
+        sorted.sort();
+        let percentile = |p: f64| {
+            let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
+        };
+        Self {
+            mean: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+            p50: percentile(50.0),
+            p95: percentile(95.0),
+            p99: percentile(99.0),
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+/// Measurements of a scenario.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchResult {
+    pub scenario: String,
+    pub requests: usize,
+
+    /// Number of failed requests, of which `transient_errors` failed with a transient error like
+    /// [`ApiError::Busy`], see [`ApiError::is_transient`].
+    pub errors: usize,
+    pub transient_errors: usize,
+
+    /// Latencies of the successful requests.
+    pub latency: LatencyStats,
+
+    /// Generated tokens of all successful requests.
+    pub generated_tokens: u64,
+
+    /// Wall clock time of the whole scenario.
+    pub duration: Duration,
+}
+
+impl BenchResult {
+    /// Share of failed requests, from 0 to 1.
+    pub fn error_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.requests as f64
+        }
+    }
+
+    /// Successful requests per second.
+    pub fn requests_per_second(&self) -> f64 {
+        per_second(self.requests - self.errors, self.duration)
+    }
+
+    /// Generated tokens per second.
+    pub fn tokens_per_second(&self) -> f64 {
+        per_second(self.generated_tokens as usize, self.duration)
+    }
+}
+
+fn per_second(count: usize, duration: Duration) -> f64 {
+    if duration.is_zero() {
+        0.0
+    } else {
+        count as f64 / duration.as_secs_f64()
+    }
+}
+
+/// Calls `operation` `requests` times with at most `concurrency` calls in flight and measures
+/// them. `operation` receives the number of the call and returns the number of generated tokens.
+/// Useful to benchmark requests not covered by [`BenchScenario`].
+pub async fn measure<F, Fut>(
+    scenario: impl Into<String>,
+    requests: usize,
+    concurrency: usize,
+    operation: F,
+) -> BenchResult
+where
+    F: Fn(usize) -> Fut,
+    Fut: Future<Output = Result<u32, ApiError>>,
+{
+    let start = Instant::now();
+    let outcomes: Vec<(Duration, Result<u32, ApiError>)> = stream::iter(0..requests)
+        .map(|call| {
+            let call = operation(call);
+            async move {
+                let start = Instant::now();
+                let result = call.await;
+                (start.elapsed(), result)
+            }
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+    let duration = start.elapsed();
+
+    let mut latencies = Vec::with_capacity(outcomes.len());
+    let (mut errors, mut transient_errors, mut generated_tokens) = (0, 0, 0);
+    for (latency, result) in outcomes {
+        match result {
+            Ok(tokens) => {
+                latencies.push(latency);
+                generated_tokens += tokens as u64;
+            }
+            Err(error) => {
+                errors += 1;
+                if error.is_transient() {
+                    transient_errors += 1;
+                }
+            }
+        }
+    }
+    BenchResult {
+        scenario: scenario.into(),
+        requests,
+        errors,
+        transient_errors,
+        latency: LatencyStats::from_latencies(&latencies),
+        generated_tokens,
+        duration,
+    }
+}
+
+/// Runs the requests of `scenario` against `client`. Requests are neither retried nor validated.
+pub async fn run_scenario(client: &Client, scenario: &BenchScenario) -> BenchResult {
+    let req = scenario.request();
+    measure(
+        scenario.name.clone(),
+        scenario.requests,
+        scenario.concurrency,
+        |_| async {
+            let response = client.completion(&req, scenario.nice).await?;
+            Ok(response.num_tokens_generated.unwrap_or_default())
+        },
+    )
+    .await
+}
+
+/// Runs the `scenarios` one after another, so they do not influence each other.
+pub async fn run_scenarios(client: &Client, scenarios: &[BenchScenario]) -> Vec<BenchResult> {
+    let mut results = Vec::with_capacity(scenarios.len());
+    for scenario in scenarios {
+        results.push(run_scenario(client, scenario).await);
+    }
+    results
+}
@@ -0,0 +1,24 @@
+//! Helpers shared by the on-disk caches.
+use super::error::ApiError;
+use std::{
+    fs,
+    path::Path,
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Writes to a temporary file first, so an interrupted run never leaves a truncated entry. The
+/// name of the temporary file is unique to the process and write, so concurrent writers of the
+/// same entry do not interfere; the last rename wins.
+pub(crate) fn write_atomically(path: &Path, bytes: &[u8]) -> Result<(), ApiError> {
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+    let write = WRITES.fetch_add(1, Ordering::Relaxed);
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.{write}.tmp", process::id()));
+    fs::write(&tmp, bytes)?;
+    if let Err(error) = fs::rename(&tmp, path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(error.into());
+    }
+    Ok(())
+}
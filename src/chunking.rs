@@ -0,0 +1,77 @@
+//! Splitting of long texts into chunks of a fixed number of tokens, e.g. to embed documents which
+//! exceed the context size of a model.
+//!
+//! ```no_run
+//!use aleph_alpha_api::{chunking::chunk_text, error::ApiError, Client, LUMINOUS_BASE};
+//!
+//!async fn chunks(client: &Client, document: &str) -> Result<(), ApiError> {
+//!    let tokenizer = client.get_tokenizer(LUMINOUS_BASE).await?;
+//!    for chunk in chunk_text(&tokenizer, document, 256, 32)? {
+//!        println!("{:?}: {}", chunk.range, chunk.text);
+//!    }
+//!    Ok(())
+//!}
+//! ```
+use super::error::{ApiError, ValidationError};
+use std::ops::Range;
+use tokenizers::Tokenizer;
+
+/// Part of a text returned by [`chunk_text`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk<'a> {
+    /// Text of the chunk, a slice of the source text.
+    pub text: &'a str,
+
+    /// Byte range of the chunk in the source text, so `&source[range] == text`.
+    pub range: Range<usize>,
+
+    /// Number of tokens of the chunk.
+    pub token_count: usize,
+}
+
+/// Splits `text` into chunks of `chunk_size` tokens of `tokenizer`, where consecutive chunks share
+/// `overlap` tokens. The last chunk may be shorter. Whitespace between tokens of different chunks
+/// is not part of any chunk.
+pub fn chunk_text<'a>(
+    tokenizer: &Tokenizer,
+    text: &'a str,
+    chunk_size: usize,
+    overlap: usize,
+) -> Result<Vec<TextChunk<'a>>, ApiError> {
+    if chunk_size == 0 {
+        return Err(ValidationError::OutOfRange {
+            parameter: "chunk_size",
+            value: 0.0,
+            min: 1.0,
+            max: usize::MAX as f64,
+        }
+        .into());
+    }
+    if overlap >= chunk_size {
+        return Err(ValidationError::OutOfRange {
+            parameter: "overlap",
+            value: overlap as f64,
+            min: 0.0,
+            max: chunk_size.saturating_sub(1) as f64,
+        }
+        .into());
+    }
+    let encoding = tokenizer.encode(text, false)?;
+    let offsets = encoding.get_offsets();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < offsets.len() {
+        let end = (start + chunk_size).min(offsets.len());
+        let range = offsets[start].0..offsets[end - 1].1;
+        chunks.push(TextChunk {
+            text: &text[range.clone()],
+            range,
+            token_count: end - start,
+        });
+        if end == offsets.len() {
+            break;
+        }
+        start = end - overlap;
+    }
+    Ok(chunks)
+}
@@ -1,26 +1,103 @@
-use super::completion::{CompletionRequest, CompletionResponse};
+use super::api_tokens::{ApiTokenMetadata, CreateApiTokenRequest, CreateApiTokenResponse};
+use super::batch::{run_batch, BatchOutcome, RetryPolicy};
+use super::chunking::chunk_text;
+use super::coalesce::Coalescer;
+use super::completion::{CompletionOutput, CompletionRequest, CompletionResponse, Prompt};
 use super::embedding::{
-    BatchSemanticEmbeddingRequest, BatchSemanticEmbeddingResponse, EmbeddingRequest,
-    EmbeddingResponse, SemanticEmbeddingRequest, SemanticEmbeddingResponse,
+    Aggregation, BatchSemanticEmbeddingRequest, BatchSemanticEmbeddingResponse, ChunkEmbedding,
+    DocumentEmbedding, EmbedBatchError, EmbedDocumentOptions, EmbedStreamOptions, EmbeddingRequest,
+    EmbeddingResponse, InstructableEmbeddingRequest, InstructableEmbeddingResponse,
+    SemanticEmbeddingRequest, SemanticEmbeddingResponse,
 };
-use super::error::ApiError;
+use super::error::{ApiError, ValidationError};
 use super::evaluate::{EvaluationRequest, EvaluationResponse};
 use super::explanation::{ExplanationRequest, ExplanationResponse};
 use super::http;
+use super::models::{ModelInfo, ModelRegistry};
+use super::postprocess::OutputPipeline;
+use super::pricing::{Budget, BudgetGuard, PricingError};
+use super::qa::{QaRequest, QaResponse};
+use super::search::{SearchRequest, SearchResponse};
+use super::summarization::{SummarizationRequest, SummarizationResponse};
 use super::tokenization::{
     DetokenizationRequest, DetokenizationResponse, TokenizationRequest, TokenizationResponse,
 };
+use super::usage::{UsageReport, UsageTracker};
+use super::users::{UserChange, UserDetail};
+use super::version::{
+    ApiCapabilities, ApiVersion, Feature, ParseVersionError, UnsupportedParameter,
+};
+use crate::impl_builder_methods;
 use bytes::Bytes;
+use futures::stream::{self, Stream, StreamExt};
+use reqwest::StatusCode;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokenizers::Tokenizer;
+use tokio::sync::{Mutex, OnceCell};
 
 pub struct Client {
     http_client: reqwest::Client,
+    /// Client without the API token, for requests to third party hosts like image downloads.
+    pub(crate) anonymous_http_client: reqwest::Client,
     pub base_url: String,
     pub api_token: String,
+    /// Tokenizers by model. Each model has its own cell, so downloads for different models do
+    /// not wait for each other.
+    pub(crate) tokenizers: Mutex<HashMap<String, TokenizerCell>>,
+    /// Capabilities of the deployment, detected on the first request in strict mode.
+    capabilities: Mutex<Option<ApiCapabilities>>,
+    /// First API versions of features, see [`Client::with_minimum_version`].
+    minimum_versions: Vec<(Feature, ApiVersion)>,
+    budget: Option<BudgetGuard>,
+    usage: Option<UsageTracker>,
+    coalescer: Option<Coalescer>,
 }
 
+pub(crate) type TokenizerCell = Arc<OnceCell<Arc<Tokenizer>>>;
+
 pub const ALEPH_ALPHA_API_BASE_URL: &str = "https://api.aleph-alpha.com";
 
+/// Optional settings for a single request, see [`Client::completion_with_options`].
+#[derive(Debug, Clone, Default)]
+pub struct RequestOptions {
+    /// Setting this to true, will signal to the API that you intend to be nice to other users by
+    /// de-prioritizing your request below concurrent ones.
+    pub nice: Option<bool>,
+
+    /// Cleanup applied to the text of every completion before it is returned.
+    pub output: Option<OutputPipeline>,
+
+    /// Check the request with [`CompletionRequest::validate`] before sending it.
+    pub validate: Option<bool>,
+
+    /// Reject requests setting parameters which the deployment does not support, see
+    /// [`CompletionRequest::unsupported_parameters`]. Older deployments silently ignore such
+    /// parameters. Strict mode also rejects parameters whose support is unknown, which are all
+    /// parameters whose minimum version is not set with [`Client::with_minimum_version`]; use
+    /// [`Client::unsupported_parameters`] to get warnings instead. The capabilities of the
+    /// deployment are detected once per client.
+    pub strict: Option<bool>,
+
+    /// Reject requests whose prompt exceeds this many bytes in the request body, see
+    /// [`crate::Prompt::check_payload_size`]. The limit is not checked by default, as it depends
+    /// on the deployment and its proxies.
+    pub max_request_bytes: Option<usize>,
+}
+
+impl_builder_methods!(
+    RequestOptions,
+    nice: bool,
+    output: OutputPipeline,
+    validate: bool,
+    strict: bool,
+    max_request_bytes: usize
+);
+
 impl Client {
     /// A new instance of an Aleph Alpha client helping you interact with the Aleph Alpha API.
     pub fn new(api_token: String) -> Result<Self, ApiError> {
@@ -32,16 +109,84 @@ impl Client {
     pub fn new_with_base_url(base_url: String, api_token: String) -> Result<Self, ApiError> {
         Ok(Self {
             http_client: http::create_client(&api_token)?,
+            anonymous_http_client: reqwest::Client::new(),
             base_url,
             api_token,
+            tokenizers: Mutex::new(HashMap::new()),
+            capabilities: Mutex::new(None),
+            minimum_versions: Vec::new(),
+            budget: None,
+            usage: None,
+            coalescer: None,
         })
     }
 
+    /// Sets the first API version supporting `feature`, so [`Client::capabilities`] reports it as
+    /// unsupported on older deployments. See [`ApiCapabilities::with_minimum_version`].
+    pub fn with_minimum_version(mut self, feature: Feature, version: ApiVersion) -> Self {
+        self.minimum_versions.push((feature, version));
+        self
+    }
+
+    /// Limits the spend of this client. Only completions are counted, as other endpoints do not
+    /// report their usage. Completion usage is counted as reported by the API; if a response
+    /// lacks it, the upper bound estimated from the request is counted instead (see
+    /// [`crate::pricing::PricingTable::estimate_cost`]). The estimate uses the tokenizer of the
+    /// model if it has been loaded, e.g. by [`Client::get_tokenizer`], and otherwise counts every
+    /// byte of text as a token. Once the spend reaches
+    /// the limit, further requests to any endpoint fail with [`ApiError::BudgetExceeded`] without
+    /// being sent. Requests in flight when the limit is reached still complete, so the spend can
+    /// exceed the limit by the usage of up to one request per concurrent task.
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = Some(BudgetGuard::new(budget));
+        self
+    }
+
+    /// Records the requests, tokens and latencies of all completions by model, see
+    /// [`Client::usage_report`]. Only requests to `/complete` are counted; embeddings,
+    /// explanations and other endpoints are not recorded, as they do not report their usage.
+    pub fn with_usage_tracking(mut self) -> Self {
+        self.usage = Some(UsageTracker::default());
+        self
+    }
+
+    /// Completion requests identical to one in flight wait for its response instead of sending
+    /// another request, e.g. when many tasks of a web server ask for the same completion. Only the
+    /// request which is actually sent counts towards budget and usage. If it fails, the waiting
+    /// requests are sent on their own.
+    pub fn with_request_coalescing(mut self) -> Self {
+        self.coalescer = Some(Coalescer::default());
+        self
+    }
+
+    /// Usage since the client was created, if it tracks usage.
+    pub fn usage_report(&self) -> Option<UsageReport> {
+        self.usage.as_ref().map(UsageTracker::report)
+    }
+
+    /// Tokens or credits spent so far, if the client has a budget.
+    pub fn spent(&self) -> Option<f64> {
+        self.budget.as_ref().map(BudgetGuard::spent)
+    }
+
     pub async fn post<I: serde::ser::Serialize, O: serde::de::DeserializeOwned>(
         &self,
         path: &str,
         data: &I,
         query: Option<Vec<(String, String)>>,
+    ) -> Result<O, ApiError> {
+        if let Some(budget) = &self.budget {
+            budget.check(None)?;
+        }
+        self.send(path, data, query).await
+    }
+
+    /// Sends a POST request without checking the budget.
+    async fn send<I: serde::ser::Serialize, O: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        data: &I,
+        query: Option<Vec<(String, String)>>,
     ) -> Result<O, ApiError> {
         use reqwest::header::{ACCEPT, CONTENT_TYPE};
 
@@ -69,12 +214,8 @@ impl Client {
         data: &I,
         nice: Option<bool>,
     ) -> Result<O, ApiError> {
-        let query = if let Some(be_nice) = nice {
-            Some(vec![("nice".to_owned(), be_nice.to_string())])
-        } else {
-            None
-        };
-        Ok(self.post(path, data, query).await?)
+        let query = nice.map(|be_nice| vec![("nice".to_owned(), be_nice.to_string())]);
+        self.post(path, data, query).await
     }
 
     pub async fn get<O: serde::de::DeserializeOwned>(&self, path: &str) -> Result<O, ApiError> {
@@ -125,7 +266,122 @@ impl Client {
         req: &CompletionRequest,
         nice: Option<bool>,
     ) -> Result<CompletionResponse, ApiError> {
-        Ok(self.post_nice("/complete", req, nice).await?)
+        if let Some(budget) = &self.budget {
+            budget.check(Some(&req.model))?;
+        }
+        match &self.coalescer {
+            Some(coalescer) => {
+                coalescer
+                    .run(req, nice, || self.send_completion(req, nice))
+                    .await
+            }
+            None => self.send_completion(req, nice).await,
+        }
+    }
+
+    /// Sends a completion request and counts it towards budget and usage.
+    async fn send_completion(
+        &self,
+        req: &CompletionRequest,
+        nice: Option<bool>,
+    ) -> Result<CompletionResponse, ApiError> {
+        // The budget has been checked by `completion`, including the price of the model.
+        let query = nice.map(|be_nice| vec![("nice".to_owned(), be_nice.to_string())]);
+        let start = Instant::now();
+        let response = self.send("/complete", req, query).await;
+        if let Some(usage) = &self.usage {
+            usage.record(&req.model, start.elapsed(), response.as_ref().ok());
+        }
+        let response = response?;
+        if let Some(budget) = &self.budget {
+            self.record_spend(budget, req, &response).await;
+        }
+        Ok(response)
+    }
+
+    /// Counts `response` towards `budget`, estimating the usage from `req` if the response does
+    /// not report it. The response has been paid for, so failing to count it must not fail the
+    /// request. The estimate uses the tokenizer of the model if it has been loaded already;
+    /// otherwise, or if it fails to encode the prompt, every byte of text counts as a token.
+    async fn record_spend(
+        &self,
+        budget: &BudgetGuard,
+        req: &CompletionRequest,
+        response: &CompletionResponse,
+    ) {
+        if let Err(PricingError::MissingUsage) = budget.record(&req.model, response) {
+            let tokenizer = self.tokenizer_cell(&req.model).await.get().cloned();
+            if budget.record_estimate(req, tokenizer.as_deref()).is_err() {
+                let _ = budget.record_estimate(req, None);
+            }
+        }
+    }
+
+    /// Like [`Client::completion`], additionally validating the request and running the output
+    /// pipeline on all completions if requested by `options`.
+    pub async fn completion_with_options(
+        &self,
+        req: &CompletionRequest,
+        options: &RequestOptions,
+    ) -> Result<CompletionResponse, ApiError> {
+        if options.validate == Some(true) {
+            req.validate()?;
+        }
+        if let Some(limit) = options.max_request_bytes {
+            req.prompt.check_payload_size(limit)?;
+        }
+        if options.strict == Some(true) {
+            let capabilities = self.detected_capabilities().await?;
+            let parameters = req.unsupported_parameters(&capabilities);
+            if !parameters.is_empty() {
+                return Err(ValidationError::Unsupported {
+                    version: capabilities.version,
+                    parameters,
+                }
+                .into());
+            }
+        }
+        let mut response = self.completion(req, options.nice).await?;
+        if let Some(output) = &options.output {
+            output.apply_to_response(&mut response, &req.prompt);
+        }
+        Ok(response)
+    }
+
+    /// Runs many completion requests concurrently with at most `max_concurrency` requests in
+    /// flight at any time. The results are returned in the order of `requests`; a failing
+    /// request (e.g. with [`ApiError::TooManyRequests`]) does not affect the others.
+    ///
+    /// There is no client side rate limiting: `max_concurrency` is the only throttle, and requests
+    /// rejected by the rate limit of the API are returned as errors without being retried. Use
+    /// [`Client::complete_all_with_retry`] to retry them.
+    pub async fn complete_all(
+        &self,
+        requests: &[CompletionRequest],
+        max_concurrency: usize,
+        nice: Option<bool>,
+    ) -> Vec<Result<CompletionResponse, ApiError>> {
+        stream::iter(requests)
+            .map(|req| self.completion(req, nice))
+            .buffered(max_concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    /// Like [`Client::complete_all`], but requests failing with a transient error (see
+    /// [`ApiError::is_transient`]) are retried according to `policy`. Only the failed requests
+    /// are repeated.
+    pub async fn complete_all_with_retry(
+        &self,
+        requests: &[CompletionRequest],
+        max_concurrency: usize,
+        policy: &RetryPolicy,
+        nice: Option<bool>,
+    ) -> BatchOutcome<CompletionResponse> {
+        run_batch(requests, max_concurrency, policy, |req| {
+            self.completion(req, nice)
+        })
+        .await
     }
 
     /// Evaluates the model's likelihood to produce a completion given a prompt.
@@ -134,7 +390,30 @@ impl Client {
         req: &EvaluationRequest,
         nice: Option<bool>,
     ) -> Result<EvaluationResponse, ApiError> {
-        Ok(self.post_nice("/evaluate", req, nice).await?)
+        self.post_nice("/evaluate", req, nice).await
+    }
+
+    /// Answers a question about a set of documents.
+    pub async fn qa(&self, req: &QaRequest, nice: Option<bool>) -> Result<QaResponse, ApiError> {
+        self.post_nice("/qa", req, nice).await
+    }
+
+    /// Finds the sections of a set of documents which are most similar to a query.
+    pub async fn search(
+        &self,
+        req: &SearchRequest,
+        nice: Option<bool>,
+    ) -> Result<SearchResponse, ApiError> {
+        self.post_nice("/search", req, nice).await
+    }
+
+    /// Summarizes a document.
+    pub async fn summarize(
+        &self,
+        req: &SummarizationRequest,
+        nice: Option<bool>,
+    ) -> Result<SummarizationResponse, ApiError> {
+        self.post_nice("/summarize", req, nice).await
     }
 
     /// Better understand the source of a completion, specifically on how much each section of a prompt impacts each token of the completion.
@@ -143,7 +422,23 @@ impl Client {
         req: &ExplanationRequest,
         nice: Option<bool>,
     ) -> Result<ExplanationResponse, ApiError> {
-        Ok(self.post_nice("/explain", req, nice).await?)
+        self.post_nice("/explain", req, nice).await
+    }
+
+    /// Runs many explanation requests concurrently with at most `max_concurrency` requests in
+    /// flight, retrying those which fail with a transient error according to `policy`. Results
+    /// are ordered by the index of their request.
+    pub async fn explain_all(
+        &self,
+        requests: &[ExplanationRequest],
+        max_concurrency: usize,
+        policy: &RetryPolicy,
+        nice: Option<bool>,
+    ) -> BatchOutcome<ExplanationResponse> {
+        run_batch(requests, max_concurrency, policy, |req| {
+            self.explain(req, nice)
+        })
+        .await
     }
 
     /// Embeds a text using a specific model. Resulting vectors that can be used for downstream tasks (e.g. semantic similarity) and models (e.g. classifiers).
@@ -152,7 +447,7 @@ impl Client {
         req: &EmbeddingRequest,
         nice: Option<bool>,
     ) -> Result<EmbeddingResponse, ApiError> {
-        Ok(self.post_nice("/embed", req, nice).await?)
+        self.post_nice("/embed", req, nice).await
     }
 
     /// Embeds a prompt using a specific model and semantic embedding method. Resulting vectors that can be used for downstream tasks (e.g. semantic similarity) and models (e.g. classifiers). To obtain a valid model,
@@ -161,7 +456,31 @@ impl Client {
         req: &SemanticEmbeddingRequest,
         nice: Option<bool>,
     ) -> Result<SemanticEmbeddingResponse, ApiError> {
-        Ok(self.post_nice("/semantic_embed", req, nice).await?)
+        self.post_nice("/semantic_embed", req, nice).await
+    }
+
+    /// Embeds a prompt conditioned on an instruction describing the downstream task.
+    pub async fn instructable_embed(
+        &self,
+        req: &InstructableEmbeddingRequest,
+        nice: Option<bool>,
+    ) -> Result<InstructableEmbeddingResponse, ApiError> {
+        self.post_nice("/instructable_embed", req, nice).await
+    }
+
+    /// Runs many semantic embedding requests concurrently, retrying those which fail with a
+    /// transient error according to `policy`.
+    pub async fn semantic_embed_all_with_retry(
+        &self,
+        requests: &[SemanticEmbeddingRequest],
+        max_concurrency: usize,
+        policy: &RetryPolicy,
+        nice: Option<bool>,
+    ) -> BatchOutcome<SemanticEmbeddingResponse> {
+        run_batch(requests, max_concurrency, policy, |req| {
+            self.semantic_embed(req, nice)
+        })
+        .await
     }
 
     /// Embeds multiple prompts using a specific model and semantic embedding method. Resulting vectors that can be used for downstream tasks (e.g. semantic similarity) and models (e.g. classifiers).
@@ -170,7 +489,132 @@ impl Client {
         req: &BatchSemanticEmbeddingRequest,
         nice: Option<bool>,
     ) -> Result<BatchSemanticEmbeddingResponse, ApiError> {
-        Ok(self.post_nice("/batch_semantic_embed", req, nice).await?)
+        self.post_nice("/batch_semantic_embed", req, nice).await
+    }
+
+    /// Like [`Client::batch_semantic_embed`], but splits the prompts into requests of at most
+    /// `chunk_size` prompts, which are sent with at most `max_concurrency` requests in flight.
+    /// The embeddings are returned in the order of `req.prompts`. Fails if any chunk fails.
+    pub async fn batch_semantic_embed_chunked(
+        &self,
+        req: &BatchSemanticEmbeddingRequest,
+        chunk_size: usize,
+        max_concurrency: usize,
+        nice: Option<bool>,
+    ) -> Result<BatchSemanticEmbeddingResponse, ApiError> {
+        let chunks: Vec<BatchSemanticEmbeddingRequest> = req
+            .prompts
+            .chunks(chunk_size.max(1))
+            .map(|prompts| req.with_prompts(prompts.to_vec()))
+            .collect();
+        let responses: Vec<Result<BatchSemanticEmbeddingResponse, ApiError>> = stream::iter(chunks)
+            .map(|chunk| async move { self.batch_semantic_embed(&chunk, nice).await })
+            .buffered(max_concurrency.max(1))
+            .collect()
+            .await;
+
+        let mut model_version = String::new();
+        let mut embeddings = Vec::with_capacity(req.prompts.len());
+        for response in responses {
+            let response = response?;
+            model_version = response.model_version;
+            embeddings.extend(response.embeddings);
+        }
+        Ok(BatchSemanticEmbeddingResponse {
+            model_version,
+            embeddings,
+        })
+    }
+
+    /// Embeds a document which may exceed the context size of the model. The document is split
+    /// into chunks of `options.chunk_size` tokens, which are embedded with batch requests and
+    /// combined with `aggregation`.
+    pub async fn embed_document(
+        &self,
+        text: &str,
+        aggregation: Aggregation,
+        options: &EmbedDocumentOptions,
+    ) -> Result<DocumentEmbedding, ApiError> {
+        let tokenizer = self.get_tokenizer(&options.model).await?;
+        let chunks = chunk_text(&tokenizer, text, options.chunk_size, options.overlap)?;
+        if chunks.is_empty() {
+            return Err(ValidationError::EmptyPrompt.into());
+        }
+        let req = BatchSemanticEmbeddingRequest {
+            model: options.model.clone(),
+            prompts: chunks
+                .iter()
+                .map(|chunk| Prompt::from_text(chunk.text))
+                .collect(),
+            representation: options.representation,
+            compress_to_size: options.compress_to_size,
+            normalize: options.normalize,
+            ..BatchSemanticEmbeddingRequest::default()
+        };
+        let response = self
+            .batch_semantic_embed_chunked(
+                &req,
+                options.batch_size,
+                options.max_concurrency,
+                options.nice,
+            )
+            .await?;
+
+        let chunks: Vec<ChunkEmbedding> = chunks
+            .into_iter()
+            .zip(response.embeddings)
+            .map(|(chunk, embedding)| ChunkEmbedding {
+                range: chunk.range,
+                token_count: chunk.token_count,
+                embedding,
+            })
+            .collect();
+        let weighted: Vec<(&[f32], usize)> = chunks
+            .iter()
+            .map(|chunk| (chunk.embedding.as_slice(), chunk.token_count))
+            .collect();
+        Ok(DocumentEmbedding {
+            model_version: response.model_version,
+            embedding: aggregation.aggregate(&weighted),
+            chunks,
+        })
+    }
+
+    /// Embeds a possibly huge stream of prompts with batch requests of `options.batch_size`
+    /// prompts. At most `options.max_concurrency` batches are in flight, and prompts are only
+    /// pulled from `prompts` as fast as the returned stream is consumed. Each embedding is
+    /// yielded together with the index of its prompt, in input order. Batches failing with a
+    /// transient error are retried; a batch failing permanently yields a single
+    /// [`EmbedBatchError`] and the stream continues with the next batch.
+    pub fn embed_stream<'a>(
+        &'a self,
+        prompts: impl Stream<Item = Prompt> + 'a,
+        options: EmbedStreamOptions,
+    ) -> impl Stream<Item = Result<(usize, Vec<f32>), EmbedBatchError>> + 'a {
+        let batch_size = options.batch_size.max(1);
+        let max_concurrency = options.max_concurrency.max(1);
+        prompts
+            .chunks(batch_size)
+            .enumerate()
+            .map(move |(batch, prompts)| {
+                let start = batch * batch_size;
+                let indices = start..start + prompts.len();
+                let req = options.to_request(prompts);
+                let retry = options.retry.clone();
+                let nice = options.nice;
+                async move {
+                    let result = retry.run(|| self.batch_semantic_embed(&req, nice)).await;
+                    (indices, result)
+                }
+            })
+            .buffered(max_concurrency)
+            .flat_map(|(indices, result)| {
+                let items: Vec<_> = match result {
+                    Ok(response) => indices.zip(response.embeddings).map(Ok).collect(),
+                    Err(source) => vec![Err(EmbedBatchError { indices, source })],
+                };
+                stream::iter(items)
+            })
     }
 
     /// Tokenize a prompt for a specific model.
@@ -178,7 +622,7 @@ impl Client {
         &self,
         req: &TokenizationRequest,
     ) -> Result<TokenizationResponse, ApiError> {
-        Ok(self.post("/tokenize", req, None).await?)
+        self.post("/tokenize", req, None).await
     }
 
     /// Detokenize a list of tokens into a string.
@@ -186,7 +630,47 @@ impl Client {
         &self,
         req: &DetokenizationRequest,
     ) -> Result<DetokenizationResponse, ApiError> {
-        Ok(self.post("/detokenize", req, None).await?)
+        self.post("/detokenize", req, None).await
+    }
+
+    /// Reconstructs the text of the `completion_tokens` of `output` with the `/detokenize`
+    /// endpoint. The tokens are mapped to ids with the tokenizer of `model`, so the completion
+    /// must have been requested with `tokens` set.
+    pub async fn detokenize_output(
+        &self,
+        model: &str,
+        output: &CompletionOutput,
+    ) -> Result<String, ApiError> {
+        let tokens = output.completion_tokens.as_ref().ok_or_else(|| {
+            ApiError::UnexpectedResponse("Completion was requested without tokens.".into())
+        })?;
+        let tokenizer = self.get_tokenizer(model).await?;
+        let token_ids = tokens
+            .iter()
+            .map(|token| {
+                tokenizer.token_to_id(token).ok_or_else(|| {
+                    ApiError::UnexpectedResponse(format!("Unknown token '{token}'."))
+                })
+            })
+            .collect::<Result<Vec<u32>, _>>()?;
+        let req = DetokenizationRequest {
+            model: model.to_owned(),
+            token_ids,
+        };
+        Ok(self.detokenize(&req).await?.result)
+    }
+
+    /// [`Client::detokenize_output`] for every completion of `response`.
+    pub async fn detokenize_outputs(
+        &self,
+        model: &str,
+        response: &CompletionResponse,
+    ) -> Result<Vec<String>, ApiError> {
+        let mut texts = Vec::with_capacity(response.completions.len());
+        for output in &response.completions {
+            texts.push(self.detokenize_output(model, output).await?);
+        }
+        Ok(texts)
     }
 
     pub async fn get_tokenizer_binary(&self, model: &str) -> Result<Bytes, ApiError> {
@@ -195,14 +679,202 @@ impl Client {
         Ok(vocabulary)
     }
 
-    pub async fn get_tokenizer(&self, model: &str) -> Result<Tokenizer, ApiError> {
-        let vocabulary = self.get_tokenizer_binary(model).await?;
-        let tokenizer = Tokenizer::from_bytes(vocabulary)?;
-        Ok(tokenizer)
+    /// Downloads the tokenizer vocabulary of `model` unless it still matches `validators` of a
+    /// cached copy, in which case `None` is returned.
+    pub async fn get_tokenizer_binary_if_modified(
+        &self,
+        model: &str,
+        validators: &http::CacheValidators,
+    ) -> Result<Option<(Bytes, http::CacheValidators)>, ApiError> {
+        let path = format!("/models/{model}/tokenizer");
+        let Some(response) =
+            http::get_if_modified(&self.http_client, &self.base_url, &path, validators).await?
+        else {
+            return Ok(None);
+        };
+        let validators = http::CacheValidators::from_response(&response);
+        Ok(Some((response.bytes().await?, validators)))
+    }
+
+    /// Tokenizer of `model`. It is downloaded on the first call for a model and shared by all
+    /// later calls.
+    pub async fn get_tokenizer(&self, model: &str) -> Result<Arc<Tokenizer>, ApiError> {
+        // Concurrent calls for the same model wait for the first download instead of starting
+        // their own; calls for other models are not blocked.
+        let cell = self.tokenizer_cell(model).await;
+        let tokenizer = cell
+            .get_or_try_init(|| async {
+                let vocabulary = self.get_tokenizer_binary(model).await?;
+                Ok::<_, ApiError>(Arc::new(Tokenizer::from_bytes(vocabulary)?))
+            })
+            .await?;
+        Ok(tokenizer.clone())
+    }
+
+    /// Cell holding the tokenizer of `model`, empty until it is loaded.
+    pub(crate) async fn tokenizer_cell(&self, model: &str) -> TokenizerCell {
+        self.tokenizers
+            .lock()
+            .await
+            .entry(model.to_owned())
+            .or_default()
+            .clone()
+    }
+
+    /// Number of tokens of `text` for `model`, counted with the local tokenizer instead of the
+    /// `/tokenize` endpoint. Only the first call for a model needs a request.
+    pub async fn count_tokens(&self, model: &str, text: &str) -> Result<usize, ApiError> {
+        let tokenizer = self.get_tokenizer(model).await?;
+        Ok(tokenizer.encode(text, false)?.len())
+    }
+
+    /// Models available to the user of the API token, e.g. to populate a model picker or to check
+    /// configured model names at startup.
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>, ApiError> {
+        self.get("/models_available").await
+    }
+
+    /// [`ModelRegistry::luminous`] updated with the models currently offered by the API.
+    pub async fn model_registry(&self) -> Result<ModelRegistry, ApiError> {
+        let mut registry = ModelRegistry::luminous();
+        registry.update(&self.list_models().await?);
+        Ok(registry)
+    }
+
+    /// All API tokens of the user, without the tokens themselves.
+    pub async fn list_api_tokens(&self) -> Result<Vec<ApiTokenMetadata>, ApiError> {
+        self.get("/users/me/tokens").await
+    }
+
+    /// Creates a new API token. The response holds the token, which cannot be retrieved later.
+    pub async fn create_api_token(
+        &self,
+        req: &CreateApiTokenRequest,
+    ) -> Result<CreateApiTokenResponse, ApiError> {
+        self.post("/users/me/tokens", req, None).await
+    }
+
+    /// Deletes the API token with `token_id`, see [`ApiTokenMetadata::token_id`].
+    pub async fn delete_api_token(&self, token_id: i32) -> Result<(), ApiError> {
+        let path = format!("/users/me/tokens/{token_id}");
+        http::delete(&self.http_client, &self.base_url, &path).await?;
+        Ok(())
+    }
+
+    /// Settings and remaining credits of the user owning the API token.
+    pub async fn get_user_settings(&self) -> Result<UserDetail, ApiError> {
+        self.get("/users/me").await
+    }
+
+    /// Credits left on the account of the user owning the API token.
+    pub async fn remaining_credits(&self) -> Result<f64, ApiError> {
+        Ok(self.get_user_settings().await?.credits_remaining)
+    }
+
+    /// Checks the remaining credits every `interval` and calls `on_low` with them whenever they
+    /// drop below `threshold`, i.e. once on each crossing rather than on every check. Meant to
+    /// run alongside a long batch job, e.g. with `tokio::select!`. Transient errors are skipped;
+    /// only other errors end the watch.
+    pub async fn watch_credits(
+        &self,
+        threshold: f64,
+        interval: Duration,
+        mut on_low: impl FnMut(f64),
+    ) -> Result<Infallible, ApiError> {
+        let mut was_low = false;
+        loop {
+            match self.remaining_credits().await {
+                Ok(credits) => {
+                    let is_low = credits < threshold;
+                    if is_low && !was_low {
+                        on_low(credits);
+                    }
+                    was_low = is_low;
+                }
+                Err(error) if error.is_transient() => {}
+                Err(error) => return Err(error),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Changes the settings of the user owning the API token and returns the updated settings.
+    pub async fn change_user_settings(&self, change: &UserChange) -> Result<UserDetail, ApiError> {
+        let url = format!("{}/users/me", self.base_url);
+        let response = self.http_client.patch(url).json(change).send().await?;
+        let response = http::translate_http_error(response).await?;
+        Ok(response.json().await?)
     }
 
     /// Will return the version number of the API that is deployed to this environment.
     pub async fn get_version(&self) -> Result<String, ApiError> {
-        Ok(self.get_string("/version").await?)
+        self.get_string("/version").await
+    }
+
+    /// Features available on the connected deployment. Useful for on-premise installations which
+    /// may run an older version of the API. Features with an endpoint of their own (see
+    /// [`Feature::endpoint`]) are detected by probing the endpoint, other features are derived
+    /// from the version of the deployment and the minimum versions set with
+    /// [`Client::with_minimum_version`]. Support of other features is unknown, see
+    /// [`ApiCapabilities::supports`].
+    pub async fn capabilities(&self) -> Result<ApiCapabilities, ApiError> {
+        let version = self.get_version().await?;
+        let version = version
+            .parse()
+            .map_err(|error: ParseVersionError| ApiError::UnexpectedResponse(error.to_string()))?;
+        let mut capabilities = self.minimum_versions.iter().fold(
+            ApiCapabilities::new(version),
+            |capabilities, (feature, since)| {
+                capabilities.with_minimum_version(*feature, since.clone())
+            },
+        );
+        for feature in Feature::ALL {
+            if let Some(path) = feature.endpoint() {
+                let supported = self.probe_endpoint(path).await?;
+                capabilities = capabilities.with_support(feature, supported);
+            }
+        }
+        Ok(capabilities)
+    }
+
+    /// Whether the deployment serves the endpoint at `path`. Sends an empty request, which an
+    /// existing endpoint rejects as invalid without running a model.
+    async fn probe_endpoint(&self, path: &str) -> Result<bool, ApiError> {
+        let url = format!("{}{path}", self.base_url);
+        let response = self
+            .http_client
+            .post(url)
+            .json(&serde_json::json!({}))
+            .send()
+            .await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS => {
+                http::translate_http_error(response).await.map(|_| true)
+            }
+            _ => Ok(true),
+        }
+    }
+
+    /// Parameters set in `req` which the connected deployment does not support, see
+    /// [`CompletionRequest::unsupported_parameters`]. Unlike [`RequestOptions::strict`], this
+    /// does not fail the request, so callers may report the parameters as warnings.
+    pub async fn unsupported_parameters(
+        &self,
+        req: &CompletionRequest,
+    ) -> Result<Vec<UnsupportedParameter>, ApiError> {
+        let capabilities = self.detected_capabilities().await?;
+        Ok(req.unsupported_parameters(&capabilities))
+    }
+
+    /// Like [`Client::capabilities`], but only the first call sends a request.
+    async fn detected_capabilities(&self) -> Result<ApiCapabilities, ApiError> {
+        let mut capabilities = self.capabilities.lock().await;
+        if let Some(capabilities) = capabilities.as_ref() {
+            return Ok(capabilities.clone());
+        }
+        let detected = self.capabilities().await?;
+        *capabilities = Some(detected.clone());
+        Ok(detected)
     }
 }
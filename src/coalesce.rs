@@ -0,0 +1,90 @@
+use super::completion::{CompletionRequest, CompletionResponse};
+use super::error::ApiError;
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::Mutex,
+};
+use tokio::sync::watch;
+
+/// State of an in-flight request shared with its followers: `None` while pending, then
+/// `Some(None)` if it failed.
+type Outcome = Option<Option<CompletionResponse>>;
+
+/// Serialized request, see [`canonical_request`], and `nice` flag.
+type Key = (Vec<u8>, Option<bool>);
+
+/// Serializes `req` with the entries of `logit_bias` ordered by token, as the iteration order of a
+/// `HashMap` differs between otherwise equal requests.
+fn canonical_request(req: &CompletionRequest) -> Result<Vec<u8>, serde_json::Error> {
+    let mut value = serde_json::to_value(req)?;
+    if let Some(logit_bias) = &req.logit_bias {
+        let logit_bias: BTreeMap<_, _> = logit_bias.iter().collect();
+        value["logit_bias"] = serde_json::to_value(logit_bias)?;
+    }
+    serde_json::to_vec(&value)
+}
+
+/// Shares the response of an in-flight completion with identical requests issued while it is
+/// pending, see [`crate::Client::with_request_coalescing`].
+#[derive(Debug, Default)]
+pub(crate) struct Coalescer {
+    in_flight: Mutex<HashMap<Key, watch::Receiver<Outcome>>>,
+}
+
+/// Removes a request from the in-flight requests once its leader finishes or is cancelled.
+struct InFlight<'a> {
+    coalescer: &'a Coalescer,
+    key: Key,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.coalescer.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+impl Coalescer {
+    /// Runs `send` unless an identical request is in flight, in which case its response is
+    /// returned instead. As [`ApiError`]s cannot be shared, followers of a failed or cancelled
+    /// request run `send` themselves.
+    pub(crate) async fn run<F: Future<Output = Result<CompletionResponse, ApiError>>>(
+        &self,
+        req: &CompletionRequest,
+        nice: Option<bool>,
+        send: impl FnOnce() -> F,
+    ) -> Result<CompletionResponse, ApiError> {
+        let key = (canonical_request(req)?, nice);
+        let (sender, receiver) = watch::channel(None);
+        let leader = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(leader) => Some(leader.clone()),
+                None => {
+                    in_flight.insert(key.clone(), receiver);
+                    None
+                }
+            }
+        };
+        match leader {
+            Some(mut leader) => {
+                if let Ok(outcome) = leader.wait_for(Option::is_some).await {
+                    if let Some(Some(response)) = &*outcome {
+                        return Ok(response.clone());
+                    }
+                }
+                send().await
+            }
+            None => {
+                let in_flight = InFlight {
+                    coalescer: self,
+                    key,
+                };
+                let response = send().await;
+                drop(in_flight);
+                sender.send_replace(Some(response.as_ref().ok().cloned()));
+                response
+            }
+        }
+    }
+}
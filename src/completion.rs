@@ -1,18 +1,31 @@
-use super::image_processing::{from_image_path, preprocess_image, LoadImageError};
-use crate::impl_builder_methods;
+use super::client::Client;
+use super::error::{ApiError, BudgetError, FetchImageError, ValidationError};
+use super::http::translate_http_error;
+use super::image_processing::{
+    center_cropped, decode_image, encode_png, from_image_path, load_image, preprocess_image,
+    preprocess_image_bytes, preprocess_image_with_crop, preprocess_image_with_options,
+    ImageOptions, LoadImageError, DESIRED_IMAGE_SIZE,
+};
+use super::version::{ApiCapabilities, Feature, UnsupportedParameter};
+use crate::{impl_builder_methods, impl_control_parameters};
+use base64::display::Base64Display;
 use base64::prelude::{Engine as _, BASE64_STANDARD};
-use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, path::Path};
-
-#[derive(Serialize, Debug)]
+use bytes::Bytes;
+use image::imageops::FilterType::CatmullRom;
+use reqwest::header;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    ops::{Index, Range},
+    path::Path,
+};
+use tokenizers::{Encoding, Tokenizer};
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct Prompt(Vec<Modality>);
 
-impl Default for Prompt {
-    fn default() -> Self {
-        Self(vec![])
-    }
-}
-
 impl Prompt {
     pub fn empty() -> Self {
         Self::default()
@@ -27,17 +40,295 @@ impl Prompt {
         Self(vec![Modality::from_text(text, Some(controls))])
     }
 
+    /// Create a text prompt with attention controls for the given character ranges of `text`,
+    /// i.e. ranges counting `char`s rather than bytes, as expected by [`TextControl`]. Returns
+    /// `None` if a range is out of bounds or decreasing.
+    ///
+    /// ```
+    ///use aleph_alpha_api::Prompt;
+    ///
+    ///let text = "Größe matters";
+    ///let start = text.chars().count() - "matters".len();
+    ///let prompt = Prompt::from_text_with_highlights(text, &[(start..start + 7, 2.0)]).unwrap();
+    /// ```
+    pub fn from_text_with_highlights(
+        text: impl Into<String>,
+        highlights: &[(Range<usize>, f64)],
+    ) -> Option<Self> {
+        let text = text.into();
+        let chars = text.chars().count();
+        let controls = highlights
+            .iter()
+            .map(|(range, factor)| {
+                if range.start > range.end || range.end > chars {
+                    return None;
+                }
+                Some(TextControl::new(
+                    i32::try_from(range.start).ok()?,
+                    i32::try_from(range.len()).ok()?,
+                    *factor,
+                ))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self::from_text_with_controls(text, controls))
+    }
+
     pub fn from_token_ids(ids: Vec<u32>, controls: Option<Vec<TokenControl>>) -> Self {
         Self(vec![Modality::from_token_ids(ids, controls)])
     }
 
+    /// Create a token ids prompt from the output of a tokenizer, e.g. one obtained via
+    /// [`crate::Client::get_tokenizer`].
+    pub fn from_encoding(encoding: &Encoding) -> Self {
+        Self::from_token_ids(encoding.get_ids().to_vec(), None)
+    }
+
+    /// Create a token ids prompt from the output of a tokenizer and apply the given attention
+    /// factor to all tokens in each range. Ranges are clipped to the number of tokens.
+    pub fn from_encoding_with_controls(
+        encoding: &Encoding,
+        controls: &[(Range<u32>, f64)],
+    ) -> Self {
+        let ids = encoding.get_ids().to_vec();
+        let count = ids.len() as u32;
+        let controls = controls
+            .iter()
+            .flat_map(|(range, factor)| {
+                TokenControl::for_range(range.start.min(count)..range.end.min(count), *factor)
+            })
+            .collect();
+        Self::from_token_ids(ids, Some(controls))
+    }
+
     /// Create a multimodal prompt from a list of individual items with any modality.
     pub fn from_vec(items: Vec<Modality>) -> Self {
         Self(items)
     }
+
+    /// Append an item of any modality.
+    pub fn push(&mut self, item: Modality) -> &mut Self {
+        self.0.push(item);
+        self
+    }
+
+    /// Append a text item.
+    pub fn push_text(&mut self, text: impl Into<String>) -> &mut Self {
+        self.push(Modality::from_text(text, None))
+    }
+
+    /// Append an image item, see [`Modality::from_image`].
+    pub fn push_image(&mut self, image: &image::DynamicImage) -> Result<&mut Self, LoadImageError> {
+        Ok(self.push(Modality::from_image(image)?))
+    }
+
+    /// Append an image item loaded from a file, see [`Modality::from_image_path`].
+    pub fn push_image_path(&mut self, path: impl AsRef<Path>) -> Result<&mut Self, LoadImageError> {
+        Ok(self.push(Modality::from_image_path(path)?))
+    }
+
+    /// Number of items in the prompt.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Modality> {
+        self.0.iter()
+    }
+
+    pub fn items(&self) -> &[Modality] {
+        &self.0
+    }
+
+    pub fn into_items(self) -> Vec<Modality> {
+        self.0
+    }
+
+    /// Estimated number of tokens the prompt occupies in the model's context. Text items are
+    /// tokenized with the given tokenizer (see [`crate::Client::get_tokenizer`]), token ids are
+    /// counted as they are and each image counts as [`IMAGE_TOKEN_COUNT`] tokens.
+    pub fn estimate_tokens(&self, tokenizer: &Tokenizer) -> Result<usize, ApiError> {
+        self.iter()
+            .map(|item| item.estimate_tokens(tokenizer))
+            .sum()
+    }
+
+    /// Size in bytes of every item of the prompt in the JSON request body. Dominated by images,
+    /// which grow by a third through base64 encoding.
+    pub fn payload_sizes(&self) -> Vec<usize> {
+        self.iter().map(Modality::payload_size).collect()
+    }
+
+    /// Checks that the prompt does not exceed `limit` bytes in the request body, so oversized
+    /// multimodal requests fail with per-item sizes instead of an HTTP 413 from the server. The
+    /// limit depends on the deployment, see [`crate::RequestOptions::max_request_bytes`].
+    pub fn check_payload_size(&self, limit: usize) -> Result<(), ValidationError> {
+        let item_sizes = self.payload_sizes();
+        let size = item_sizes.iter().sum();
+        if size > limit {
+            return Err(ValidationError::PayloadTooLarge {
+                size,
+                limit,
+                item_sizes,
+            });
+        }
+        Ok(())
+    }
+
+    /// Number of tokens of every item of the prompt, counted like in [`Prompt::estimate_tokens`].
+    pub fn tokenize(&self, tokenizer: &Tokenizer) -> Result<PromptTokenCounts, ApiError> {
+        let items = self
+            .iter()
+            .map(|item| item.estimate_tokens(tokenizer))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(PromptTokenCounts {
+            total: items.iter().sum(),
+            items,
+        })
+    }
+
+    /// Removes tokens from the text and token id items until the prompt fits into `max_tokens`
+    /// tokens. Image items are always preserved, the `strategy` determines which part of the
+    /// remaining content is cut. Controls referring to removed content are dropped, the ones
+    /// following a cut are shifted accordingly. Items which become empty are removed.
+    ///
+    /// Fails with [`ApiError::ContextOverflow`] if the images alone exceed `max_tokens`.
+    pub fn truncate_to(
+        &mut self,
+        max_tokens: usize,
+        tokenizer: &Tokenizer,
+        strategy: TruncationStrategy,
+    ) -> Result<(), ApiError> {
+        // Text is cut at token boundaries, but re-tokenizing the cut text may yield a slightly
+        // different number of tokens, so repeat until the prompt fits.
+        let mut previous_total = usize::MAX;
+        loop {
+            let mut encodings = Vec::with_capacity(self.len());
+            let mut truncatable = 0;
+            let mut fixed = 0;
+            for item in &self.0 {
+                let encoding = match item {
+                    Modality::Text { data, .. } => Some(tokenizer.encode(data.as_str(), false)?),
+                    _ => None,
+                };
+                match item {
+                    Modality::Text { .. } => truncatable += encoding.as_ref().unwrap().len(),
+                    Modality::TokenIds { data, .. } => truncatable += data.len(),
+                    Modality::Image { .. } => fixed += IMAGE_TOKEN_COUNT,
+                }
+                encodings.push(encoding);
+            }
+
+            let total = truncatable + fixed;
+            if total <= max_tokens {
+                return Ok(());
+            }
+            if fixed > max_tokens || total >= previous_total {
+                return Err(ApiError::ContextOverflow {
+                    required: fixed.max(total.min(previous_total)),
+                    available: max_tokens,
+                });
+            }
+            previous_total = total;
+
+            let excess = total - max_tokens;
+            let cut_start = match strategy {
+                TruncationStrategy::Head => 0,
+                TruncationStrategy::Tail => truncatable - excess,
+                TruncationStrategy::Middle => (truncatable - excess) / 2,
+            };
+            let cut = cut_start..cut_start + excess;
+
+            let mut offset = 0;
+            for (item, encoding) in self.0.iter_mut().zip(&encodings) {
+                let len = match item {
+                    Modality::Text { .. } => encoding.as_ref().unwrap().len(),
+                    Modality::TokenIds { data, .. } => data.len(),
+                    Modality::Image { .. } => continue,
+                };
+                let start = cut.start.clamp(offset, offset + len) - offset;
+                let end = cut.end.clamp(offset, offset + len) - offset;
+                offset += len;
+                if start < end {
+                    item.remove_tokens(start..end, encoding.as_ref());
+                }
+            }
+            self.0.retain(|item| match item {
+                Modality::Text { data, .. } => !data.is_empty(),
+                Modality::TokenIds { data, .. } => !data.is_empty(),
+                Modality::Image { .. } => true,
+            });
+        }
+    }
+
+    /// Store the prompt including images and controls as JSON file.
+    pub fn to_json_file(&self, path: impl AsRef<Path>) -> Result<(), ApiError> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    /// Load a prompt previously stored with [`Prompt::to_json_file`].
+    pub fn from_json_file(path: impl AsRef<Path>) -> Result<Self, ApiError> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+impl From<&[u32]> for Prompt {
+    fn from(ids: &[u32]) -> Self {
+        Self::from_token_ids(ids.to_vec(), None)
+    }
+}
+
+impl From<&Encoding> for Prompt {
+    fn from(encoding: &Encoding) -> Self {
+        Self::from_encoding(encoding)
+    }
+}
+
+impl Index<usize> for Prompt {
+    type Output = Modality;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.0[index]
+    }
+}
+
+impl Extend<Modality> for Prompt {
+    fn extend<T: IntoIterator<Item = Modality>>(&mut self, iter: T) {
+        self.0.extend(iter)
+    }
+}
+
+impl FromIterator<Modality> for Prompt {
+    fn from_iter<T: IntoIterator<Item = Modality>>(iter: T) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for Prompt {
+    type Item = Modality;
+    type IntoIter = std::vec::IntoIter<Modality>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
 }
 
-#[derive(Serialize, Debug, Clone, PartialEq)]
+impl<'a> IntoIterator for &'a Prompt {
+    type Item = &'a Modality;
+    type IntoIter = std::slice::Iter<'a, Modality>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct TokenControl {
     /// Index of the token, relative to the list of tokens IDs in the current prompt item.
     pub index: u32,
@@ -50,20 +341,43 @@ pub struct TokenControl {
     pub factor: f64,
 }
 
-#[derive(Serialize, Debug, Clone, PartialEq)]
+impl TokenControl {
+    pub fn new(index: u32, factor: f64) -> Self {
+        Self { index, factor }
+    }
+
+    /// One control for each token index in `range`, all using the same factor.
+    pub fn for_range(range: Range<u32>, factor: f64) -> impl Iterator<Item = TokenControl> {
+        range.map(move |index| TokenControl::new(index, factor))
+    }
+}
+
+/// What to do if a control partially overlaps with a text or image token.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlTokenOverlap {
+    /// The factor is adjusted proportionally with the amount of the token the control overlaps.
+    #[default]
+    Partial,
+
+    /// The full factor is applied as long as the control overlaps with the token at all.
+    Complete,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct TextControl {
     /// Starting character index to apply the factor to.
-    start: i32,
+    pub start: i32,
 
     /// The amount of characters to apply the factor to.
-    length: i32,
+    pub length: i32,
 
     /// Factor to apply to the given token in the attention matrix.
     ///
     /// - 0 <= factor < 1 => Suppress the given token
     /// - factor == 1 => identity operation, no change to attention
     /// - factor > 1 => Amplify the given token
-    factor: f64,
+    pub factor: f64,
 
     /// What to do if a control partially overlaps with a text token.
     ///
@@ -75,7 +389,60 @@ pub struct TextControl {
     /// If set to "complete", the full factor will be applied as long as the control
     /// overlaps with the token at all.
     #[serde(skip_serializing_if = "Option::is_none")]
-    token_overlap: Option<String>,
+    pub token_overlap: Option<ControlTokenOverlap>,
+}
+
+impl TextControl {
+    /// Applies `factor` to the `length` characters starting at character `start` of the text.
+    pub fn new(start: i32, length: i32, factor: f64) -> Self {
+        Self {
+            start,
+            length,
+            factor,
+            token_overlap: None,
+        }
+    }
+}
+
+impl_builder_methods!(TextControl, token_overlap: ControlTokenOverlap);
+
+/// Square area of an image in pixels which the model gets to see, see
+/// [`Modality::from_image_with_crop`].
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Crop {
+    /// x-coordinate of the top left corner.
+    pub x: u32,
+
+    /// y-coordinate of the top left corner.
+    pub y: u32,
+
+    /// Edge length of the square.
+    pub size: u32,
+}
+
+impl Crop {
+    pub fn new(x: u32, y: u32, size: u32) -> Self {
+        Self { x, y, size }
+    }
+
+    /// Checks that the crop is not empty and lies within an image of `width`x`height` pixels.
+    pub fn validate(&self, width: u32, height: u32) -> Result<(), LoadImageError> {
+        let fits = |offset: u32, extent: u32| {
+            offset
+                .checked_add(self.size)
+                .is_some_and(|end| end <= extent)
+        };
+        if self.size == 0 || !fits(self.x, width) || !fits(self.y, height) {
+            return Err(LoadImageError::InvalidCrop {
+                x: self.x,
+                y: self.y,
+                size: self.size,
+                width,
+                height,
+            });
+        }
+        Ok(())
+    }
 }
 
 /// Bounding box in logical coordinates. From 0 to 1. With (0,0) being the upper left corner,
@@ -84,26 +451,39 @@ pub struct TextControl {
 /// Keep in mind, non-square images are center-cropped by default before going to the model.
 /// (You can specify a custom cropping if you want.). Since control coordinates are relative to
 /// the entire image, all or a portion of your control may be outside the "model visible area".
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
 pub struct BoundingBox {
     /// x-coordinate of top left corner of the control bounding box.
     /// Must be a value between 0 and 1, where 0 is the left corner and 1 is the right corner.
-    left: f64,
+    pub left: f64,
 
     /// y-coordinate of top left corner of the control bounding box
     /// Must be a value between 0 and 1, where 0 is the top pixel row and 1 is the bottom row.
-    top: f64,
+    pub top: f64,
 
     /// width of the control bounding box
     /// Must be a value between 0 and 1, where 1 means the full width of the image.
-    width: f64,
+    pub width: f64,
 
     /// height of the control bounding box
     /// Must be a value between 0 and 1, where 1 means the full height of the image.
-    heigh: f64,
+    #[serde(alias = "heigh")]
+    pub height: f64,
+}
+
+impl BoundingBox {
+    /// Box with its top left corner at (`left`, `top`), all values relative to the image size.
+    pub fn new(left: f64, top: f64, width: f64, height: f64) -> Self {
+        Self {
+            left,
+            top,
+            width,
+            height,
+        }
+    }
 }
 
-#[derive(Serialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct ImageControl {
     /// Bounding box in logical coordinates. From 0 to 1. With (0,0) being the upper left corner,
     /// and relative to the entire image.
@@ -111,14 +491,14 @@ pub struct ImageControl {
     /// Keep in mind, non-square images are center-cropped by default before going to the model. (You
     /// can specify a custom cropping if you want.). Since control coordinates are relative to the
     /// entire image, all or a portion of your control may be outside the "model visible area".
-    rect: BoundingBox,
+    pub rect: BoundingBox,
 
     /// Factor to apply to the given token in the attention matrix.
     ///
     /// - 0 <= factor < 1 => Suppress the given token
     /// - factor == 1 => identity operation, no change to attention
     /// - factor > 1 => Amplify the given token
-    factor: f64,
+    pub factor: f64,
 
     /// What to do if a control partially overlaps with a text token.
     ///
@@ -130,12 +510,64 @@ pub struct ImageControl {
     /// If set to "complete", the full factor will be applied as long as the control
     /// overlaps with the token at all.
     #[serde(skip_serializing_if = "Option::is_none")]
-    token_overlap: Option<String>,
+    pub token_overlap: Option<ControlTokenOverlap>,
+}
+
+impl ImageControl {
+    /// Applies `factor` to the area of the image covered by `rect`.
+    pub fn new(rect: BoundingBox, factor: f64) -> Self {
+        Self {
+            rect,
+            factor,
+            token_overlap: None,
+        }
+    }
+}
+
+impl_builder_methods!(ImageControl, token_overlap: ControlTokenOverlap);
+
+/// Encoded image of an image prompt item. It is kept as raw bytes, which are cheap to clone and
+/// only base64 encoded while the request is serialized, straight into the request body.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageData(pub Bytes);
+
+impl ImageData {
+    /// Decodes base64, as e.g. found in requests logged as JSON.
+    pub fn from_base64(encoded: &str) -> Result<Self, base64::DecodeError> {
+        Ok(Self(BASE64_STANDARD.decode(encoded)?.into()))
+    }
+
+    pub fn to_base64(&self) -> String {
+        BASE64_STANDARD.encode(&self.0)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<T: Into<Bytes>> From<T> for ImageData {
+    fn from(bytes: T) -> Self {
+        Self(bytes.into())
+    }
+}
+
+impl Serialize for ImageData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&Base64Display::new(&self.0, &BASE64_STANDARD))
+    }
+}
+
+impl<'de> Deserialize<'de> for ImageData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        Self::from_base64(&encoded).map_err(de::Error::custom)
+    }
 }
 
 /// The prompt for models can be a combination of different modalities (Text and Image). The type of
 /// modalities which are supported depend on the Model in question.
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Modality {
     /// The only type of prompt which can be used with pure language models
@@ -147,7 +579,7 @@ pub enum Modality {
     },
     /// An image input into the model. See [`Modality::from_image_path`].
     Image {
-        /// An image send as part of a prompt to a model. The image is represented as base64.
+        /// An image send as part of a prompt to a model. The image is sent as base64.
         ///
         /// Note: The models operate on square images. All non-square images are center-cropped
         /// before going to the model, so portions of the image may not be visible.
@@ -155,7 +587,7 @@ pub enum Modality {
         /// You can supply specific cropping parameters if you like, to choose a different area
         /// of the image than a center-crop. Or, you can always transform the image yourself to
         /// a square before sending it.
-        data: String,
+        data: ImageData,
 
         /// x-coordinate of top left corner of cropping box in pixels
         #[serde(skip_serializing_if = "Option::is_none")]
@@ -181,7 +613,146 @@ pub enum Modality {
     },
 }
 
+/// Writer which only counts the bytes written to it.
+struct ByteCounter(usize);
+
+impl io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Number of tokens each image prompt item is converted into.
+pub const IMAGE_TOKEN_COUNT: usize = 144;
+
+/// Checks that `prompt` plus up to `maximum_tokens` generated tokens fit into a context of
+/// `context_length` tokens. On overflow the error tells by how many tokens prompt or completion
+/// have to shrink.
+pub fn fits_context(
+    context_length: usize,
+    prompt: &Prompt,
+    maximum_tokens: u32,
+    tokenizer: &Tokenizer,
+) -> Result<(), BudgetError> {
+    let prompt_tokens = prompt.estimate_tokens(tokenizer)?;
+    let required = prompt_tokens + maximum_tokens as usize;
+    if required > context_length {
+        return Err(BudgetError::Overflow {
+            prompt_tokens,
+            maximum_tokens,
+            context_length,
+            overflow: required - context_length,
+        });
+    }
+    Ok(())
+}
+
+/// Token counts of a prompt, see [`Prompt::tokenize`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromptTokenCounts {
+    /// Number of tokens of each item, in prompt order.
+    pub items: Vec<usize>,
+
+    /// Number of tokens of the whole prompt.
+    pub total: usize,
+}
+
+/// Which part of a prompt is removed by [`Prompt::truncate_to`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TruncationStrategy {
+    /// Remove tokens from the beginning, keeping the end of the prompt.
+    #[default]
+    Head,
+    /// Remove tokens from the end, keeping the beginning of the prompt.
+    Tail,
+    /// Remove tokens from the middle, keeping beginning and end of the prompt (e.g. an
+    /// instruction and a question surrounding a long document).
+    Middle,
+}
+
 impl Modality {
+    /// Removes the tokens in `range` from a text or token ids item. For text items `encoding`
+    /// must be the tokenized text.
+    fn remove_tokens(&mut self, range: Range<usize>, encoding: Option<&Encoding>) {
+        match self {
+            Modality::Text { data, controls } => {
+                let offsets = encoding.unwrap().get_offsets();
+                // Remove everything after the last kept token before the cut up to the end of
+                // the last removed token, so whitespace in front of kept tokens is preserved.
+                let mut cut_start = match range.start {
+                    0 => 0,
+                    start => offsets[start - 1].1,
+                };
+                let mut cut_end = if range.end == offsets.len() {
+                    data.len()
+                } else {
+                    offsets[range.end - 1].1
+                };
+                while !data.is_char_boundary(cut_start) {
+                    cut_start -= 1;
+                }
+                while !data.is_char_boundary(cut_end) {
+                    cut_end += 1;
+                }
+
+                let kept_chars = data[..cut_start].chars().count() as i32;
+                let removed_chars = data[cut_start..cut_end].chars().count() as i32;
+                if let Some(controls) = controls {
+                    controls.retain_mut(|control| {
+                        if control.start + control.length <= kept_chars {
+                            true
+                        } else if control.start >= kept_chars + removed_chars {
+                            control.start -= removed_chars;
+                            true
+                        } else {
+                            false
+                        }
+                    });
+                }
+                data.replace_range(cut_start..cut_end, "");
+            }
+            Modality::TokenIds { data, controls } => {
+                let removed = (range.end - range.start) as u32;
+                if let Some(controls) = controls {
+                    controls.retain_mut(|control| {
+                        let index = control.index as usize;
+                        if index < range.start {
+                            true
+                        } else if index >= range.end {
+                            control.index -= removed;
+                            true
+                        } else {
+                            false
+                        }
+                    });
+                }
+                data.drain(range);
+            }
+            Modality::Image { .. } => {}
+        }
+    }
+
+    /// Size of this item in the JSON request body, see [`Prompt::payload_sizes`].
+    pub fn payload_size(&self) -> usize {
+        let mut counter = ByteCounter(0);
+        serde_json::to_writer(&mut counter, self).unwrap();
+        counter.0
+    }
+
+    /// Estimated number of tokens of this item, see [`Prompt::estimate_tokens`].
+    pub fn estimate_tokens(&self, tokenizer: &Tokenizer) -> Result<usize, ApiError> {
+        Ok(match self {
+            Modality::Text { data, .. } => tokenizer.encode(data.as_str(), false)?.len(),
+            Modality::Image { .. } => IMAGE_TOKEN_COUNT,
+            Modality::TokenIds { data, .. } => data.len(),
+        })
+    }
+
     /// Instantiates a text prompt
     pub fn from_text(text: impl Into<String>, controls: Option<Vec<TextControl>>) -> Self {
         Modality::Text {
@@ -200,7 +771,7 @@ impl Modality {
 
     pub fn from_image_path(path: impl AsRef<Path>) -> Result<Self, LoadImageError> {
         let bytes = from_image_path(path.as_ref())?;
-        Ok(Self::from_image_bytes(&bytes))
+        Ok(Self::from_encoded_image(bytes))
     }
 
     /// Generates an image input from the binary representation of the image.
@@ -209,9 +780,9 @@ impl Modality {
     /// guaranteed to be supported, and all others formats are converted into it. Furthermore, the
     /// model can only look at square shaped pictures. If the picture is not square shaped it will
     /// be center cropped.
-    fn from_image_bytes(image: &[u8]) -> Self {
+    fn from_encoded_image(image: impl Into<ImageData>) -> Self {
         Modality::Image {
-            data: BASE64_STANDARD.encode(image).into(),
+            data: image.into(),
             x: None,
             y: None,
             size: None,
@@ -225,8 +796,152 @@ impl Modality {
     /// method instead of [`Self::from_image_path`] in case you have the image in memory already
     /// and do not want to load it from a file again.
     pub fn from_image(image: &image::DynamicImage) -> Result<Self, LoadImageError> {
-        let bytes = preprocess_image(image);
-        Ok(Self::from_image_bytes(&bytes))
+        let bytes = preprocess_image(image)?;
+        Ok(Self::from_encoded_image(bytes))
+    }
+
+    /// Image input for model showing the square `crop` of `image` instead of its center. The crop
+    /// is applied on the client side, before the image is scaled down for upload.
+    pub fn from_image_with_crop(
+        image: &image::DynamicImage,
+        crop: Crop,
+    ) -> Result<Self, LoadImageError> {
+        let bytes = preprocess_image_with_crop(image, &crop)?;
+        Ok(Self::from_encoded_image(bytes))
+    }
+
+    /// Image input for model, squared and encoded according to `options`, e.g. letterboxed
+    /// instead of center-cropped.
+    pub fn from_image_with_options(
+        image: &image::DynamicImage,
+        options: &ImageOptions,
+    ) -> Result<Self, LoadImageError> {
+        let bytes = preprocess_image_with_options(image, options)?;
+        Ok(Self::from_encoded_image(bytes))
+    }
+
+    /// Like [`Self::from_image_with_options`], but the image is loaded from `path`.
+    pub fn from_image_path_with_options(
+        path: impl AsRef<Path>,
+        options: &ImageOptions,
+    ) -> Result<Self, LoadImageError> {
+        Self::from_image_with_options(&load_image(path.as_ref())?, options)
+    }
+
+    /// Image input from an encoded image of any supported format, e.g. a user upload. The format
+    /// is sniffed from the content. Square PNGs within [`ImageOptions::max_edge`] are sent as
+    /// they are, everything else is decoded and preprocessed according to `options`. Fails with
+    /// [`LoadImageError::TooLarge`] for inputs exceeding [`ImageOptions::max_bytes`].
+    pub fn from_image_bytes(bytes: &[u8], options: &ImageOptions) -> Result<Self, LoadImageError> {
+        let bytes = preprocess_image_bytes(bytes, options)?;
+        Ok(Self::from_encoded_image(bytes.into_owned()))
+    }
+
+    /// Image input from a data URL like `data:image/png;base64,...`, as handed over by browsers.
+    /// The decoded image is treated like [`Self::from_image_bytes`].
+    pub fn from_data_url(url: &str, options: &ImageOptions) -> Result<Self, LoadImageError> {
+        let encoded = url
+            .strip_prefix("data:")
+            .and_then(|url| url.split_once(','))
+            .and_then(|(media_type, data)| media_type.ends_with(";base64").then_some(data))
+            .ok_or(LoadImageError::InvalidDataUrl)?;
+        let bytes = BASE64_STANDARD
+            .decode(encoded.trim())
+            .map_err(|_| LoadImageError::InvalidDataUrl)?;
+        Self::from_image_bytes(&bytes, options)
+    }
+
+    /// The image of an image item as data URL, e.g. to display it in a browser. `None` for other
+    /// items or if the image data is not of a known format.
+    pub fn to_data_url(&self) -> Option<String> {
+        let Modality::Image { data, .. } = self else {
+            return None;
+        };
+        let format = image::guess_format(data.as_bytes()).ok()?;
+        let mime_type = format.to_mime_type();
+        Some(format!("data:{mime_type};base64,{}", data.to_base64()))
+    }
+
+    /// The square image the model sees for an image item: the uploaded image, cropped as given by
+    /// `x`, `y` and `size` or else center-cropped, and scaled to the model's resolution. Use it to
+    /// check the crop before sending a prompt. `None` for other items, undecodable images or crops
+    /// not within the image.
+    pub fn model_view(&self) -> Option<image::DynamicImage> {
+        let Modality::Image {
+            data, x, y, size, ..
+        } = self
+        else {
+            return None;
+        };
+        let image = decode_image(data.as_bytes()).ok()?;
+        let square = match (x, y, size) {
+            (Some(x), Some(y), Some(size)) => {
+                let crop = Crop::new(
+                    u32::try_from(*x).ok()?,
+                    u32::try_from(*y).ok()?,
+                    u32::try_from(*size).ok()?,
+                );
+                crop.validate(image.width(), image.height()).ok()?;
+                image.crop_imm(crop.x, crop.y, crop.size, crop.size)
+            }
+            _ => center_cropped(&image),
+        };
+        Some(square.resize_exact(DESIRED_IMAGE_SIZE, DESIRED_IMAGE_SIZE, CatmullRom))
+    }
+
+    /// [`Self::model_view`] encoded as PNG.
+    pub fn model_view_png(&self) -> Option<Result<Vec<u8>, image::ImageError>> {
+        self.model_view().map(|image| encode_png(&image))
+    }
+
+    /// Downloads the image at `url` and preprocesses it according to `options`. Downloads use a
+    /// connection pool of `client` separate from the one of API requests, so its API token is
+    /// never sent. Fails if the server does not declare an `image/*` content type or the image
+    /// exceeds [`ImageOptions::max_bytes`].
+    pub async fn from_image_url(
+        client: &Client,
+        url: &str,
+        options: &ImageOptions,
+    ) -> Result<Self, FetchImageError> {
+        let response = client
+            .anonymous_http_client
+            .get(url)
+            .send()
+            .await
+            .map_err(ApiError::from)?;
+        let mut response = translate_http_error(response).await?;
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .ok_or(FetchImageError::MissingContentType)?
+            .to_str()
+            .unwrap_or_default();
+        if !content_type.starts_with("image/") {
+            return Err(FetchImageError::NotAnImage(content_type.to_owned()));
+        }
+        let limit = options.max_bytes;
+        if response
+            .content_length()
+            .is_some_and(|length| length > limit as u64)
+        {
+            return Err(FetchImageError::TooLarge { limit });
+        }
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(ApiError::from)? {
+            if bytes.len() + chunk.len() > limit {
+                return Err(FetchImageError::TooLarge { limit });
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Ok(Self::from_image_bytes(&bytes, options)?)
+    }
+
+    /// Like [`Self::from_image_with_crop`], but the image is loaded from `path`.
+    pub fn from_image_path_with_crop(
+        path: impl AsRef<Path>,
+        crop: Crop,
+    ) -> Result<Self, LoadImageError> {
+        Self::from_image_with_crop(&load_image(path.as_ref())?, crop)
     }
 }
 
@@ -239,12 +954,75 @@ impl Modality {
 ///
 /// Setting it to "aleph-alpha" allows us to only process the request in our own datacenters. Choose this
 /// option for maximal data privacy.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub enum Hosting {
     #[serde(rename = "aleph-alpha")]
     AlephAlpha,
+
+    /// Any other hosting identifier, e.g. of a self-hosted or partner deployment. Serialized as
+    /// the plain string.
+    #[serde(untagged)]
+    Custom(String),
+}
+
+/// Log probabilities returned with a completion, see [`CompletionRequest::log_probs`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LogProbsSetting {
+    /// No log probabilities are returned.
+    #[default]
+    None,
+
+    /// Log probabilities of the generated tokens are returned.
+    Generated,
+
+    /// Log probabilities of the generated tokens and of the top n most likely tokens are returned.
+    /// n must be at least 1, as the API would take `Top(0)` for [`LogProbsSetting::Generated`];
+    /// [`CompletionRequest::validate`] rejects it.
+    Top(u32),
 }
 
+impl LogProbsSetting {
+    pub fn is_none(&self) -> bool {
+        matches!(self, LogProbsSetting::None)
+    }
+}
+
+impl Serialize for LogProbsSetting {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            LogProbsSetting::None => serializer.serialize_none(),
+            LogProbsSetting::Generated => serializer.serialize_u32(0),
+            LogProbsSetting::Top(n) => serializer.serialize_u32(*n),
+        }
+    }
+}
+
+/// Attention control settings shared by all requests whose prompt may contain controls.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ControlParameters {
+    /// If set to `None`, attention control parameters only apply to those tokens that have
+    /// explicitly been set in the request.
+    /// If set to a non-null value, we apply the control parameters to similar tokens as well.
+    /// Controls that have been applied to one token will then be applied to all other tokens
+    /// that have at least the similarity score defined by this parameter.
+    /// The similarity score is the cosine similarity of token embeddings.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contextual_control_threshold: Option<f64>,
+
+    /// Default value: true
+    /// `true`: apply controls on prompt items by adding the `log(control_factor)` to attention scores.
+    /// `false`: apply controls on prompt items by
+    /// `(attention_scores - -attention_scores.min(-1)) * control_factor`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub control_log_additive: Option<bool>,
+}
+
+impl_builder_methods!(
+    ControlParameters,
+    contextual_control_threshold: f64,
+    control_log_additive: bool
+);
+
 #[derive(Serialize, Debug, Default)]
 pub struct CompletionRequest {
     /// The name of the model from the Luminous model family, e.g. `luminous-base"`.
@@ -399,12 +1177,10 @@ pub struct CompletionRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub n: Option<u32>,
 
-    /// Number of top log probabilities for each token generated. Log probabilities can be used in downstream
-    /// tasks or to assess the model's certainty when producing tokens. No log probabilities are returned if
-    /// set to None. Log probabilities of generated tokens are returned if set to 0. Log probabilities of
-    /// generated tokens and top n log probabilities are returned if set to n.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub log_probs: Option<i32>,
+    /// Which log probabilities are returned for each token generated. Log probabilities can be used in
+    /// downstream tasks or to assess the model's certainty when producing tokens.
+    #[serde(skip_serializing_if = "LogProbsSetting::is_none")]
+    pub log_probs: LogProbsSetting,
 
     /// List of strings that will stop generation if they're generated. Stop sequences may be helpful in
     /// structured texts.
@@ -456,20 +1232,9 @@ pub struct CompletionRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub completion_bias_exclusion_first_token_only: Option<bool>,
 
-    /// If set to `null`, attention control parameters only apply to those tokens that have
-    /// explicitly been set in the request.
-    /// If set to a non-null value, we apply the control parameters to similar tokens as well.
-    /// Controls that have been applied to one token will then be applied to all other tokens
-    /// that have at least the similarity score defined by this parameter.
-    /// The similarity score is the cosine similarity of token embeddings.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub contextual_control_threshold: Option<f64>,
-
-    /// `true`: apply controls on prompt items by adding the `log(control_factor)` to attention scores.
-    /// `false`: apply controls on prompt items by
-    /// `(attention_scores - -attention_scores.min(-1)) * control_factor`
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub control_log_additive: Option<bool>,
+    /// Settings for how attention controls of the prompt are applied.
+    #[serde(flatten)]
+    pub control_parameters: ControlParameters,
 
     /// The logit bias allows to influence the likelihood of generating tokens. A dictionary mapping token
     /// ids (int) to a bias (float) can be provided. Such bias is added to the logits as generated by the
@@ -479,17 +1244,132 @@ pub struct CompletionRequest {
 }
 
 impl CompletionRequest {
-    pub fn new(model: String, prompt: Prompt, maximum_tokens: u32) -> Self {
+    /// `model` may be a name or a [`crate::Model`].
+    pub fn new(model: impl Into<String>, prompt: Prompt, maximum_tokens: u32) -> Self {
         Self {
-            model,
+            model: model.into(),
             prompt,
             maximum_tokens,
             ..Self::default()
         }
     }
-    pub fn from_text(model: String, prompt: String, maximum_tokens: u32) -> Self {
+    pub fn from_text(
+        model: impl Into<String>,
+        prompt: impl Into<String>,
+        maximum_tokens: u32,
+    ) -> Self {
         Self::new(model, Prompt::from_text(prompt), maximum_tokens)
     }
+
+    pub fn log_probs(mut self, log_probs: LogProbsSetting) -> Self {
+        self.log_probs = log_probs;
+        self
+    }
+
+    /// Parameters set in the request which the deployment described by `capabilities` does not
+    /// support, or is not known to support (see [`ApiCapabilities::supports`]). The API ignores
+    /// unknown parameters, so these do not fail the request, but change its behavior.
+    pub fn unsupported_parameters(
+        &self,
+        capabilities: &ApiCapabilities,
+    ) -> Vec<UnsupportedParameter> {
+        let sequence_penalty = self.sequence_penalty.is_some()
+            || self.sequence_penalty_min_length.is_some()
+            || self.use_multiplicative_sequence_penalty.is_some();
+        [
+            (
+                "sequence_penalty",
+                sequence_penalty,
+                Feature::SequencePenalty,
+            ),
+            (
+                "raw_completion",
+                self.raw_completion.is_some(),
+                Feature::RawCompletion,
+            ),
+            (
+                "control_log_additive",
+                self.control_parameters.control_log_additive.is_some(),
+                Feature::ControlLogAdditive,
+            ),
+            ("logit_bias", self.logit_bias.is_some(), Feature::LogitBias),
+        ]
+        .into_iter()
+        .filter_map(|(parameter, is_set, feature)| capabilities.check(parameter, is_set, feature))
+        .collect()
+    }
+
+    /// Checks the request against known constraints of the API, so invalid requests can be
+    /// rejected without a round-trip. Passing the validation does not guarantee that the API
+    /// accepts the request.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let empty = self.prompt.iter().all(|item| match item {
+            Modality::Text { data, .. } => data.is_empty(),
+            Modality::TokenIds { data, .. } => data.is_empty(),
+            Modality::Image { .. } => false,
+        });
+        if empty {
+            return Err(ValidationError::EmptyPrompt);
+        }
+
+        check_range("temperature", self.temperature, 0.0, f64::INFINITY)?;
+        check_range("top_p", self.top_p, 0.0, 1.0)?;
+        if let LogProbsSetting::Top(n) = self.log_probs {
+            check_range("log_probs", Some(n.into()), 1.0, u32::MAX.into())?;
+        }
+
+        if let Some(minimum) = self.minimum_tokens {
+            if minimum > self.maximum_tokens {
+                return Err(ValidationError::MinimumExceedsMaximum {
+                    minimum,
+                    maximum: self.maximum_tokens,
+                });
+            }
+        }
+
+        if let Some(best_of) = self.best_of {
+            let n = self.n.unwrap_or(1);
+            if best_of <= n {
+                return Err(ValidationError::BestOfNotGreaterThanN { best_of, n });
+            }
+        }
+
+        if let (Some(inclusions), Some(exclusions)) = (
+            &self.completion_bias_inclusion,
+            &self.completion_bias_exclusion,
+        ) {
+            for inclusion in inclusions {
+                for exclusion in exclusions {
+                    if inclusion.starts_with(exclusion.as_str())
+                        || exclusion.starts_with(inclusion.as_str())
+                    {
+                        return Err(ValidationError::BiasPrefixConflict {
+                            inclusion: inclusion.clone(),
+                            exclusion: exclusion.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_range(
+    parameter: &'static str,
+    value: Option<f64>,
+    min: f64,
+    max: f64,
+) -> Result<(), ValidationError> {
+    match value {
+        Some(value) if !(min..=max).contains(&value) => Err(ValidationError::OutOfRange {
+            parameter,
+            value,
+            min,
+            max,
+        }),
+        _ => Ok(()),
+    }
 }
 
 impl_builder_methods!(
@@ -513,7 +1393,6 @@ impl_builder_methods!(
     penalty_exceptions_include_stop_sequences: bool,
     best_of: u32,
     n: u32,
-    log_probs: i32,
     stop_sequences: Vec<String>,
     tokens: bool,
     raw_completion: bool,
@@ -522,17 +1401,25 @@ impl_builder_methods!(
     completion_bias_inclusion_first_token_only: bool,
     completion_bias_exclusion: Vec<String>,
     completion_bias_exclusion_first_token_only: bool,
-    contextual_control_threshold: f64,
-    control_log_additive: bool,
     logit_bias: HashMap<i32, f32>
 );
 
-#[derive(Deserialize, Debug)]
+impl_control_parameters!(CompletionRequest);
+
+#[derive(Deserialize, Debug, Clone)]
 pub struct CompletionResponse {
     /// model name and version (if any) of the used model for inference
     pub model_version: String,
     /// list of completions; may contain only one entry if no more are requested (see parameter n)
     pub completions: Vec<CompletionOutput>,
+    /// Number of tokens combined across all completion tasks. In particular, if you set best_of
+    /// or n to a number larger than 1 then we report the combined prompt token count for all
+    /// best_of or n tasks.
+    pub num_tokens_prompt_total: Option<u32>,
+    /// Number of tokens combined across all completion tasks. If multiple completions are
+    /// returned or best_of is set to a value greater than 1 then this value contains the
+    /// combined generated token count.
+    pub num_tokens_generated: Option<u32>,
 }
 
 impl CompletionResponse {
@@ -547,10 +1434,127 @@ impl CompletionResponse {
     pub fn best_text(&self) -> &str {
         &self.best().completion
     }
+
+    /// Splits the tokens of all completions of a request with `echo` and `tokens` enabled into
+    /// the echoed prompt and the generated part, see [`CompletionOutput::split_echo`].
+    ///
+    /// The prompt length is derived from `num_tokens_prompt_total`, which covers all `best_of`
+    /// respectively `n` tasks of `request`. Returns `None` if it or the completion tokens are
+    /// missing.
+    pub fn split_echo(&self, request: &CompletionRequest) -> Option<Vec<EchoSplit>> {
+        let tasks = request.best_of.or(request.n).unwrap_or(1).max(1);
+        let prompt_tokens = (self.num_tokens_prompt_total? / tasks) as usize;
+        self.completions
+            .iter()
+            .map(|output| output.split_echo(prompt_tokens))
+            .collect()
+    }
+
+    /// Texts of all completions.
+    pub fn texts(&self) -> impl Iterator<Item = &str> {
+        self.completions
+            .iter()
+            .map(|output| output.completion.as_str())
+    }
+
+    /// Finish reason of each completion, e.g. `maximum_tokens` or `end_of_text`.
+    pub fn finish_reasons(&self) -> impl Iterator<Item = &str> {
+        self.completions
+            .iter()
+            .map(|output| output.finish_reason.as_str())
+    }
+
+    /// Number of tokens generated for all completions, see [`Self::num_tokens_generated`]. Unlike
+    /// the `completion_tokens` of the completions, this excludes prompt tokens returned with
+    /// `echo`. Returns `None` if the API did not report it.
+    pub fn total_generated_tokens(&self) -> Option<usize> {
+        self.num_tokens_generated.map(|tokens| tokens as usize)
+    }
 }
 
-#[derive(Deserialize, Debug)]
+impl IntoIterator for CompletionResponse {
+    type Item = CompletionOutput;
+    type IntoIter = std::vec::IntoIter<CompletionOutput>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.completions.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a CompletionResponse {
+    type Item = &'a CompletionOutput;
+    type IntoIter = std::slice::Iter<'a, CompletionOutput>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.completions.iter()
+    }
+}
+
+/// Log probabilities of the top tokens at one position of the sequence. Values may be missing
+/// for tokens which could not be scored, e.g. the very first token of the prompt.
+pub type LogProbs = HashMap<String, Option<f64>>;
+
+#[derive(Deserialize, Debug, Clone)]
 pub struct CompletionOutput {
     pub completion: String,
     pub finish_reason: String,
+    /// The un-optimized completion, returned if `raw_completion`, `tokens` or `log_probs` was set.
+    pub raw_completion: Option<String>,
+    /// Tokens of the completion, returned if `tokens` was set. With `echo` enabled the prompt
+    /// tokens are included in front of the generated tokens.
+    pub completion_tokens: Option<Vec<String>>,
+    /// One entry per token of `completion_tokens`, returned if `log_probs` was set.
+    pub log_probs: Option<Vec<LogProbs>>,
+}
+
+impl CompletionOutput {
+    /// Tokens together with their log probabilities. Returns `None` if the request did not ask
+    /// for `tokens`.
+    pub fn scored_tokens(&self) -> Option<Vec<ScoredToken>> {
+        let tokens = self.completion_tokens.as_ref()?;
+        Some(
+            tokens
+                .iter()
+                .enumerate()
+                .map(|(index, token)| {
+                    let top_log_probs = self
+                        .log_probs
+                        .as_ref()
+                        .and_then(|log_probs| log_probs.get(index))
+                        .cloned()
+                        .unwrap_or_default();
+                    ScoredToken {
+                        token: token.clone(),
+                        log_prob: top_log_probs.get(token).copied().flatten(),
+                        top_log_probs,
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// Splits the tokens of a completion requested with `echo` into the first `prompt_tokens`
+    /// tokens belonging to the prompt and the generated tokens.
+    pub fn split_echo(&self, prompt_tokens: usize) -> Option<EchoSplit> {
+        let mut prompt = self.scored_tokens()?;
+        let completion = prompt.split_off(prompt_tokens.min(prompt.len()));
+        Some(EchoSplit { prompt, completion })
+    }
+}
+
+/// A token together with its log probability and those of the top alternatives.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoredToken {
+    pub token: String,
+    /// Log probability of this token, if it was scored.
+    pub log_prob: Option<f64>,
+    /// Top log probabilities at this position as requested via `log_probs`.
+    pub top_log_probs: LogProbs,
+}
+
+/// Tokens of an echoed completion separated into prompt and generated part.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EchoSplit {
+    pub prompt: Vec<ScoredToken>,
+    pub completion: Vec<ScoredToken>,
 }
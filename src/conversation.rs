@@ -0,0 +1,198 @@
+use super::client::Client;
+use super::completion::{CompletionRequest, Prompt};
+use super::error::ApiError;
+use super::models::ModelCapabilities;
+use crate::impl_builder_methods;
+use std::fmt::Write;
+use tokenizers::Tokenizer;
+
+const INSTRUCTION_HEADER: &str = "### Instruction:";
+const INPUT_HEADER: &str = "### Input:";
+const RESPONSE_HEADER: &str = "### Response:";
+
+/// Author of a conversation turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turn {
+    pub role: Role,
+    pub text: String,
+}
+
+/// A chat session with one of the instruction tuned (`*-control`) models.
+///
+/// The conversation keeps the system prompt and the history of turns and renders them in the
+/// `### Instruction:` / `### Input:` / `### Response:` format expected by the control models.
+/// Before each completion the oldest turns are dropped until the prompt plus `maximum_tokens`
+/// fits into `context_budget` tokens as counted by the model's tokenizer.
+pub struct Conversation {
+    /// Name of the model used for the completions, e.g. `luminous-base-control`.
+    pub model: String,
+
+    /// Instruction which is placed in front of the conversation history.
+    pub system_prompt: Option<String>,
+
+    /// History of the conversation, oldest turn first.
+    pub turns: Vec<Turn>,
+
+    /// Maximum number of tokens prompt and completion may use together.
+    pub context_budget: u32,
+
+    /// Maximum number of tokens generated for each reply of the assistant.
+    pub maximum_tokens: u32,
+
+    /// Optional completion parameters, passed on to every completion request.
+    pub temperature: Option<f64>,
+    pub top_k: Option<u32>,
+    pub top_p: Option<f64>,
+}
+
+impl Conversation {
+    pub fn new(model: impl Into<String>, context_budget: u32, maximum_tokens: u32) -> Self {
+        Self {
+            model: model.into(),
+            system_prompt: None,
+            turns: Vec::new(),
+            context_budget,
+            maximum_tokens,
+            temperature: None,
+            top_k: None,
+            top_p: None,
+        }
+    }
+
+    /// Conversation using the whole context of `model`, as described by `capabilities` (see
+    /// [`crate::ModelRegistry`]).
+    pub fn for_model(
+        model: impl Into<String>,
+        capabilities: &ModelCapabilities,
+        maximum_tokens: u32,
+    ) -> Self {
+        let context_budget = capabilities.context_length.min(u32::MAX as usize) as u32;
+        Self::new(model, context_budget, maximum_tokens)
+    }
+
+    /// Append a message of the user to the history.
+    pub fn push_user(&mut self, text: impl Into<String>) {
+        self.turns.push(Turn {
+            role: Role::User,
+            text: text.into(),
+        });
+    }
+
+    /// Append a message of the assistant to the history.
+    pub fn push_assistant(&mut self, text: impl Into<String>) {
+        self.turns.push(Turn {
+            role: Role::Assistant,
+            text: text.into(),
+        });
+    }
+
+    /// Removes all turns, the system prompt is kept.
+    pub fn clear(&mut self) {
+        self.turns.clear();
+    }
+
+    /// Renders system prompt and history, ending with an open `### Response:` section for the
+    /// next reply of the assistant.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        if let Some(system_prompt) = &self.system_prompt {
+            write!(text, "{INSTRUCTION_HEADER}\n{system_prompt}\n\n").unwrap();
+        }
+        for turn in &self.turns {
+            let header = match turn.role {
+                Role::User => INPUT_HEADER,
+                Role::Assistant => RESPONSE_HEADER,
+            };
+            write!(text, "{header}\n{}\n\n", turn.text).unwrap();
+        }
+        text.push_str(RESPONSE_HEADER);
+        text
+    }
+
+    pub fn to_prompt(&self) -> Prompt {
+        Prompt::from_text(self.to_text())
+    }
+
+    /// Number of tokens of the rendered prompt.
+    pub fn token_count(&self, tokenizer: &Tokenizer) -> Result<usize, ApiError> {
+        let encoding = tokenizer.encode(self.to_text(), false)?;
+        Ok(encoding.len())
+    }
+
+    /// Drops the oldest turns until the rendered prompt plus `maximum_tokens` fits into the
+    /// context budget. The most recent turn is never removed, and the remaining history always
+    /// starts with a user turn. Returns the number of removed turns.
+    pub fn truncate(&mut self, tokenizer: &Tokenizer) -> Result<usize, ApiError> {
+        let available = self.context_budget.saturating_sub(self.maximum_tokens) as usize;
+        let mut removed = 0;
+        let mut required = self.token_count(tokenizer)?;
+        while required > available && self.turns.len() > 1 {
+            self.turns.remove(0);
+            removed += 1;
+            while self.turns.len() > 1 && self.turns[0].role == Role::Assistant {
+                self.turns.remove(0);
+                removed += 1;
+            }
+            required = self.token_count(tokenizer)?;
+        }
+        if required > available {
+            return Err(ApiError::ContextOverflow {
+                required,
+                available,
+            });
+        }
+        Ok(removed)
+    }
+
+    /// Completion request for the (already truncated) conversation.
+    pub fn to_request(&self) -> CompletionRequest {
+        let mut req =
+            CompletionRequest::new(self.model.clone(), self.to_prompt(), self.maximum_tokens)
+                .stop_sequences(vec![INPUT_HEADER.to_owned(), RESPONSE_HEADER.to_owned()]);
+        req.temperature = self.temperature;
+        req.top_k = self.top_k;
+        req.top_p = self.top_p;
+        req
+    }
+
+    /// Truncates the history to the context budget, requests the next reply of the assistant and
+    /// appends it to the conversation. The model's tokenizer is fetched via the client, which
+    /// downloads it only once per model.
+    pub async fn complete(
+        &mut self,
+        client: &Client,
+        nice: Option<bool>,
+    ) -> Result<&str, ApiError> {
+        let tokenizer = client.get_tokenizer(&self.model).await?;
+        self.truncate(&tokenizer)?;
+
+        let response = client.completion(&self.to_request(), nice).await?;
+        self.push_assistant(response.best_text().trim());
+        Ok(&self.turns.last().unwrap().text)
+    }
+
+    /// Appends the message of the user and returns the reply of the assistant.
+    pub async fn reply(
+        &mut self,
+        client: &Client,
+        message: impl Into<String>,
+        nice: Option<bool>,
+    ) -> Result<&str, ApiError> {
+        self.push_user(message);
+        self.complete(client, nice).await
+    }
+}
+
+impl_builder_methods!(
+    Conversation,
+    system_prompt: String,
+    temperature: f64,
+    top_k: u32,
+    top_p: f64
+);
@@ -0,0 +1,99 @@
+use super::completion::Prompt;
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path};
+use thiserror::Error as ThisError;
+
+/// Default limit for the size of Docx files, see [`Document::from_docx_bytes`].
+pub const MAX_DOCX_BYTES: usize = 10 * 1024 * 1024;
+
+/// A document for the summarization, question answering and search endpoints.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Document {
+    /// A base64 encoded Docx file.
+    Docx(String),
+
+    /// Plain text.
+    Text(String),
+
+    /// A multimodal prompt, e.g. text together with images.
+    Prompt(Prompt),
+}
+
+impl Document {
+    pub fn from_text(text: impl Into<String>) -> Self {
+        Document::Text(text.into())
+    }
+
+    pub fn from_prompt(prompt: Prompt) -> Self {
+        Document::Prompt(prompt)
+    }
+
+    /// Document from the content of a Docx file. Fails if it is larger than `max_bytes` (e.g.
+    /// [`MAX_DOCX_BYTES`]) or is not a Docx file at all.
+    pub fn from_docx_bytes(bytes: &[u8], max_bytes: usize) -> Result<Self, LoadDocumentError> {
+        if bytes.len() > max_bytes {
+            return Err(LoadDocumentError::TooLarge {
+                size: bytes.len(),
+                limit: max_bytes,
+            });
+        }
+        // Docx files are zip archives.
+        if !bytes.starts_with(b"PK\x03\x04") {
+            return Err(LoadDocumentError::NotDocx);
+        }
+        Ok(Document::Docx(BASE64_STANDARD.encode(bytes)))
+    }
+
+    /// Like [`Document::from_docx_bytes`] for the file at `path`, limited to [`MAX_DOCX_BYTES`].
+    pub fn from_docx_path(path: impl AsRef<Path>) -> Result<Self, LoadDocumentError> {
+        let size = fs::metadata(path.as_ref())
+            .map_err(LoadDocumentError::Io)?
+            .len();
+        if size > MAX_DOCX_BYTES as u64 {
+            return Err(LoadDocumentError::TooLarge {
+                size: size as usize,
+                limit: MAX_DOCX_BYTES,
+            });
+        }
+        let bytes = fs::read(path).map_err(LoadDocumentError::Io)?;
+        Self::from_docx_bytes(&bytes, MAX_DOCX_BYTES)
+    }
+
+    /// The text of a plain text document.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Document::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+impl From<String> for Document {
+    fn from(text: String) -> Self {
+        Document::Text(text)
+    }
+}
+
+impl From<&str> for Document {
+    fn from(text: &str) -> Self {
+        Document::Text(text.to_owned())
+    }
+}
+
+impl From<Prompt> for Document {
+    fn from(prompt: Prompt) -> Self {
+        Document::Prompt(prompt)
+    }
+}
+
+#[derive(ThisError, Debug)]
+pub enum LoadDocumentError {
+    #[error("Error reading the document file.")]
+    Io(#[source] io::Error),
+    #[error("The document has {size} bytes, exceeding the limit of {limit} bytes.")]
+    TooLarge { size: usize, limit: usize },
+    #[error("The document is not a Docx file.")]
+    NotDocx,
+}
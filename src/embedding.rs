@@ -1,7 +1,108 @@
-use super::completion::{Hosting, Prompt};
-use crate::impl_builder_methods;
+use super::batch::RetryPolicy;
+use super::completion::{ControlParameters, Hosting, Prompt};
+use super::error::{ApiError, ValidationError};
+use crate::{impl_builder_methods, impl_control_parameters};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::Range};
+use thiserror::Error as ThisError;
+
+/// Pooling operation aggregating the token embeddings of a layer across the sequence dimension.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Pooling {
+    /// Average of the token embeddings.
+    Mean,
+
+    /// Position weighted mean with latter tokens having a higher weight.
+    WeightedMean,
+
+    /// Maximum of the token embeddings.
+    Max,
+
+    /// Embedding of the last token.
+    LastToken,
+
+    /// Maximum of the absolute values of the token embeddings.
+    AbsMax,
+}
+
+/// Layer of the model from which embeddings are returned. Serialized as the layer index used by
+/// the API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Layer {
+    /// The word embeddings used as input to the first transformer layer (index 0).
+    Input,
+
+    /// The hidden state as output by the n-th transformer layer, starting at 1.
+    Output(u32),
+
+    /// The n-th transformer layer counted from the end, `FromLast(1)` being the last layer (not
+    /// the language modelling head).
+    FromLast(u32),
+}
+
+impl Layer {
+    /// Layer index as expected by the API. Fails for `Output(0)` and `FromLast(0)`, which would
+    /// be taken for [`Layer::Input`], and for numbers not fitting the index.
+    pub fn index(&self) -> Result<i32, ValidationError> {
+        let n = match self {
+            Layer::Input => return Ok(0),
+            Layer::Output(n) | Layer::FromLast(n) => *n,
+        };
+        let index = i32::try_from(n).ok().filter(|&index| index > 0).ok_or(
+            ValidationError::OutOfRange {
+                parameter: "layer",
+                value: n as f64,
+                min: 1.0,
+                max: i32::MAX as f64,
+            },
+        )?;
+        Ok(match self {
+            Layer::FromLast(_) => -index,
+            _ => index,
+        })
+    }
+
+    /// Key of the layer in [`EmbeddingResponse::embeddings`], e.g. `layer_1`.
+    pub fn key(&self) -> String {
+        let index = match self {
+            Layer::Input => 0,
+            Layer::Output(n) => i64::from(*n),
+            Layer::FromLast(n) => -i64::from(*n),
+        };
+        format!("layer_{index}")
+    }
+
+    /// Parses a key of [`EmbeddingResponse::embeddings`].
+    pub fn from_key(key: &str) -> Option<Self> {
+        key.strip_prefix("layer_")?
+            .parse::<i32>()
+            .ok()
+            .map(Self::from)
+    }
+}
+
+impl From<i32> for Layer {
+    fn from(index: i32) -> Self {
+        match index {
+            0 => Layer::Input,
+            n if n > 0 => Layer::Output(n as u32),
+            n => Layer::FromLast(n.unsigned_abs()),
+        }
+    }
+}
+
+impl Serialize for Layer {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(self.index().map_err(serde::ser::Error::custom)?)
+    }
+}
+
+impl<'de> Deserialize<'de> for Layer {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        i32::deserialize(deserializer).map(Self::from)
+    }
+}
 
 #[derive(Serialize, Debug, Default)]
 pub struct EmbeddingRequest {
@@ -27,23 +128,15 @@ pub struct EmbeddingRequest {
     /// Each image is converted into 144 tokens.
     pub prompt: Prompt,
 
-    /// A list of layer indices from which to return embeddings.
-    /// - Index 0 corresponds to the word embeddings used as input to the first transformer layer
-    /// - Index 1 corresponds to the hidden state as output by the first transformer layer, index 2 to the output of the second layer etc.
-    /// - Index -1 corresponds to the last transformer layer (not the language modelling head), index -2 to the second last
-    pub layers: Vec<i32>,
+    /// A list of layers from which to return embeddings, see [`Layer`].
+    pub layers: Vec<Layer>,
 
     /// Flag indicating whether the tokenized prompt is to be returned (True) or not (False)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tokens: Option<bool>,
 
-    /// Pooling operation to use. Pooling operations include:
-    /// - "mean": Aggregate token embeddings across the sequence dimension using an average.
-    /// - "weighted_mean": Position weighted mean across sequence dimension with latter tokens having a higher weight.
-    /// - "max": Aggregate token embeddings across the sequence dimension using a maximum.
-    /// - "last_token": Use the last token.
-    /// - "abs_max": Aggregate token embeddings across the sequence dimension using a maximum of absolute values.
-    pub pooling: Vec<String>,
+    /// Pooling operations to use, see [`Pooling`].
+    pub pooling: Vec<Pooling>,
 
     /// Explicitly set embedding type to be passed to the model. This parameter was created to allow for semantic_embed embeddings and will be deprecated. Please use the semantic_embed-endpoint instead.
     #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
@@ -54,33 +147,39 @@ pub struct EmbeddingRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub normalize: Option<bool>,
 
-    /// If set to `None`, attention control parameters only apply to those tokens that have explicitly been set
-    /// in the request. If set to a non-null value, we apply the control parameters to similar tokens as
-    /// well. Controls that have been applied to one token will then be applied to all other tokens that have
-    /// at least the similarity score defined by this parameter. The similarity score is the cosine
-    /// similarity of token embeddings.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub contextual_control_threshold: Option<f64>,
-
-    /// `true`: apply controls on prompt items by adding the `log(control_factor)` to attention scores.
-    /// `false`: apply controls on prompt items by `(attention_scores - -attention_scores.min(-1)) * control_factor`
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub control_log_additive: Option<bool>,
+    /// Settings for how attention controls of the prompt are applied.
+    #[serde(flatten)]
+    pub control_parameters: ControlParameters,
 }
 
 impl EmbeddingRequest {
+    pub fn new(
+        model: impl Into<String>,
+        prompt: Prompt,
+        layers: Vec<Layer>,
+        pooling: Vec<Pooling>,
+    ) -> Self {
+        Self {
+            model: model.into(),
+            prompt,
+            layers,
+            pooling,
+            ..Self::default()
+        }
+    }
+
     pub fn from_text(
         model: impl Into<String>,
         prompt: impl Into<String>,
-        layer: i32,
-        pooling: impl Into<String>,
+        layer: Layer,
+        pooling: Pooling,
         normalize: bool,
     ) -> Self {
         Self {
             model: model.into(),
             prompt: Prompt::from_text(prompt),
-            layers: vec![layer.into()],
-            pooling: vec![pooling.into()],
+            layers: vec![layer],
+            pooling: vec![pooling],
             normalize: Some(normalize),
             ..Self::default()
         }
@@ -89,15 +188,16 @@ impl EmbeddingRequest {
 
 impl_builder_methods!(
     EmbeddingRequest,
+    hosting: Hosting,
     tokens: bool,
     embedding_type: String,
-    normalize: bool,
-    contextual_control_threshold: f64,
-    control_log_additive: bool
+    normalize: bool
 );
 
+impl_control_parameters!(EmbeddingRequest);
+
 type Embedding = Vec<f32>;
-type PoolingEmbeddings = HashMap<String, Embedding>;
+type PoolingEmbeddings = HashMap<Pooling, Embedding>;
 type LayerEmbedings = HashMap<String, PoolingEmbeddings>;
 
 #[derive(Deserialize, Debug)]
@@ -112,6 +212,28 @@ pub struct EmbeddingResponse {
     pub tokens: Option<Vec<String>>,
 }
 
+impl EmbeddingResponse {
+    /// Embedding of `layer` pooled with `pooling`, `None` if it was not requested.
+    pub fn get(&self, layer: Layer, pooling: Pooling) -> Option<&[f32]> {
+        self.embeddings
+            .get(&layer.key())?
+            .get(&pooling)
+            .map(Vec::as_slice)
+    }
+
+    /// All returned embeddings with their layer and pooling, in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (Layer, Pooling, &[f32])> {
+        self.embeddings
+            .iter()
+            .filter_map(|(key, poolings)| Some((Layer::from_key(key)?, poolings)))
+            .flat_map(|(layer, poolings)| {
+                poolings
+                    .iter()
+                    .map(move |(pooling, embedding)| (layer, *pooling, embedding.as_slice()))
+            })
+    }
+}
+
 /// Type of embedding representation to embed the prompt with.
 ///
 /// `"symmetric"`: Symmetric embeddings assume that the text to be compared is interchangeable. Usage examples for symmetric embeddings are clustering, classification, anomaly detection or visualisation tasks. "symmetric" embeddings should be compared with other "symmetric" embeddings.
@@ -121,17 +243,60 @@ pub struct EmbeddingResponse {
 /// `"query"`-embeddings are optimized for shorter texts, such as questions or keywords.
 ///
 /// `"document"`-embeddings are optimized for larger pieces of text to compare queries against.
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum EmbeddingRepresentation {
+    #[default]
     Symmetric,
     Document,
     Query,
 }
 
-impl Default for EmbeddingRepresentation {
-    fn default() -> Self {
-        EmbeddingRepresentation::Symmetric
+/// Size of semantic embeddings. Models return full size embeddings with e.g. 5120 dimensions
+/// for `luminous-base`, which can be compressed to fewer dimensions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum CompressToSize {
+    /// Uncompressed embedding with the full dimension of the model.
+    #[default]
+    Full,
+
+    /// Embedding compressed to 128 dimensions.
+    Compressed128,
+}
+
+impl CompressToSize {
+    pub fn is_full(&self) -> bool {
+        matches!(self, CompressToSize::Full)
+    }
+
+    /// Number of dimensions of compressed embeddings, `None` for full size embeddings.
+    pub fn size(&self) -> Option<u32> {
+        match self {
+            CompressToSize::Full => None,
+            CompressToSize::Compressed128 => Some(128),
+        }
+    }
+}
+
+impl TryFrom<u32> for CompressToSize {
+    type Error = ValidationError;
+
+    /// Compression to `size` dimensions, if supported by the API.
+    fn try_from(size: u32) -> Result<Self, Self::Error> {
+        match size {
+            128 => Ok(CompressToSize::Compressed128),
+            _ => Err(ValidationError::UnsupportedCompressToSize(size)),
+        }
+    }
+}
+
+impl Serialize for CompressToSize {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.size() {
+            None => serializer.serialize_none(),
+            Some(size) => serializer.serialize_u32(size),
+        }
     }
 }
 
@@ -166,36 +331,48 @@ pub struct SemanticEmbeddingRequest {
     /// The default behavior is to return the full embedding with 5120 dimensions. With this parameter you can compress the returned embedding to 128 dimensions.
     /// The compression is expected to result in a small drop in accuracy performance (4-6%), with the benefit of being much smaller, which makes comparing these embeddings much faster for use cases where speed is critical.
     /// With the compressed embedding can also perform better if you are embedding really short texts or documents.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub compress_to_size: Option<i32>,
+    #[serde(skip_serializing_if = "CompressToSize::is_full")]
+    pub compress_to_size: CompressToSize,
 
     /// Return normalized embeddings. This can be used to save on additional compute when applying a cosine similarity metric.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub normalize: Option<bool>,
 
-    /// If set to `null`, attention control parameters only apply to those tokens that have explicitly been set in the request.
-    /// If set to a non-null value, we apply the control parameters to similar tokens as well.
-    /// Controls that have been applied to one token will then be applied to all other tokens
-    /// that have at least the similarity score defined by this parameter.
-    /// The similarity score is the cosine similarity of token embeddings.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub contextual_control_threshold: Option<f64>,
+    /// Settings for how attention controls of the prompt are applied.
+    #[serde(flatten)]
+    pub control_parameters: ControlParameters,
+}
 
-    /// `true`: apply controls on prompt items by adding the `log(control_factor)` to attention scores.
-    /// `false`: apply controls on prompt items by `(attention_scores - -attention_scores.min(-1)) * control_factor`
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub control_log_additive: Option<bool>,
+impl SemanticEmbeddingRequest {
+    pub fn from_text(
+        model: impl Into<String>,
+        text: impl Into<String>,
+        representation: EmbeddingRepresentation,
+    ) -> Self {
+        Self {
+            model: model.into(),
+            prompt: Prompt::from_text(text),
+            representation,
+            ..Self::default()
+        }
+    }
 }
 
 impl_builder_methods!(
     SemanticEmbeddingRequest,
     hosting: Hosting,
-    compress_to_size: i32,
-    normalize: bool,
-    contextual_control_threshold: f64,
-    control_log_additive: bool
+    normalize: bool
 );
 
+impl SemanticEmbeddingRequest {
+    pub fn compress_to_size(mut self, compress_to_size: CompressToSize) -> Self {
+        self.compress_to_size = compress_to_size;
+        self
+    }
+}
+
+impl_control_parameters!(SemanticEmbeddingRequest);
+
 #[derive(Deserialize, Debug)]
 pub struct SemanticEmbeddingResponse {
     /// model name and version (if any) of the used model for inference
@@ -205,7 +382,7 @@ pub struct SemanticEmbeddingResponse {
     pub embedding: Embedding,
 }
 
-#[derive(Serialize, Debug, Default)]
+#[derive(Serialize, Debug, Default, Clone)]
 pub struct BatchSemanticEmbeddingRequest {
     /// Name of the model to use. A model name refers to a model's architecture (number of parameters among others). The most recent version of the model is always used. The model output contains information as to the model version. To create semantic embeddings, please use `luminous-base`.
     pub model: String,
@@ -230,36 +407,62 @@ pub struct BatchSemanticEmbeddingRequest {
     /// The default behavior is to return the full embedding with 5120 dimensions. With this parameter you can compress the returned embedding to 128 dimensions.
     /// The compression is expected to result in a small drop in accuracy performance (4-6%), with the benefit of being much smaller, which makes comparing these embeddings much faster for use cases where speed is critical.
     /// With the compressed embedding can also perform better if you are embedding really short texts or documents.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub compress_to_size: Option<i32>,
+    #[serde(skip_serializing_if = "CompressToSize::is_full")]
+    pub compress_to_size: CompressToSize,
 
     /// Return normalized embeddings. This can be used to save on additional compute when applying a cosine similarity metric.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub normalize: Option<bool>,
 
-    /// If set to `null`, attention control parameters only apply to those tokens that have explicitly been set in the request.
-    /// If set to a non-null value, we apply the control parameters to similar tokens as well.
-    /// Controls that have been applied to one token will then be applied to all other tokens
-    /// that have at least the similarity score defined by this parameter.
-    /// The similarity score is the cosine similarity of token embeddings.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub contextual_control_threshold: Option<f64>,
+    /// Settings for how attention controls of the prompt are applied.
+    #[serde(flatten)]
+    pub control_parameters: ControlParameters,
+}
 
-    /// `true`: apply controls on prompt items by adding the `log(control_factor)` to attention scores.
-    /// `false`: apply controls on prompt items by `(attention_scores - -attention_scores.min(-1)) * control_factor`
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub control_log_additive: Option<bool>,
+impl BatchSemanticEmbeddingRequest {
+    /// One text prompt for each of `texts`.
+    pub fn from_texts<T: Into<String>>(
+        model: impl Into<String>,
+        texts: impl IntoIterator<Item = T>,
+        representation: EmbeddingRepresentation,
+    ) -> Self {
+        Self {
+            model: model.into(),
+            prompts: texts.into_iter().map(Prompt::from_text).collect(),
+            representation,
+            ..Self::default()
+        }
+    }
+
+    /// Copy of the request with all settings but only the given prompts.
+    pub(crate) fn with_prompts(&self, prompts: Vec<Prompt>) -> Self {
+        Self {
+            model: self.model.clone(),
+            hosting: self.hosting.clone(),
+            prompts,
+            representation: self.representation,
+            compress_to_size: self.compress_to_size,
+            normalize: self.normalize,
+            control_parameters: self.control_parameters.clone(),
+        }
+    }
 }
 
 impl_builder_methods!(
     BatchSemanticEmbeddingRequest,
     hosting: Hosting,
-    compress_to_size: i32,
-    normalize: bool,
-    contextual_control_threshold: f64,
-    control_log_additive: bool
+    normalize: bool
 );
 
+impl BatchSemanticEmbeddingRequest {
+    pub fn compress_to_size(mut self, compress_to_size: CompressToSize) -> Self {
+        self.compress_to_size = compress_to_size;
+        self
+    }
+}
+
+impl_control_parameters!(BatchSemanticEmbeddingRequest);
+
 #[derive(Deserialize, Debug)]
 pub struct BatchSemanticEmbeddingResponse {
     /// model name and version (if any) of the used model for inference
@@ -268,3 +471,277 @@ pub struct BatchSemanticEmbeddingResponse {
     /// Vector of embeddings (one fore each prompt)
     pub embeddings: Vec<Embedding>,
 }
+
+/// Embeds a prompt conditioned on an instruction describing the downstream task, e.g.
+/// "Represent the question for retrieving supporting documents".
+#[derive(Serialize, Debug, Default)]
+pub struct InstructableEmbeddingRequest {
+    /// Name of the model to use. Only models supporting instructable embeddings can be used.
+    pub model: String,
+
+    /// Optional parameter that specifies which datacenters may process the request, see
+    /// [`Hosting`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hosting: Option<Hosting>,
+
+    /// Prompt to embed.
+    pub input: Prompt,
+
+    /// Instruction describing how the input should be embedded.
+    pub instruction: String,
+
+    /// Return normalized embeddings. This can be used to save on additional compute when applying a cosine similarity metric.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalize: Option<bool>,
+}
+
+impl InstructableEmbeddingRequest {
+    pub fn from_text(
+        model: impl Into<String>,
+        text: impl Into<String>,
+        instruction: impl Into<String>,
+    ) -> Self {
+        Self {
+            model: model.into(),
+            input: Prompt::from_text(text),
+            instruction: instruction.into(),
+            ..Self::default()
+        }
+    }
+}
+
+impl_builder_methods!(InstructableEmbeddingRequest, hosting: Hosting, normalize: bool);
+
+#[derive(Deserialize, Debug)]
+pub struct InstructableEmbeddingResponse {
+    /// model name and version (if any) of the used model for inference
+    pub model_version: String,
+
+    /// A list of floats that can be used to compare against other embeddings.
+    pub embedding: Embedding,
+}
+
+/// Settings of [`Client::embed_stream`](crate::Client::embed_stream).
+#[derive(Debug, Clone)]
+pub struct EmbedStreamOptions {
+    /// Name of the model to use, e.g. `luminous-base`.
+    pub model: String,
+
+    pub representation: EmbeddingRepresentation,
+    pub compress_to_size: CompressToSize,
+    pub normalize: Option<bool>,
+
+    /// Number of prompts sent with each batch request.
+    pub batch_size: usize,
+
+    /// Maximum number of batch requests in flight.
+    pub max_concurrency: usize,
+
+    /// Retries of batches failing with a transient error.
+    pub retry: RetryPolicy,
+
+    pub nice: Option<bool>,
+}
+
+impl EmbedStreamOptions {
+    pub fn new(model: impl Into<String>, representation: EmbeddingRepresentation) -> Self {
+        Self {
+            model: model.into(),
+            representation,
+            compress_to_size: CompressToSize::Full,
+            normalize: None,
+            batch_size: 100,
+            max_concurrency: 4,
+            retry: RetryPolicy::default(),
+            nice: None,
+        }
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn compress_to_size(mut self, compress_to_size: CompressToSize) -> Self {
+        self.compress_to_size = compress_to_size;
+        self
+    }
+
+    pub(crate) fn to_request(&self, prompts: Vec<Prompt>) -> BatchSemanticEmbeddingRequest {
+        BatchSemanticEmbeddingRequest {
+            model: self.model.clone(),
+            prompts,
+            representation: self.representation,
+            compress_to_size: self.compress_to_size,
+            normalize: self.normalize,
+            ..BatchSemanticEmbeddingRequest::default()
+        }
+    }
+}
+
+impl_builder_methods!(EmbedStreamOptions, normalize: bool, nice: bool);
+
+/// How the chunk embeddings of [`Client::embed_document`](crate::Client::embed_document) are
+/// combined into a single embedding.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    /// Element-wise mean of the chunk embeddings.
+    #[default]
+    Mean,
+
+    /// Element-wise maximum of the chunk embeddings.
+    Max,
+
+    /// Element-wise mean weighted by the number of tokens of each chunk, so a short last chunk
+    /// contributes less.
+    WeightedMean,
+}
+
+impl Aggregation {
+    /// Combines `embeddings`, each given with its weight for [`Aggregation::WeightedMean`].
+    /// Embeddings of different length are combined over the shortest length.
+    pub fn aggregate(&self, embeddings: &[(&[f32], usize)]) -> Vec<f32> {
+        let dimension = embeddings
+            .iter()
+            .map(|(embedding, _)| embedding.len())
+            .min()
+            .unwrap_or(0);
+        match self {
+            Aggregation::Max => (0..dimension)
+                .map(|i| {
+                    embeddings
+                        .iter()
+                        .map(|(embedding, _)| embedding[i])
+                        .fold(f32::NEG_INFINITY, f32::max)
+                })
+                .collect(),
+            Aggregation::Mean | Aggregation::WeightedMean => {
+                let weight = |w: usize| match self {
+                    Aggregation::WeightedMean => w as f32,
+                    _ => 1.0,
+                };
+                let total: f32 = embeddings.iter().map(|(_, w)| weight(*w)).sum();
+                let mut sum = vec![0.0; dimension];
+                for (embedding, w) in embeddings {
+                    for (s, v) in sum.iter_mut().zip(embedding.iter()) {
+                        *s += v * weight(*w);
+                    }
+                }
+                if total > 0.0 {
+                    sum.iter_mut().for_each(|s| *s /= total);
+                }
+                sum
+            }
+        }
+    }
+}
+
+/// Settings of [`Client::embed_document`](crate::Client::embed_document).
+#[derive(Debug, Clone)]
+pub struct EmbedDocumentOptions {
+    /// Name of the model to use, e.g. `luminous-base`.
+    pub model: String,
+
+    pub representation: EmbeddingRepresentation,
+    pub compress_to_size: CompressToSize,
+    pub normalize: Option<bool>,
+
+    /// Number of tokens of each chunk.
+    pub chunk_size: usize,
+
+    /// Number of tokens shared by consecutive chunks.
+    pub overlap: usize,
+
+    /// Number of chunks embedded per request.
+    pub batch_size: usize,
+
+    /// Number of requests in flight at any time.
+    pub max_concurrency: usize,
+
+    pub nice: Option<bool>,
+}
+
+impl EmbedDocumentOptions {
+    pub fn new(model: impl Into<String>, representation: EmbeddingRepresentation) -> Self {
+        Self {
+            model: model.into(),
+            representation,
+            compress_to_size: CompressToSize::Full,
+            normalize: None,
+            chunk_size: 512,
+            overlap: 64,
+            batch_size: 100,
+            max_concurrency: 4,
+            nice: None,
+        }
+    }
+
+    pub fn compress_to_size(mut self, compress_to_size: CompressToSize) -> Self {
+        self.compress_to_size = compress_to_size;
+        self
+    }
+
+    pub fn chunks(mut self, chunk_size: usize, overlap: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self.overlap = overlap;
+        self
+    }
+
+    /// Number of chunks embedded per request. Defaults to 100.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Number of requests in flight at any time. Defaults to 4.
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency;
+        self
+    }
+}
+
+impl_builder_methods!(EmbedDocumentOptions, normalize: bool, nice: bool);
+
+/// Embedding of one chunk of a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkEmbedding {
+    /// Byte range of the chunk in the document.
+    pub range: Range<usize>,
+
+    /// Number of tokens of the chunk.
+    pub token_count: usize,
+
+    pub embedding: Embedding,
+}
+
+/// Result of [`Client::embed_document`](crate::Client::embed_document).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentEmbedding {
+    /// model name and version (if any) of the used model for inference
+    pub model_version: String,
+
+    /// Aggregate of the chunk embeddings.
+    pub embedding: Embedding,
+
+    /// Embeddings of the chunks in document order.
+    pub chunks: Vec<ChunkEmbedding>,
+}
+
+/// A batch of [`Client::embed_stream`](crate::Client::embed_stream) failed permanently.
+#[derive(ThisError, Debug)]
+#[error("Embedding of the prompts {indices:?} failed: {source}")]
+pub struct EmbedBatchError {
+    /// Indices of the prompts of the failed batch.
+    pub indices: Range<usize>,
+    pub source: ApiError,
+}
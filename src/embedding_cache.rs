@@ -0,0 +1,177 @@
+//! On-disk cache for semantic embeddings, so repeated runs of a pipeline only pay for new or
+//! changed documents. Requires the `embedding-cache` feature.
+//!
+//! Every embedding is stored as a file of little endian `f32` values named after the SHA-256
+//! hash of its [`CacheKey`].
+use super::cache_file::write_atomically;
+use super::client::Client;
+use super::completion::{Modality, Prompt};
+use super::embedding::{
+    BatchSemanticEmbeddingRequest, BatchSemanticEmbeddingResponse, CompressToSize,
+    EmbeddingRepresentation,
+};
+use super::error::ApiError;
+use sha2::{Digest, Sha256};
+use std::{fs, io, path::PathBuf};
+
+/// Identifies an embedding by all settings which influence it. Texts are normalized by trimming
+/// them and collapsing runs of whitespace, so formatting changes do not invalidate the cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheKey {
+    hash: String,
+}
+
+impl CacheKey {
+    pub fn new(
+        model: &str,
+        representation: EmbeddingRepresentation,
+        compress_to_size: CompressToSize,
+        normalize: Option<bool>,
+        text: &str,
+    ) -> Self {
+        let representation = match representation {
+            EmbeddingRepresentation::Symmetric => "symmetric",
+            EmbeddingRepresentation::Document => "document",
+            EmbeddingRepresentation::Query => "query",
+        };
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let mut hasher = Sha256::new();
+        for part in [
+            model,
+            representation,
+            &format!("{:?}", compress_to_size.size()),
+            &format!("{normalize:?}"),
+            &text,
+        ] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        Self {
+            hash: format!("{:x}", hasher.finalize()),
+        }
+    }
+
+    /// Key for `prompt` embedded with the settings of `req`. Only prompts consisting of a single
+    /// text item without controls can be cached.
+    pub fn for_prompt(req: &BatchSemanticEmbeddingRequest, prompt: &Prompt) -> Option<Self> {
+        match prompt.items() {
+            [Modality::Text {
+                data,
+                controls: None,
+            }] => Some(Self::new(
+                &req.model,
+                req.representation,
+                req.compress_to_size,
+                req.normalize,
+                data,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Hex encoded SHA-256 hash of the key.
+    pub fn as_str(&self) -> &str {
+        &self.hash
+    }
+}
+
+/// Directory of cached embeddings.
+pub struct EmbeddingCache {
+    dir: PathBuf,
+}
+
+impl EmbeddingCache {
+    /// Opens the cache in `dir`, creating the directory if necessary.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, ApiError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, key: &CacheKey) -> PathBuf {
+        self.dir.join(&key.hash[..2]).join(&key.hash)
+    }
+
+    /// The cached embedding for `key`. Empty or truncated entries, e.g. from a crashed writer,
+    /// are removed and treated as missing.
+    pub fn get(&self, key: &CacheKey) -> Result<Option<Vec<f32>>, ApiError> {
+        let path = self.path(key);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        if bytes.is_empty() || bytes.len() % 4 != 0 {
+            let _ = fs::remove_file(path);
+            return Ok(None);
+        }
+        Ok(Some(
+            bytes
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+                .collect(),
+        ))
+    }
+
+    pub fn put(&self, key: &CacheKey, embedding: &[f32]) -> Result<(), ApiError> {
+        let path = self.path(key);
+        fs::create_dir_all(path.parent().unwrap())?;
+        let bytes: Vec<u8> = embedding.iter().flat_map(|v| v.to_le_bytes()).collect();
+        write_atomically(&path, &bytes)
+    }
+}
+
+impl Client {
+    /// Like [`Client::batch_semantic_embed`], but embeddings found in `cache` are not requested
+    /// again, and new embeddings are added to the cache. If all prompts are cached, the
+    /// `model_version` of the response is empty.
+    pub async fn batch_semantic_embed_cached(
+        &self,
+        req: &BatchSemanticEmbeddingRequest,
+        cache: &EmbeddingCache,
+        nice: Option<bool>,
+    ) -> Result<BatchSemanticEmbeddingResponse, ApiError> {
+        let mut embeddings = Vec::with_capacity(req.prompts.len());
+        let mut missing = Vec::new();
+        for (index, prompt) in req.prompts.iter().enumerate() {
+            let key = CacheKey::for_prompt(req, prompt);
+            let cached = match &key {
+                Some(key) => cache.get(key)?,
+                None => None,
+            };
+            if cached.is_none() {
+                missing.push((index, key));
+            }
+            embeddings.push(cached);
+        }
+
+        let mut model_version = String::new();
+        if !missing.is_empty() {
+            let prompts = missing
+                .iter()
+                .map(|(index, _)| req.prompts[*index].clone())
+                .collect();
+            let response = self
+                .batch_semantic_embed(&req.with_prompts(prompts), nice)
+                .await?;
+            model_version = response.model_version;
+            for ((index, key), embedding) in missing.into_iter().zip(response.embeddings) {
+                if let Some(key) = key {
+                    cache.put(&key, &embedding)?;
+                }
+                embeddings[index] = Some(embedding);
+            }
+        }
+
+        let embeddings = embeddings
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| {
+                ApiError::UnexpectedResponse("Fewer embeddings than prompts returned.".into())
+            })?;
+        Ok(BatchSemanticEmbeddingResponse {
+            model_version,
+            embeddings,
+        })
+    }
+}
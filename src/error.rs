@@ -1,3 +1,6 @@
+use crate::image_processing::LoadImageError;
+use crate::version::{ApiVersion, UnsupportedParameter};
+
 #[derive(thiserror::Error, Debug)]
 pub enum ApiError {
     /// User exceeds his current Task Quota.
@@ -23,4 +26,142 @@ pub enum ApiError {
 
     #[error(transparent)]
     Tokenizer(#[from] tokenizers::Error),
+
+    /// The API answered successfully, but the response lacks data required by a helper.
+    #[error("Unexpected response: {0}")]
+    UnexpectedResponse(String),
+
+    /// Reading or writing a local file failed.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// (De-)serialization of locally stored data failed.
+    #[error(transparent)]
+    Serialization(#[from] serde_json::Error),
+
+    /// The request was rejected locally before sending it, see [`ValidationError`].
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
+
+    /// The budget of the client is used up, see [`crate::Client::with_budget`]. `spent` and
+    /// `limit` are tokens or credits, depending on the [`crate::pricing::Budget`].
+    #[error("The budget of {limit} is used up, {spent} have been spent.")]
+    BudgetExceeded { spent: f64, limit: f64 },
+
+    /// The prompt does not fit into the available context even after truncation.
+    #[error("Prompt requires {required} tokens, but only {available} tokens are available.")]
+    ContextOverflow { required: usize, available: usize },
+}
+
+/// Violation of a known API constraint, detected before the request is sent.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    #[error("The prompt is empty.")]
+    EmptyPrompt,
+
+    #[error("{parameter} is {value}, but must be within {min} and {max}.")]
+    OutOfRange {
+        parameter: &'static str,
+        value: f64,
+        min: f64,
+        max: f64,
+    },
+
+    #[error("minimum_tokens ({minimum}) must not exceed maximum_tokens ({maximum}).")]
+    MinimumExceedsMaximum { minimum: u32, maximum: u32 },
+
+    #[error("best_of ({best_of}) must be strictly greater than n ({n}).")]
+    BestOfNotGreaterThanN { best_of: u32, n: u32 },
+
+    #[error(
+        "Completion bias inclusion '{inclusion}' and exclusion '{exclusion}' must not be prefixes \
+        of each other."
+    )]
+    BiasPrefixConflict {
+        inclusion: String,
+        exclusion: String,
+    },
+
+    #[error("Embeddings cannot be compressed to {0} dimensions, only to 128.")]
+    UnsupportedCompressToSize(u32),
+
+    /// The prompt is larger than the request size limit. `item_sizes` holds the size of every
+    /// prompt item in bytes, to find the culprit.
+    #[error("The prompt has {size} bytes, exceeding the limit of {limit} bytes.")]
+    PayloadTooLarge {
+        size: usize,
+        limit: usize,
+        item_sizes: Vec<usize>,
+    },
+
+    /// The client has a budget in credits, but there is no price for the model of the request.
+    #[error("No price known for model '{0}', so its cost cannot be counted against the budget.")]
+    UnpricedModel(String),
+
+    /// The request sets parameters unknown to the deployment or whose support is unknown, see
+    /// [`crate::RequestOptions::strict`].
+    #[error(
+        "API version {version} may not support all parameters: {}.",
+        parameters.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+    )]
+    Unsupported {
+        version: ApiVersion,
+        parameters: Vec<UnsupportedParameter>,
+    },
+}
+
+/// Failure of [`crate::fits_context`].
+#[derive(thiserror::Error, Debug)]
+pub enum BudgetError {
+    /// Prompt and completion together need more tokens than the model's context provides.
+    #[error(
+        "Prompt ({prompt_tokens} tokens) plus maximum_tokens ({maximum_tokens}) exceed the \
+        context of {context_length} tokens by {overflow} tokens."
+    )]
+    Overflow {
+        prompt_tokens: usize,
+        maximum_tokens: u32,
+        context_length: usize,
+        /// Number of tokens by which prompt or completion have to shrink.
+        overflow: usize,
+    },
+
+    /// The prompt could not be tokenized.
+    #[error(transparent)]
+    Tokenization(#[from] ApiError),
+}
+
+/// Failure of [`crate::Modality::from_image_url`].
+#[derive(thiserror::Error, Debug)]
+pub enum FetchImageError {
+    /// Downloading the image failed.
+    #[error(transparent)]
+    Request(#[from] ApiError),
+
+    #[error("Expected an image, but the server sent content of type {0}.")]
+    NotAnImage(String),
+
+    /// The server did not declare the content type, so the content may not be an image.
+    #[error("Expected an image, but the server sent no content type.")]
+    MissingContentType,
+
+    #[error("The image exceeds the limit of {limit} bytes.")]
+    TooLarge { limit: usize },
+
+    /// The image could not be decoded.
+    #[error(transparent)]
+    Load(#[from] LoadImageError),
+}
+
+impl ApiError {
+    /// `true` for errors which are likely to go away when the request is repeated later, i.e.
+    /// rate limits, busy models, server errors and connection problems.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ApiError::TooManyRequests | ApiError::Busy => true,
+            ApiError::Http { status, .. } => *status == 408 || *status >= 500,
+            ApiError::Client(error) => error.is_timeout() || error.is_connect(),
+            _ => false,
+        }
+    }
 }
@@ -0,0 +1,626 @@
+//! Evaluation of a model on a dataset of prompts with expected completions, and scoring helpers
+//! like zero-shot classification, all using the `/evaluate` endpoint.
+//!
+//! ```no_run
+//!use aleph_alpha_api::{eval::EvalItem, Client, RetryPolicy, LUMINOUS_BASE};
+//!
+//!async fn run(client: &Client) {
+//!    let file = std::io::BufReader::new(std::fs::File::open("dataset.jsonl").unwrap());
+//!    let items = EvalItem::read_jsonl(file).unwrap();
+//!    let report = client
+//!        .run_eval(LUMINOUS_BASE, &items, 4, &RetryPolicy::default(), Some(true))
+//!        .await;
+//!    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+//!}
+//! ```
+use super::batch::{run_batch, RetryPolicy};
+use super::client::Client;
+use super::completion::{CompletionRequest, CompletionResponse, Prompt};
+use super::error::{ApiError, ValidationError};
+use super::evaluate::{EvaluationRequest, EvaluationResult};
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, fmt, io::BufRead};
+use tokenizers::Tokenizer;
+
+/// A prompt together with the completion the model is expected to produce.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EvalItem {
+    pub prompt: String,
+    #[serde(alias = "completion_expected")]
+    pub expected_completion: String,
+}
+
+impl EvalItem {
+    pub fn new(prompt: impl Into<String>, expected_completion: impl Into<String>) -> Self {
+        Self {
+            prompt: prompt.into(),
+            expected_completion: expected_completion.into(),
+        }
+    }
+
+    /// Reads one JSON object with `prompt` and `expected_completion` per line. Empty lines are
+    /// skipped.
+    pub fn read_jsonl(reader: impl BufRead) -> Result<Vec<Self>, ApiError> {
+        let mut items = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                items.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(items)
+    }
+}
+
+/// Result of a single [`EvalItem`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EvalItemResult {
+    /// Position of the item in the dataset.
+    pub index: usize,
+    pub log_perplexity_per_character: Option<f64>,
+    pub correct_greedy: Option<bool>,
+    /// Error message if the item failed permanently.
+    pub error: Option<String>,
+}
+
+impl EvalItemResult {
+    pub fn from_result(index: usize, result: &EvaluationResult) -> Self {
+        Self {
+            index,
+            log_perplexity_per_character: result.log_perplexity_per_character,
+            correct_greedy: result.correct_greedy,
+            error: None,
+        }
+    }
+}
+
+/// Aggregated metrics of an evaluation run together with the results of all items.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EvalReport {
+    pub model: String,
+
+    /// Mean of the log perplexity per character over all items which report it. Independent of
+    /// the tokenizer, so it can be compared across models.
+    pub mean_log_perplexity_per_character: Option<f64>,
+
+    /// Share of the items for which greedy decoding produces the expected completion.
+    pub greedy_accuracy: Option<f64>,
+
+    /// Number of items which failed permanently.
+    pub failed: usize,
+
+    /// Per item results in dataset order.
+    pub items: Vec<EvalItemResult>,
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0usize), |(sum, count), v| (sum + v, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+impl EvalReport {
+    /// Aggregates the metrics of `items`.
+    pub fn new(model: impl Into<String>, items: Vec<EvalItemResult>) -> Self {
+        let mean_log_perplexity_per_character =
+            mean(items.iter().filter_map(|i| i.log_perplexity_per_character));
+        let greedy_accuracy = mean(
+            items
+                .iter()
+                .filter_map(|i| i.correct_greedy)
+                .map(|correct| if correct { 1.0 } else { 0.0 }),
+        );
+        Self {
+            model: model.into(),
+            mean_log_perplexity_per_character,
+            greedy_accuracy,
+            failed: items.iter().filter(|i| i.error.is_some()).count(),
+            items,
+        }
+    }
+}
+
+/// Metrics of one model in a [`ModelComparison`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModelMetrics {
+    pub model: String,
+    pub mean_log_perplexity_per_character: Option<f64>,
+    pub greedy_accuracy: Option<f64>,
+    pub failed: usize,
+}
+
+/// The same evaluation set run against several models, see [`Client::compare_models`]. Models
+/// are compared by perplexity per character, so differing tokenizers do not skew the results.
+/// Displays as a text table.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModelComparison {
+    /// One row per model, in the order the models were given.
+    pub models: Vec<ModelMetrics>,
+}
+
+impl ModelComparison {
+    pub fn from_reports(reports: &[EvalReport]) -> Self {
+        let models = reports
+            .iter()
+            .map(|report| ModelMetrics {
+                model: report.model.clone(),
+                mean_log_perplexity_per_character: report.mean_log_perplexity_per_character,
+                greedy_accuracy: report.greedy_accuracy,
+                failed: report.failed,
+            })
+            .collect();
+        Self { models }
+    }
+
+    /// The model with the lowest mean log perplexity per character.
+    pub fn best(&self) -> Option<&ModelMetrics> {
+        self.models
+            .iter()
+            .filter(|metrics| metrics.mean_log_perplexity_per_character.is_some())
+            .min_by(|a, b| {
+                a.mean_log_perplexity_per_character
+                    .partial_cmp(&b.mean_log_perplexity_per_character)
+                    .unwrap_or(Ordering::Equal)
+            })
+    }
+}
+
+impl fmt::Display for ModelComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .models
+            .iter()
+            .map(|metrics| metrics.model.len())
+            .max()
+            .unwrap_or(0)
+            .max("model".len());
+        let format = |value: Option<f64>| value.map_or("-".to_owned(), |v| format!("{v:.4}"));
+        writeln!(f, "{:width$} | log ppl/char | greedy acc | failed", "model")?;
+        for metrics in &self.models {
+            writeln!(
+                f,
+                "{:width$} | {:>12} | {:>10} | {:>6}",
+                metrics.model,
+                format(metrics.mean_log_perplexity_per_character),
+                format(metrics.greedy_accuracy),
+                metrics.failed
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Score of a label of a zero-shot classification, see [`Client::classify`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LabelScore {
+    pub label: String,
+
+    /// Log perplexity per character of the label as completion of the prompt, lower is better.
+    pub log_perplexity_per_character: f64,
+
+    /// Probability of the label, the softmax of the negated log perplexities over all labels.
+    pub probability: f64,
+}
+
+/// Turns the log perplexities per character of `labels` into a distribution, most likely label
+/// first.
+pub fn label_distribution(labels: &[&str], log_perplexities: &[f64]) -> Vec<LabelScore> {
+    let max = log_perplexities
+        .iter()
+        .map(|p| -p)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let weights: Vec<f64> = log_perplexities.iter().map(|p| (-p - max).exp()).collect();
+    let total: f64 = weights.iter().sum();
+    let mut scores: Vec<LabelScore> = labels
+        .iter()
+        .zip(log_perplexities)
+        .zip(&weights)
+        .map(|((label, perplexity), weight)| LabelScore {
+            label: label.to_string(),
+            log_perplexity_per_character: *perplexity,
+            probability: weight / total,
+        })
+        .collect();
+    scores.sort_by(|a, b| {
+        b.probability
+            .partial_cmp(&a.probability)
+            .unwrap_or(Ordering::Equal)
+    });
+    scores
+}
+
+/// Scores of the choices of a multiple-choice question, see [`Client::rank_choices`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChoiceRanking {
+    /// Index of the highest scoring choice.
+    pub best: usize,
+
+    /// Score of each choice in input order, higher is better. Either the log probability of the
+    /// choice or, if length normalized, its negated log perplexity per character.
+    pub scores: Vec<f64>,
+}
+
+impl ChoiceRanking {
+    /// Ranks the choices by their evaluation `results`. `None` if a result lacks the required
+    /// metric or there are no choices.
+    pub fn from_results(results: &[EvaluationResult], length_normalized: bool) -> Option<Self> {
+        let scores = results
+            .iter()
+            .map(|result| match length_normalized {
+                true => result.log_perplexity_per_character.map(|p| -p),
+                false => result.log_probability,
+            })
+            .collect::<Option<Vec<f64>>>()?;
+        let best = scores
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(Ordering::Equal))?
+            .0;
+        Some(Self { best, scores })
+    }
+}
+
+/// Prompt of a multiple-choice question, `"{context}\n\nQuestion: {question}\nAnswer:"`.
+pub fn multiple_choice_prompt(context: &str, question: &str) -> String {
+    format!("{context}\n\nQuestion: {question}\nAnswer:")
+}
+
+/// A completion with its length normalized log probability, see
+/// [`Client::rerank_completions`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScoredCompletion {
+    pub completion: String,
+
+    /// Log probability per character of the completion given the prompt, higher is better.
+    pub score: f64,
+}
+
+/// Settings of [`Client::corpus_perplexity`].
+#[derive(Debug, Clone)]
+pub struct PerplexityOptions {
+    /// Number of tokens of each evaluated window, prompt included. Must fit into the context of
+    /// the model.
+    pub window_size: usize,
+
+    /// Number of preceding tokens given as prompt to each window, so the scored tokens are not
+    /// evaluated without context. Must be at least 1 and smaller than `window_size`.
+    pub context_size: usize,
+
+    /// Maximum number of evaluation requests in flight.
+    pub max_concurrency: usize,
+
+    pub retry: RetryPolicy,
+    pub nice: Option<bool>,
+}
+
+impl Default for PerplexityOptions {
+    fn default() -> Self {
+        Self {
+            window_size: 1024,
+            context_size: 256,
+            max_concurrency: 4,
+            retry: RetryPolicy::default(),
+            nice: None,
+        }
+    }
+}
+
+/// Splits `text` into (prompt, expected completion) pairs for perplexity evaluation. Every token
+/// but the first is scored exactly once, each as part of a completion of at most
+/// `window_size - context_size` tokens following a prompt of up to `context_size` tokens. Fails
+/// unless `context_size` is at least 1 and smaller than `window_size`.
+pub fn perplexity_windows(
+    tokenizer: &Tokenizer,
+    text: &str,
+    window_size: usize,
+    context_size: usize,
+) -> Result<Vec<(String, String)>, ApiError> {
+    if context_size == 0 || context_size >= window_size {
+        return Err(ValidationError::OutOfRange {
+            parameter: "context_size",
+            value: context_size as f64,
+            min: 1.0,
+            max: window_size.saturating_sub(1) as f64,
+        }
+        .into());
+    }
+    let encoding = tokenizer.encode(text, false)?;
+    let offsets = encoding.get_offsets();
+    let stride = window_size - context_size;
+    Ok((1..offsets.len())
+        .step_by(stride)
+        .map(|start| {
+            let end = (start + stride).min(offsets.len());
+            let prompt_start = start.saturating_sub(context_size);
+            let split = offsets[start - 1].1;
+            (
+                text[offsets[prompt_start].0..split].to_owned(),
+                text[split..offsets[end - 1].1].to_owned(),
+            )
+        })
+        .collect())
+}
+
+/// Perplexity of a corpus, aggregated over all evaluated windows.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CorpusPerplexity {
+    /// Number of evaluated windows.
+    pub windows: usize,
+
+    /// Number of scored characters.
+    pub characters: usize,
+
+    /// Negative log probability of the scored text divided by its number of characters.
+    pub log_perplexity_per_character: f64,
+
+    /// 95% confidence interval of `log_perplexity_per_character`, estimated from the variation
+    /// between windows.
+    pub confidence_interval: (f64, f64),
+}
+
+impl CorpusPerplexity {
+    /// Aggregates windows given as negative log probability and number of characters. `None` if
+    /// no characters were scored.
+    pub fn from_windows(windows: &[(f64, usize)]) -> Option<Self> {
+        let characters: usize = windows.iter().map(|(_, chars)| chars).sum();
+        if characters == 0 {
+            return None;
+        }
+        let total = characters as f64;
+        let mean = windows.iter().map(|(nll, _)| nll).sum::<f64>() / total;
+        // Standard error of a ratio estimator, with windows as samples.
+        let n = windows.len() as f64;
+        let variance = if windows.len() > 1 {
+            windows
+                .iter()
+                .map(|(nll, chars)| (nll - mean * *chars as f64).powi(2))
+                .sum::<f64>()
+                / (total * total)
+                * n
+                / (n - 1.0)
+        } else {
+            0.0
+        };
+        let margin = 1.96 * variance.sqrt();
+        Some(Self {
+            windows: windows.len(),
+            characters,
+            log_perplexity_per_character: mean,
+            confidence_interval: (mean - margin, mean + margin),
+        })
+    }
+
+    /// Perplexity per character, `exp(log_perplexity_per_character)`.
+    pub fn perplexity_per_character(&self) -> f64 {
+        self.log_perplexity_per_character.exp()
+    }
+}
+
+/// Maximum number of evaluation requests in flight while scoring the labels, choices or
+/// completions of a single prompt.
+const MAX_CONCURRENT_EVALUATIONS: usize = 4;
+
+/// Expected completion for `label`, with a separating space unless it starts with whitespace.
+fn as_completion(label: &str) -> String {
+    if label.starts_with(char::is_whitespace) {
+        label.to_owned()
+    } else {
+        format!(" {label}")
+    }
+}
+
+impl Client {
+    /// Evaluates all `completions` of `prompt` concurrently, with at most
+    /// [`MAX_CONCURRENT_EVALUATIONS`] requests in flight.
+    async fn evaluate_completions(
+        &self,
+        model: &str,
+        prompt: &Prompt,
+        completions: impl IntoIterator<Item = String>,
+        nice: Option<bool>,
+    ) -> Result<Vec<EvaluationResult>, ApiError> {
+        let requests: Vec<EvaluationRequest> = completions
+            .into_iter()
+            .map(|completion_expected| EvaluationRequest {
+                model: model.to_owned(),
+                prompt: prompt.clone(),
+                completion_expected,
+                ..EvaluationRequest::default()
+            })
+            .collect();
+        stream::iter(&requests)
+            .map(|req| self.evaluate(req, nice))
+            .buffered(MAX_CONCURRENT_EVALUATIONS)
+            .map_ok(|response| response.result)
+            .try_collect()
+            .await
+    }
+
+    /// Zero-shot classification of `prompt`: every label is evaluated as completion of the
+    /// prompt (with a leading space unless it starts with whitespace) and the labels are ranked
+    /// by their log perplexity per character, so longer labels are not penalized.
+    pub async fn classify(
+        &self,
+        model: &str,
+        prompt: &str,
+        labels: &[&str],
+        nice: Option<bool>,
+    ) -> Result<Vec<LabelScore>, ApiError> {
+        let results = self
+            .evaluate_completions(
+                model,
+                &Prompt::from_text(prompt),
+                labels.iter().map(|l| as_completion(l)),
+                nice,
+            )
+            .await?;
+        let log_perplexities = results
+            .iter()
+            .map(|result| {
+                result.log_perplexity_per_character.ok_or_else(|| {
+                    ApiError::UnexpectedResponse(
+                        "Evaluation lacks log_perplexity_per_character.".into(),
+                    )
+                })
+            })
+            .collect::<Result<Vec<f64>, _>>()?;
+        Ok(label_distribution(labels, &log_perplexities))
+    }
+
+    /// Answers a multiple-choice question by evaluating every choice as completion of
+    /// [`multiple_choice_prompt`]. With `length_normalized` the choices are compared by their
+    /// log perplexity per character instead of their log probability, which otherwise favors
+    /// short choices.
+    pub async fn rank_choices(
+        &self,
+        model: &str,
+        context: &str,
+        question: &str,
+        choices: &[&str],
+        length_normalized: bool,
+        nice: Option<bool>,
+    ) -> Result<ChoiceRanking, ApiError> {
+        let prompt = multiple_choice_prompt(context, question);
+        let results = self
+            .evaluate_completions(
+                model,
+                &Prompt::from_text(prompt),
+                choices.iter().map(|c| as_completion(c)),
+                nice,
+            )
+            .await?;
+        ChoiceRanking::from_results(&results, length_normalized).ok_or_else(|| {
+            ApiError::UnexpectedResponse("Evaluation lacks the metric to rank choices.".into())
+        })
+    }
+
+    /// Re-scores the completions of `response`, e.g. sampled with `n > 1`, against the prompt of
+    /// `req` and returns them ordered by their log probability per character, best first.
+    pub async fn rerank_completions(
+        &self,
+        req: &CompletionRequest,
+        response: &CompletionResponse,
+        nice: Option<bool>,
+    ) -> Result<Vec<ScoredCompletion>, ApiError> {
+        let completions = response.texts().map(str::to_owned);
+        let results = self
+            .evaluate_completions(&req.model, &req.prompt, completions, nice)
+            .await?;
+        let mut scored = response
+            .texts()
+            .zip(&results)
+            .map(|(completion, result)| {
+                let perplexity = result.log_perplexity_per_character.ok_or_else(|| {
+                    ApiError::UnexpectedResponse(
+                        "Evaluation lacks log_perplexity_per_character.".into(),
+                    )
+                })?;
+                Ok(ScoredCompletion {
+                    completion: completion.to_owned(),
+                    score: -perplexity,
+                })
+            })
+            .collect::<Result<Vec<_>, ApiError>>()?;
+        scored.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+        Ok(scored)
+    }
+
+    /// Perplexity of `model` on a stream of documents. Each document is split with
+    /// [`perplexity_windows`], the windows are evaluated with at most `options.max_concurrency`
+    /// requests in flight and aggregated into a corpus level perplexity per character.
+    pub async fn corpus_perplexity(
+        &self,
+        model: &str,
+        documents: impl Stream<Item = String>,
+        options: &PerplexityOptions,
+    ) -> Result<CorpusPerplexity, ApiError> {
+        let tokenizer = self.get_tokenizer(model).await?;
+        let windows: Vec<(f64, usize)> = documents
+            .map(|document| {
+                perplexity_windows(
+                    &tokenizer,
+                    &document,
+                    options.window_size,
+                    options.context_size,
+                )
+            })
+            .map_ok(|windows| stream::iter(windows).map(Ok))
+            .try_flatten()
+            .map_ok(|(prompt, completion)| async move {
+                let req = EvaluationRequest::from_text(model, prompt, completion);
+                let response = options
+                    .retry
+                    .run(|| self.evaluate(&req, options.nice))
+                    .await?;
+                let log_probability = response.result.log_probability.ok_or_else(|| {
+                    ApiError::UnexpectedResponse("Evaluation lacks log_probability.".into())
+                })?;
+                let characters = match response.result.character_count {
+                    Some(count) => count as usize,
+                    None => req.completion_expected.chars().count(),
+                };
+                Ok::<_, ApiError>((-log_probability, characters))
+            })
+            .try_buffered(options.max_concurrency.max(1))
+            .try_collect()
+            .await?;
+        CorpusPerplexity::from_windows(&windows).ok_or_else(|| ValidationError::EmptyPrompt.into())
+    }
+
+    /// Runs the evaluation set `items` against each of `models` (one after another, see
+    /// [`Client::run_eval`]) and compares the results.
+    pub async fn compare_models(
+        &self,
+        models: &[&str],
+        items: &[EvalItem],
+        max_concurrency: usize,
+        policy: &RetryPolicy,
+        nice: Option<bool>,
+    ) -> ModelComparison {
+        let mut reports = Vec::with_capacity(models.len());
+        for model in models {
+            reports.push(
+                self.run_eval(model, items, max_concurrency, policy, nice)
+                    .await,
+            );
+        }
+        ModelComparison::from_reports(&reports)
+    }
+
+    /// Evaluates `model` on all `items` with at most `max_concurrency` requests in flight.
+    /// Requests failing with a transient error are retried according to `policy`, items failing
+    /// permanently are reported with their error instead of aborting the run.
+    pub async fn run_eval(
+        &self,
+        model: &str,
+        items: &[EvalItem],
+        max_concurrency: usize,
+        policy: &RetryPolicy,
+        nice: Option<bool>,
+    ) -> EvalReport {
+        let requests: Vec<EvaluationRequest> = items
+            .iter()
+            .map(|item| {
+                EvaluationRequest::from_text(model, &item.prompt, &item.expected_completion)
+            })
+            .collect();
+        let outcome = run_batch(&requests, max_concurrency, policy, |req| {
+            self.evaluate(req, nice)
+        })
+        .await;
+
+        let mut results: Vec<EvalItemResult> = outcome
+            .succeeded
+            .iter()
+            .map(|(index, response)| EvalItemResult::from_result(*index, &response.result))
+            .chain(outcome.failed.iter().map(|(index, error)| EvalItemResult {
+                index: *index,
+                log_perplexity_per_character: None,
+                correct_greedy: None,
+                error: Some(error.to_string()),
+            }))
+            .collect();
+        results.sort_by_key(|result| result.index);
+        EvalReport::new(model, results)
+    }
+}
@@ -1,5 +1,5 @@
-use super::completion::{Hosting, Prompt};
-use crate::impl_builder_methods;
+use super::completion::{ControlParameters, Hosting, Prompt};
+use crate::{impl_builder_methods, impl_control_parameters};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Debug, Default)]
@@ -24,19 +24,9 @@ pub struct EvaluationRequest {
     /// empty string (default). The prompt may contain a zero shot or few shot task.
     pub completion_expected: String,
 
-    /// If set to `None`, attention control parameters only apply to those tokens that have explicitly been set
-    /// in the request. If set to a non-null value, we apply the control parameters to similar tokens as
-    /// well. Controls that have been applied to one token will then be applied to all other tokens that have
-    /// at least the similarity score defined by this parameter. The similarity score is the cosine
-    /// similarity of token embeddings.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub contextual_control_threshold: Option<f64>,
-
-    /// Default value: true
-    /// true: apply controls on prompt items by adding the `log(control_factor)`` to attention scores.
-    /// false: apply controls on prompt items by `(attention_scores - -attention_scores.min(-1)) * control_factor`
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub control_log_additive: Option<bool>,
+    /// Settings for how attention controls of the prompt are applied.
+    #[serde(flatten)]
+    pub control_parameters: ControlParameters,
 }
 
 impl EvaluationRequest {
@@ -56,11 +46,11 @@ impl EvaluationRequest {
 
 impl_builder_methods!(
     EvaluationRequest,
-    hosting: Hosting,
-    contextual_control_threshold: f64,
-    control_log_additive: bool
+    hosting: Hosting
 );
 
+impl_control_parameters!(EvaluationRequest);
+
 #[derive(Deserialize, Debug)]
 pub struct EvaluationResponse {
     /// model name and version (if any) of the used model for inference
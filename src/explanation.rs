@@ -1,6 +1,9 @@
-use super::completion::{BoundingBox, Hosting, Prompt};
-use crate::impl_builder_methods;
+use super::completion::{
+    BoundingBox, ControlParameters, ControlTokenOverlap, Hosting, Modality, Prompt,
+};
+use crate::{impl_builder_methods, impl_control_parameters};
 use serde::{Deserialize, Serialize};
+use std::ops::Range;
 
 #[derive(Serialize, Debug, Default)]
 #[serde(rename_all = "snake_case")]
@@ -25,7 +28,7 @@ pub enum PromptGranularityType {
     Custom,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Default)]
 pub struct PromptGranularity {
     /// At which granularity should the target be explained in terms of the prompt.
     /// If you choose, for example, "sentence" then we report the importance score of each
@@ -49,14 +52,39 @@ pub struct PromptGranularity {
     granularity_type: PromptGranularityType,
 
     /// A delimiter string to split the prompt on if "custom" granularity is chosen.
-    delimiter: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delimiter: Option<String>,
 }
 
-impl Default for PromptGranularity {
-    fn default() -> Self {
+impl PromptGranularity {
+    fn new(granularity_type: PromptGranularityType) -> Self {
         Self {
-            granularity_type: PromptGranularityType::default(),
-            delimiter: String::new(),
+            granularity_type,
+            delimiter: None,
+        }
+    }
+
+    pub fn token() -> Self {
+        Self::new(PromptGranularityType::Token)
+    }
+
+    pub fn word() -> Self {
+        Self::new(PromptGranularityType::Word)
+    }
+
+    pub fn sentence() -> Self {
+        Self::new(PromptGranularityType::Sentence)
+    }
+
+    pub fn paragraph() -> Self {
+        Self::new(PromptGranularityType::Paragraph)
+    }
+
+    /// Splits the prompt on `delimiter`.
+    pub fn custom(delimiter: impl Into<String>) -> Self {
+        Self {
+            granularity_type: PromptGranularityType::Custom,
+            delimiter: Some(delimiter.into()),
         }
     }
 }
@@ -72,14 +100,6 @@ pub enum TargetGranularity {
     Token,
 }
 
-#[derive(Serialize, Debug, Default)]
-#[serde(rename_all = "snake_case")]
-pub enum ControlTokenOverlap {
-    #[default]
-    Partial,
-    Complete,
-}
-
 #[derive(Serialize, Debug, Default)]
 pub struct ExplanationRequest {
     /// Name of the model to use.
@@ -110,17 +130,9 @@ pub struct ExplanationRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub control_factor: Option<f64>,
 
-    /// If set to `null`, attention control parameters only apply to those tokens that have explicitly been set in the request.
-    /// If set to a non-null value, we apply the control parameters to similar tokens as well.
-    /// Controls that have been applied to one token will then be applied to all other tokens that have at least the similarity score defined by this parameter.
-    /// The similarity score is the cosine similarity of token embeddings.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub contextual_control_threshold: Option<f64>,
-
-    /// `true`: apply controls on prompt items by adding the `log(control_factor)` to attention scores.
-    /// `false`: apply controls on prompt items by `(attention_scores - -attention_scores.min(-1)) * control_factor`
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub control_log_additive: Option<bool>,
+    /// Settings for how attention controls of the prompt are applied.
+    #[serde(flatten)]
+    pub control_parameters: ControlParameters,
 
     /// Optionally apply postprocessing to the difference in cross entropy scores for each token.
     /// "none": Apply no postprocessing.
@@ -151,6 +163,7 @@ pub struct ExplanationRequest {
     /// Word -> 6x6
     /// Sentence -> 3x3
     /// Paragraph -> 1
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub prompt_granularity: Option<PromptGranularity>,
 
     /// How many explanations should be returned in the output.
@@ -173,13 +186,23 @@ pub struct ExplanationRequest {
     pub control_token_overlap: Option<ControlTokenOverlap>,
 }
 
+impl ExplanationRequest {
+    /// Explains how `prompt` contributes to `target` being generated as its completion.
+    pub fn new(model: impl Into<String>, prompt: Prompt, target: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            prompt,
+            target: Some(target.into()),
+            ..Self::default()
+        }
+    }
+}
+
 impl_builder_methods!(
     ExplanationRequest,
     hosting: Hosting,
     target: String,
     control_factor: f64,
-    contextual_control_threshold: f64,
-    control_log_additive: bool,
     postprocessing: Postprocessing,
     normalize: bool,
     prompt_granularity: PromptGranularity,
@@ -187,20 +210,22 @@ impl_builder_methods!(
     control_token_overlap: ControlTokenOverlap
 );
 
-#[derive(Deserialize, Debug)]
+impl_control_parameters!(ExplanationRequest);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ScoredSegment {
     pub start: i32,
     pub length: i32,
     pub score: f32,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ScoredRect {
     pub rect: BoundingBox,
     pub score: f32,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum ItemImportance {
     /// Explains the importance of a request prompt item of type "token_ids".
@@ -224,7 +249,7 @@ pub enum ItemImportance {
     Image { scores: Vec<ScoredRect> },
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ExplanationItem {
     /// The string representation of the target token which is being explained
     pub target: String,
@@ -234,10 +259,200 @@ pub struct ExplanationItem {
 }
 
 /// The top-level response data structure that will be returned from an explanation request.
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ExplanationResponse {
     pub model_version: String,
 
     /// This array will contain one explanation object for each token in the target string.
     pub explanations: Vec<ExplanationItem>,
 }
+
+/// A scored part of a text prompt item, see [`ExplanationItem::text_spans`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoredSpan<'a> {
+    /// The explained substring of the prompt item.
+    pub text: &'a str,
+
+    /// Byte range of `text` within the prompt item.
+    pub range: Range<usize>,
+
+    pub score: f32,
+}
+
+/// Byte range of the `length` characters starting at character `start` of `text`, clamped to
+/// the text.
+fn char_span_to_bytes(text: &str, start: i32, length: i32) -> Range<usize> {
+    let byte_offset = |chars: usize| {
+        text.char_indices()
+            .nth(chars)
+            .map_or(text.len(), |(offset, _)| offset)
+    };
+    let start_char = start.max(0) as usize;
+    let end_char = start_char + length.max(0) as usize;
+    byte_offset(start_char)..byte_offset(end_char)
+}
+
+impl ExplanationItem {
+    /// Scored substrings of each item of `prompt`, the prompt the explanation was requested for.
+    /// Returns one entry per prompt item, `None` for items which are not text.
+    pub fn text_spans<'a>(&self, prompt: &'a Prompt) -> Vec<Option<Vec<ScoredSpan<'a>>>> {
+        prompt
+            .items()
+            .iter()
+            .zip(&self.items)
+            .map(|(item, importance)| match (item, importance) {
+                (Modality::Text { data, .. }, ItemImportance::Text { scores }) => Some(
+                    scores
+                        .iter()
+                        .map(|segment| {
+                            let range = char_span_to_bytes(data, segment.start, segment.length);
+                            ScoredSpan {
+                                text: &data[range.clone()],
+                                range,
+                                score: segment.score,
+                            }
+                        })
+                        .collect(),
+                ),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// How the scores of the token segments within a word or sentence are combined, see
+/// [`ExplanationItem::aggregate`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreAggregation {
+    #[default]
+    Sum,
+    Max,
+    Mean,
+}
+
+impl ScoreAggregation {
+    fn apply(&self, scores: &[f32]) -> f32 {
+        match self {
+            ScoreAggregation::Sum => scores.iter().sum(),
+            ScoreAggregation::Max => scores.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+            ScoreAggregation::Mean => scores.iter().sum::<f32>() / scores.len() as f32,
+        }
+    }
+}
+
+/// Unit text scores are aggregated to, see [`ExplanationItem::aggregate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextUnit {
+    /// Runs of non-whitespace characters.
+    Word,
+
+    /// Text up to and including a run of `.`, `!` or `?`, without surrounding whitespace.
+    Sentence,
+}
+
+impl TextUnit {
+    /// Character ranges of the units of `text`.
+    fn split(&self, text: &str) -> Vec<Range<usize>> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut units = Vec::new();
+        let mut start = None;
+        for (i, &c) in chars.iter().enumerate() {
+            if start.is_none() && !c.is_whitespace() {
+                start = Some(i);
+            }
+            let Some(unit_start) = start else {
+                continue;
+            };
+            let next = chars.get(i + 1);
+            let ends = match self {
+                // Not `Option::is_none_or`, which needs Rust 1.82.
+                TextUnit::Word => match next {
+                    Some(n) => n.is_whitespace(),
+                    None => true,
+                },
+                TextUnit::Sentence => {
+                    matches!(c, '.' | '!' | '?') && !matches!(next, Some('.' | '!' | '?'))
+                }
+            };
+            if ends {
+                units.push(unit_start..i + 1);
+                start = None;
+            }
+        }
+        if let Some(unit_start) = start {
+            // A trailing sentence without terminator, excluding trailing whitespace.
+            let end = chars.iter().rposition(|c| !c.is_whitespace()).unwrap() + 1;
+            units.push(unit_start..end);
+        }
+        units
+    }
+}
+
+/// Combines the scored (token) segments of `text` into one segment per `unit`. Each segment is
+/// assigned to the unit it overlaps most, segments covering only whitespace are dropped, as are
+/// units without any segment.
+pub fn aggregate_segments(
+    text: &str,
+    segments: &[ScoredSegment],
+    unit: TextUnit,
+    aggregation: ScoreAggregation,
+) -> Vec<ScoredSegment> {
+    let units = unit.split(text);
+    let mut scores = vec![Vec::new(); units.len()];
+    for segment in segments {
+        let start = segment.start.max(0) as usize;
+        let end = start + segment.length.max(0) as usize;
+        let overlap = |unit: &Range<usize>| end.min(unit.end).saturating_sub(start.max(unit.start));
+        if let Some((index, _)) = units
+            .iter()
+            .enumerate()
+            .map(|(index, unit)| (index, overlap(unit)))
+            .filter(|(_, overlap)| *overlap > 0)
+            .max_by_key(|(_, overlap)| *overlap)
+        {
+            scores[index].push(segment.score);
+        }
+    }
+    units
+        .into_iter()
+        .zip(scores)
+        .filter(|(_, scores)| !scores.is_empty())
+        .map(|(unit, scores)| ScoredSegment {
+            start: unit.start as i32,
+            length: unit.len() as i32,
+            score: aggregation.apply(&scores),
+        })
+        .collect()
+}
+
+impl ExplanationItem {
+    /// Coarsens a token level explanation (e.g. with [`PromptGranularity::token`]) of `prompt`
+    /// to word or sentence level, without requesting the explanation again. Only text prompt
+    /// items are aggregated, all others (including the target) are kept as they are.
+    pub fn aggregate(
+        &self,
+        prompt: &Prompt,
+        unit: TextUnit,
+        aggregation: ScoreAggregation,
+    ) -> ExplanationItem {
+        let items = self
+            .items
+            .iter()
+            .enumerate()
+            .map(
+                |(index, importance)| match (prompt.items().get(index), importance) {
+                    (Some(Modality::Text { data, .. }), ItemImportance::Text { scores }) => {
+                        ItemImportance::Text {
+                            scores: aggregate_segments(data, scores, unit, aggregation),
+                        }
+                    }
+                    _ => importance.clone(),
+                },
+            )
+            .collect();
+        ExplanationItem {
+            target: self.target.clone(),
+            items,
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Export of embeddings to files for other tools. Writing Parquet requires the `parquet` feature,
+//! writing `.npz` archives the `npz` feature and inserting into PostgreSQL with
+//! [pgvector](https://github.com/pgvector/pgvector) the `pgvector` feature.
+#[cfg(feature = "parquet")]
+pub use self::parquet_export::*;
+use super::embedding::{BatchSemanticEmbeddingResponse, SemanticEmbeddingResponse};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+
+/// One embedded document of an export.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmbeddingRecord<'a> {
+    pub id: &'a str,
+    pub text: &'a str,
+    pub embedding: &'a [f32],
+}
+
+/// Writes `data` as little endian `f32` array of the given `shape` in numpy's `.npy` format.
+fn write_npy_array(mut writer: impl Write, shape: &[usize], data: &[f32]) -> io::Result<()> {
+    let shape = match shape {
+        [length] => format!("({length},)"),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': {shape}, }}");
+    // Magic, version and the length field take 10 bytes, the header ends with a line break and
+    // the data has to start at a multiple of 64 bytes.
+    let unpadded = NPY_MAGIC.len() + 2 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    writer.write_all(NPY_MAGIC)?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for value in data {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+/// Writes `embeddings` as `.npy` matrix with one row per embedding. Fails if the embeddings
+/// differ in length.
+pub fn write_npy<E: AsRef<[f32]>>(writer: impl Write, embeddings: &[E]) -> io::Result<()> {
+    let dimension = embeddings.first().map_or(0, |e| e.as_ref().len());
+    if embeddings.iter().any(|e| e.as_ref().len() != dimension) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Embeddings differ in length.",
+        ));
+    }
+    let data: Vec<f32> = embeddings
+        .iter()
+        .flat_map(|e| e.as_ref().iter().copied())
+        .collect();
+    write_npy_array(writer, &[embeddings.len(), dimension], &data)
+}
+
+/// Saves `embeddings` as `.npy` file, see [`write_npy`].
+pub fn save_npy<E: AsRef<[f32]>>(path: impl AsRef<Path>, embeddings: &[E]) -> io::Result<()> {
+    write_npy(BufWriter::new(File::create(path)?), embeddings)
+}
+
+/// Saves several named embedding matrices as `.npz` archive, loadable with `numpy.load`.
+#[cfg(feature = "npz")]
+pub fn save_npz<E: AsRef<[f32]>>(
+    path: impl AsRef<Path>,
+    arrays: &[(&str, &[E])],
+) -> io::Result<()> {
+    let mut archive = zip::ZipWriter::new(File::create(path)?);
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (name, embeddings) in arrays {
+        archive.start_file(format!("{name}.npy"), options)?;
+        write_npy(&mut archive, embeddings)?;
+    }
+    archive.finish()?;
+    Ok(())
+}
+
+impl SemanticEmbeddingResponse {
+    /// Saves the embedding as one dimensional `.npy` array.
+    pub fn save_npy(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        write_npy_array(writer, &[self.embedding.len()], &self.embedding)
+    }
+}
+
+impl BatchSemanticEmbeddingResponse {
+    /// Saves the embeddings as `.npy` matrix with one row per prompt.
+    pub fn save_npy(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        save_npy(path, &self.embeddings)
+    }
+}
+
+/// Formats `embedding` as pgvector literal, e.g. `[0.5,-1]`, to be used as `'[0.5,-1]'::vector`.
+pub fn pgvector_literal(embedding: &[f32]) -> String {
+    let values: Vec<String> = embedding.iter().map(f32::to_string).collect();
+    format!("[{}]", values.join(","))
+}
+
+/// Escapes `text` for the text format of PostgreSQL's `COPY`.
+fn escape_copy(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes `records` as rows of `id`, `text` and `embedding` for
+/// `COPY table (id, text, embedding) FROM STDIN`, with the embedding as pgvector literal.
+pub fn write_pgvector_copy(
+    mut writer: impl Write,
+    records: &[EmbeddingRecord<'_>],
+) -> io::Result<()> {
+    for record in records {
+        writeln!(
+            writer,
+            "{}\t{}\t{}",
+            escape_copy(record.id),
+            escape_copy(record.text),
+            pgvector_literal(record.embedding)
+        )?;
+    }
+    writer.flush()
+}
+
+/// Inserts `records` into the columns `id`, `text` and `embedding` (of type `vector`) of `table`
+/// and returns the number of inserted rows. Requires the `pgvector` feature. `table` is inserted
+/// into the statement as is and must not come from untrusted input.
+#[cfg(feature = "pgvector")]
+pub async fn insert_pgvector(
+    connection: &mut sqlx::PgConnection,
+    table: &str,
+    records: &[EmbeddingRecord<'_>],
+) -> Result<u64, sqlx::Error> {
+    // Postgres allows at most 65535 bind parameters per statement, each record binds three.
+    const RECORDS_PER_STATEMENT: usize = 10_000;
+    let mut inserted = 0;
+    for chunk in records.chunks(RECORDS_PER_STATEMENT) {
+        let mut query =
+            sqlx::QueryBuilder::new(format!("INSERT INTO {table} (id, text, embedding) "));
+        query.push_values(chunk, |mut row, record| {
+            row.push_bind(record.id)
+                .push_bind(record.text)
+                .push_bind(pgvector_literal(record.embedding))
+                .push_unseparated("::vector");
+        });
+        inserted += query
+            .build()
+            .execute(&mut *connection)
+            .await?
+            .rows_affected();
+    }
+    Ok(inserted)
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_export {
+    use super::EmbeddingRecord;
+    use arrow_array::{ArrayRef, FixedSizeListArray, Float32Array, RecordBatch, StringArray};
+    use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+    use parquet::{arrow::ArrowWriter, errors::ParquetError};
+    use std::{io::Write, sync::Arc};
+    use thiserror::Error as ThisError;
+
+    #[derive(ThisError, Debug)]
+    pub enum ParquetExportError {
+        #[error("Embedding of '{id}' has {actual} dimensions, expected {expected}.")]
+        DimensionMismatch {
+            id: String,
+            expected: usize,
+            actual: usize,
+        },
+        #[error(transparent)]
+        Arrow(#[from] ArrowError),
+        #[error(transparent)]
+        Parquet(#[from] ParquetError),
+    }
+
+    /// Writes [`EmbeddingRecord`]s as Parquet with the columns `id` and `text` (strings) and
+    /// `embedding` (fixed size list of floats). Each call of
+    /// [`ParquetEmbeddingWriter::write_batch`] produces one record batch.
+    pub struct ParquetEmbeddingWriter<W: Write + Send> {
+        writer: ArrowWriter<W>,
+        schema: SchemaRef,
+        dimension: usize,
+    }
+
+    impl<W: Write + Send> ParquetEmbeddingWriter<W> {
+        /// New writer for embeddings with `dimension` values each.
+        pub fn new(sink: W, dimension: usize) -> Result<Self, ParquetExportError> {
+            let schema = Arc::new(Schema::new(vec![
+                Field::new("id", DataType::Utf8, false),
+                Field::new("text", DataType::Utf8, false),
+                Field::new(
+                    "embedding",
+                    DataType::FixedSizeList(
+                        Arc::new(Field::new("item", DataType::Float32, false)),
+                        dimension as i32,
+                    ),
+                    false,
+                ),
+            ]));
+            let writer = ArrowWriter::try_new(sink, schema.clone(), None)?;
+            Ok(Self {
+                writer,
+                schema,
+                dimension,
+            })
+        }
+
+        pub fn write_batch(
+            &mut self,
+            records: &[EmbeddingRecord<'_>],
+        ) -> Result<(), ParquetExportError> {
+            let mut values = Vec::with_capacity(records.len() * self.dimension);
+            for record in records {
+                if record.embedding.len() != self.dimension {
+                    return Err(ParquetExportError::DimensionMismatch {
+                        id: record.id.to_owned(),
+                        expected: self.dimension,
+                        actual: record.embedding.len(),
+                    });
+                }
+                values.extend_from_slice(record.embedding);
+            }
+            let ids: StringArray = records.iter().map(|record| Some(record.id)).collect();
+            let texts: StringArray = records.iter().map(|record| Some(record.text)).collect();
+            let embeddings = FixedSizeListArray::try_new(
+                Arc::new(Field::new("item", DataType::Float32, false)),
+                self.dimension as i32,
+                Arc::new(Float32Array::from(values)),
+                None,
+            )?;
+            let columns: Vec<ArrayRef> = vec![Arc::new(ids), Arc::new(texts), Arc::new(embeddings)];
+            let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+            self.writer.write(&batch)?;
+            Ok(())
+        }
+
+        /// Writes the footer, the file is invalid without it.
+        pub fn close(self) -> Result<(), ParquetExportError> {
+            self.writer.close()?;
+            Ok(())
+        }
+    }
+}
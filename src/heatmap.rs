@@ -0,0 +1,116 @@
+//! Rendering of explanation scores as heatmaps over the prompt text, for terminals (ANSI colors)
+//! and web pages (HTML). Positive scores are shaded red, negative ones blue, with an intensity
+//! relative to the largest absolute score.
+//!
+//! ```no_run
+//!use aleph_alpha_api::{heatmap, Client, ExplanationRequest, Modality, Prompt, LUMINOUS_BASE};
+//!
+//!async fn show(client: &Client) {
+//!    let prompt = Prompt::from_text("I am a programmer and French. My favorite food is");
+//!    let req = ExplanationRequest::new(LUMINOUS_BASE, prompt.clone(), " pizza");
+//!    let response = client.explain(&req, None).await.unwrap();
+//!    let spans = response.explanations[0].text_spans(&prompt);
+//!    if let (Modality::Text { data, .. }, Some(spans)) = (&prompt.items()[0], &spans[0]) {
+//!        println!("{}", heatmap::render_ansi(data, spans));
+//!    }
+//!}
+//! ```
+use super::explanation::ScoredSpan;
+use std::fmt::Write;
+
+/// Background color of `score` relative to `max`, as RGB.
+fn color(score: f32, max: f32) -> (u8, u8, u8) {
+    let intensity = if max > 0.0 {
+        (score.abs() / max).min(1.0)
+    } else {
+        0.0
+    };
+    let fade = (255.0 * (1.0 - intensity)) as u8;
+    if score >= 0.0 {
+        (255, fade, fade)
+    } else {
+        (fade, fade, 255)
+    }
+}
+
+/// Splits `text` into the parts between and within `spans`, the latter with their score.
+fn segments<'a>(text: &'a str, spans: &[ScoredSpan<'_>]) -> Vec<(&'a str, Option<f32>)> {
+    let mut spans: Vec<&ScoredSpan> = spans.iter().collect();
+    spans.sort_by_key(|span| span.range.start);
+    let mut segments = Vec::new();
+    let mut position = 0;
+    for span in spans {
+        let start = span.range.start.clamp(position, text.len());
+        let end = span.range.end.clamp(start, text.len());
+        if position < start {
+            segments.push((&text[position..start], None));
+        }
+        if start < end {
+            segments.push((&text[start..end], Some(span.score)));
+        }
+        position = end;
+    }
+    if position < text.len() {
+        segments.push((&text[position..], None));
+    }
+    segments
+}
+
+fn max_score(spans: &[ScoredSpan<'_>]) -> f32 {
+    spans
+        .iter()
+        .fold(0.0, |max, span| max.max(span.score.abs()))
+}
+
+/// `text` with the `spans` highlighted by 24-bit ANSI background colors.
+pub fn render_ansi(text: &str, spans: &[ScoredSpan<'_>]) -> String {
+    let max = max_score(spans);
+    let mut rendered = String::with_capacity(text.len());
+    for (part, score) in segments(text, spans) {
+        match score {
+            Some(score) => {
+                let (r, g, b) = color(score, max);
+                write!(rendered, "\x1b[48;2;{r};{g};{b}m\x1b[30m{part}\x1b[0m").unwrap();
+            }
+            None => rendered.push_str(part),
+        }
+    }
+    rendered
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `text` as HTML, with every span wrapped into a `<span>` with a background color and the score
+/// as tooltip.
+pub fn render_html(text: &str, spans: &[ScoredSpan<'_>]) -> String {
+    let max = max_score(spans);
+    let mut rendered = String::with_capacity(text.len());
+    for (part, score) in segments(text, spans) {
+        match score {
+            Some(score) => {
+                let (r, g, b) = color(score, max);
+                write!(
+                    rendered,
+                    "<span style=\"background-color: rgb({r}, {g}, {b})\" title=\"{score}\">{}</span>",
+                    escape_html(part)
+                )
+                .unwrap();
+            }
+            None => rendered.push_str(&escape_html(part)),
+        }
+    }
+    rendered
+}
@@ -1,6 +1,7 @@
 use super::error::ApiError;
 use reqwest::header::{HeaderMap, HeaderValue};
 use reqwest::{header, Client, ClientBuilder, Error, StatusCode};
+use serde::{Deserialize, Serialize};
 
 pub fn create_client(api_token: &str) -> Result<Client, Error> {
     let mut headers = HeaderMap::new();
@@ -10,7 +11,7 @@ pub fn create_client(api_token: &str) -> Result<Client, Error> {
     auth_value.set_sensitive(true);
     headers.insert(header::AUTHORIZATION, auth_value);
 
-    Ok(ClientBuilder::new().default_headers(headers).build()?)
+    ClientBuilder::new().default_headers(headers).build()
 }
 
 pub async fn translate_http_error(
@@ -44,12 +45,10 @@ pub async fn get(
 ) -> Result<reqwest::Response, ApiError> {
     let url = format!("{base_url}{path}");
     let mut request = client.get(url);
-    println!("{:?}", request);
     if let Some(q) = query {
         request = request.query(&q);
     }
     let response = request.send().await?;
-    println!("response: {:?}", response);
     translate_http_error(response).await
 }
 
@@ -62,3 +61,48 @@ pub async fn delete(
     let response = client.delete(url).send().await?;
     translate_http_error(response).await
 }
+
+/// `ETag` and `Last-Modified` headers of a response, used to validate a cached copy of it.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct CacheValidators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl CacheValidators {
+    pub fn from_response(response: &reqwest::Response) -> Self {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned)
+        };
+        Self {
+            etag: header(header::ETAG),
+            last_modified: header(header::LAST_MODIFIED),
+        }
+    }
+}
+
+/// Like [`get`], but sends `validators` as `If-None-Match` and `If-Modified-Since` headers.
+/// Returns `None` if the server answers with `304 Not Modified`.
+pub async fn get_if_modified(
+    client: &reqwest::Client,
+    base_url: &str,
+    path: &str,
+    validators: &CacheValidators,
+) -> Result<Option<reqwest::Response>, ApiError> {
+    let mut request = client.get(format!("{base_url}{path}"));
+    if let Some(etag) = &validators.etag {
+        request = request.header(header::IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &validators.last_modified {
+        request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+    }
+    let response = request.send().await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    translate_http_error(response).await.map(Some)
+}
@@ -1,43 +1,364 @@
 // code copied from official AlephAlpha rust client: https://github.com/Aleph-Alpha/aleph-alpha-client-rs/blob/main/src/image_preprocessing.rs
+use super::completion::Crop;
+use super::explanation::ScoredRect;
 use image::{
-    imageops::FilterType::CatmullRom, DynamicImage, GenericImageView, ImageError, ImageFormat,
+    codecs::jpeg::JpegEncoder,
+    imageops::{self, FilterType::CatmullRom},
+    io::{Limits, Reader},
+    DynamicImage, GenericImageView, ImageError, ImageFormat, Rgba, RgbaImage,
 };
 use std::{
-    cmp::min,
-    fs::File,
-    io::{self, BufReader, Cursor},
+    borrow::Cow,
+    cmp::{max, min},
+    fs,
+    io::{self, Cursor},
     path::Path,
 };
 use thiserror::Error as ThisError;
 
 /// Image is shrank on the server side, before it is send to the model. We might as well save the
 /// bandwidth and do it right away on the client side.
-const DESIRED_IMAGE_SIZE: u32 = 384;
+pub const DESIRED_IMAGE_SIZE: u32 = 384;
 
-pub fn from_image_path(path: &Path) -> Result<Vec<u8>, LoadImageError> {
-    let file = BufReader::new(File::open(path).map_err(LoadImageError::Io)?);
+/// Default limit for the size of encoded images taken from untrusted sources, e.g. downloads.
+pub const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+/// Default limit for the width and height of decoded images taken from untrusted sources.
+pub const MAX_DECODED_EDGE: u32 = 8192;
+
+/// How a non-square image is turned into the square the model sees.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeStrategy {
+    /// Cut out the largest square in the center, dropping the borders of the longer side.
+    #[default]
+    CenterCrop,
+
+    /// Keep the whole image and pad the shorter side with `fill` (RGB) on both ends, e.g. for
+    /// wide documents and screenshots.
+    Letterbox { fill: [u8; 3] },
+
+    /// Keep the whole image and distort it to a square.
+    Stretch,
+}
+
+/// Encoding of preprocessed images for upload.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ImageEncoding {
+    /// Lossless, best for screenshots, documents and diagrams.
+    #[default]
+    Png,
+
+    /// Lossy with `quality` from 1 to 100, usually much smaller than PNG for photos. A quality
+    /// of 85 is a good trade-off.
+    Jpeg { quality: u8 },
+}
+
+impl ImageEncoding {
+    fn format(&self) -> ImageFormat {
+        match self {
+            ImageEncoding::Png => ImageFormat::Png,
+            ImageEncoding::Jpeg { .. } => ImageFormat::Jpeg,
+        }
+    }
+}
+
+/// Settings of the client side image preprocessing, see [`preprocess_image_with_options`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageOptions {
+    pub strategy: ResizeStrategy,
+
+    /// Edge length in pixels larger squares are scaled down to before upload. Smaller images are
+    /// sent as they are. Defaults to [`DESIRED_IMAGE_SIZE`], the resolution the model sees.
+    pub max_edge: u32,
+
+    /// Maximum size of the encoded input image in bytes, enforced for images from untrusted
+    /// sources like [`Modality::from_image_url`](crate::Modality::from_image_url). Defaults to
+    /// [`MAX_IMAGE_BYTES`].
+    pub max_bytes: usize,
+
+    /// Maximum width and height of the decoded input image in pixels, enforced for images from
+    /// untrusted sources. Small encoded images may still decode to huge ones. Defaults to
+    /// [`MAX_DECODED_EDGE`].
+    pub max_decoded_edge: u32,
+
+    pub encoding: ImageEncoding,
+}
+
+impl Default for ImageOptions {
+    fn default() -> Self {
+        Self {
+            strategy: ResizeStrategy::default(),
+            max_edge: DESIRED_IMAGE_SIZE,
+            max_bytes: MAX_IMAGE_BYTES,
+            max_decoded_edge: MAX_DECODED_EDGE,
+            encoding: ImageEncoding::default(),
+        }
+    }
+}
+
+impl ImageOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_edge(mut self, max_edge: u32) -> Self {
+        self.max_edge = max_edge;
+        self
+    }
+
+    pub fn encoding(mut self, encoding: ImageEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn max_decoded_edge(mut self, max_decoded_edge: u32) -> Self {
+        self.max_decoded_edge = max_decoded_edge;
+        self
+    }
+
+    pub fn strategy(mut self, strategy: ResizeStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+}
+
+/// Loads the image at `path`, rotated and flipped as given by its EXIF orientation.
+pub fn load_image(path: &Path) -> Result<DynamicImage, LoadImageError> {
     let format = ImageFormat::from_path(path).map_err(LoadImageError::UnknownImageFormat)?;
-    let image = image::load(file, format).map_err(LoadImageError::InvalidImageEncoding)?;
+    let bytes = fs::read(path).map_err(LoadImageError::Io)?;
+    decode_with_format(&bytes, format, Limits::default())
+}
 
-    let bytes = preprocess_image(&image);
-    Ok(bytes)
+/// Decodes an encoded image, guessing its format from its content, and rotates it according to
+/// its EXIF orientation. Animated images are reduced to their first frame.
+pub fn decode_image(bytes: &[u8]) -> Result<DynamicImage, LoadImageError> {
+    let format = image::guess_format(bytes).map_err(LoadImageError::UnknownImageFormat)?;
+    decode_with_format(bytes, format, Limits::default())
 }
 
-pub fn preprocess_image(org_image: &DynamicImage) -> Vec<u8> {
-    let center_cropped = center_cropped(org_image);
-    let resized = center_cropped.resize_exact(DESIRED_IMAGE_SIZE, DESIRED_IMAGE_SIZE, CatmullRom);
+fn decode_with_format(
+    bytes: &[u8],
+    format: ImageFormat,
+    limits: Limits,
+) -> Result<DynamicImage, LoadImageError> {
+    let mut reader = Reader::with_format(Cursor::new(bytes), format);
+    reader.limits(limits);
+    let image = reader.decode().map_err(|error| match error {
+        ImageError::Unsupported(_) => LoadImageError::UnsupportedFormat(format),
+        ImageError::Limits(_) => LoadImageError::ExceedsLimits(error),
+        error => LoadImageError::InvalidImageEncoding(error),
+    })?;
+    Ok(match exif_orientation(bytes) {
+        Some(orientation) => apply_orientation(image, orientation),
+        None => image,
+    })
+}
+
+/// Value of the EXIF orientation tag of an encoded image (1 to 8), if present.
+pub fn exif_orientation(bytes: &[u8]) -> Option<u32> {
+    let exif = exif::Reader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok()?;
+    exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?
+        .value
+        .get_uint(0)
+}
+
+/// Rotates and flips `image` so it is displayed upright given its EXIF `orientation`. Unknown
+/// orientations leave the image unchanged.
+pub fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+pub fn from_image_path(path: &Path) -> Result<Vec<u8>, LoadImageError> {
+    let image = load_image(path)?;
+
+    preprocess_image(&image)
+}
+
+pub fn preprocess_image(org_image: &DynamicImage) -> Result<Vec<u8>, LoadImageError> {
+    preprocess_image_with_options(org_image, &ImageOptions::default())
+}
+
+/// Squares `org_image` according to `options`, scales it down and encodes it for upload.
+pub fn preprocess_image_with_options(
+    org_image: &DynamicImage,
+    options: &ImageOptions,
+) -> Result<Vec<u8>, LoadImageError> {
+    let square = match options.strategy {
+        ResizeStrategy::CenterCrop => center_cropped(org_image),
+        ResizeStrategy::Letterbox { fill } => letterboxed(org_image, fill),
+        ResizeStrategy::Stretch => org_image.clone(),
+    };
+    encode_resized(&square, options)
+}
+
+/// Validates an encoded image and preprocesses it according to `options`. Square images already
+/// in the format of `options.encoding`, without EXIF rotation and not exceeding
+/// `options.max_edge` are returned unchanged.
+pub fn preprocess_image_bytes<'a>(
+    bytes: &'a [u8],
+    options: &ImageOptions,
+) -> Result<Cow<'a, [u8]>, LoadImageError> {
+    if bytes.len() > options.max_bytes {
+        return Err(LoadImageError::TooLarge {
+            size: bytes.len(),
+            limit: options.max_bytes,
+        });
+    }
+    let format = image::guess_format(bytes).map_err(LoadImageError::UnknownImageFormat)?;
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(options.max_decoded_edge);
+    limits.max_image_height = Some(options.max_decoded_edge);
+    let image = decode_with_format(bytes, format, limits)?;
+    let (width, height) = image.dimensions();
+    let upright = exif_orientation(bytes).unwrap_or(1) == 1;
+    let square = width == height && width <= options.max_edge;
+    if format == options.encoding.format() && upright && square {
+        Ok(Cow::Borrowed(bytes))
+    } else {
+        Ok(Cow::Owned(preprocess_image_with_options(&image, options)?))
+    }
+}
+
+/// Like [`preprocess_image`], but the square given by `crop` is cut out instead of the center.
+pub fn preprocess_image_with_crop(
+    org_image: &DynamicImage,
+    crop: &Crop,
+) -> Result<Vec<u8>, LoadImageError> {
+    let (width, height) = org_image.dimensions();
+    crop.validate(width, height)?;
+    let cropped = org_image.crop_imm(crop.x, crop.y, crop.size, crop.size);
+    encode_resized(&cropped, &ImageOptions::default())
+}
+
+/// Encodes `square` with an edge length of at most `options.max_edge`. Non-square images are
+/// stretched. Images with 16 bit or float channels, e.g. from EXR or HDR files, are converted to
+/// 8 bit first.
+fn encode_resized(
+    square: &DynamicImage,
+    options: &ImageOptions,
+) -> Result<Vec<u8>, LoadImageError> {
+    let (width, height) = square.dimensions();
+    let edge = max(width, height).min(options.max_edge.max(1));
+    let resized = if (width, height) == (edge, edge) {
+        square.clone()
+    } else {
+        square.resize_exact(edge, edge, CatmullRom)
+    };
     let buf = Vec::new();
     let mut out = Cursor::new(buf);
-    resized.write_to(&mut out, ImageFormat::Png).unwrap();
-    out.into_inner()
+    match options.encoding {
+        ImageEncoding::Png => {
+            let resized = if resized.color().has_alpha() {
+                DynamicImage::ImageRgba8(resized.to_rgba8())
+            } else {
+                DynamicImage::ImageRgb8(resized.to_rgb8())
+            };
+            resized.write_to(&mut out, ImageFormat::Png)
+        }
+        ImageEncoding::Jpeg { quality } => {
+            JpegEncoder::new_with_quality(&mut out, quality).encode_image(&resized.to_rgb8())
+        }
+    }
+    .map_err(LoadImageError::Encoding)?;
+    Ok(out.into_inner())
+}
+
+pub(crate) fn center_cropped(image: &DynamicImage) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    let size = min(width, height);
+    let x = (width - size) / 2;
+    let y = (height - size) / 2;
+    image.crop_imm(x, y, size, size)
+}
+
+fn letterboxed(image: &DynamicImage, [r, g, b]: [u8; 3]) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    let size = max(width, height);
+    let mut square = RgbaImage::from_pixel(size, size, Rgba([r, g, b, 255]));
+    let x = (size - width) / 2;
+    let y = (size - height) / 2;
+    imageops::overlay(&mut square, &image.to_rgba8(), x.into(), y.into());
+    DynamicImage::ImageRgba8(square)
+}
+
+/// Maximum opacity of the heatmap, reached by the rectangle with the largest absolute score.
+const HEATMAP_OPACITY: f32 = 0.6;
+
+/// Overlays the scored rectangles of an image explanation (`ItemImportance::Image`) onto `image`
+/// as a translucent heatmap. Positive scores are shaded red, negative ones blue, with an opacity
+/// relative to the largest absolute score. The rectangles are relative to the image the model
+/// saw, so pass the image as it was sent in the prompt.
+pub fn overlay_heatmap(image: &DynamicImage, scores: &[ScoredRect]) -> DynamicImage {
+    let mut overlay = image.to_rgba8();
+    let (width, height) = overlay.dimensions();
+    let max = scores.iter().fold(0.0f32, |max, s| max.max(s.score.abs()));
+    if max == 0.0 {
+        return DynamicImage::ImageRgba8(overlay);
+    }
+    let to_pixels = |fraction: f64, size: u32| {
+        ((fraction.clamp(0.0, 1.0) * size as f64).round() as u32).min(size)
+    };
+    for scored in scores {
+        let alpha = HEATMAP_OPACITY * (scored.score.abs() / max).min(1.0);
+        let color: [f32; 3] = if scored.score >= 0.0 {
+            [255.0, 0.0, 0.0]
+        } else {
+            [0.0, 0.0, 255.0]
+        };
+        let rect = &scored.rect;
+        let (left, top) = (to_pixels(rect.left, width), to_pixels(rect.top, height));
+        let right = to_pixels(rect.left + rect.width, width);
+        let bottom = to_pixels(rect.top + rect.height, height);
+        for y in top..bottom {
+            for x in left..right {
+                let Rgba([r, g, b, a]) = *overlay.get_pixel(x, y);
+                let blend = |channel: u8, c: f32| {
+                    (channel as f32 * (1.0 - alpha) + c * alpha).round() as u8
+                };
+                overlay.put_pixel(
+                    x,
+                    y,
+                    Rgba([
+                        blend(r, color[0]),
+                        blend(g, color[1]),
+                        blend(b, color[2]),
+                        a,
+                    ]),
+                );
+            }
+        }
+    }
+    DynamicImage::ImageRgba8(overlay)
+}
+
+/// [`overlay_heatmap`] encoded as PNG, e.g. to be written to a file for inspection.
+pub fn overlay_heatmap_png(
+    image: &DynamicImage,
+    scores: &[ScoredRect],
+) -> Result<Vec<u8>, ImageError> {
+    encode_png(&overlay_heatmap(image, scores))
 }
 
-fn center_cropped(image: &DynamicImage) -> DynamicImage {
-    let (height, width) = image.dimensions();
-    let size = min(height, width);
-    let x = (height - size) / 2;
-    let y = (width - size) / 2;
-    image.crop_imm(x, y, width, height)
+pub(crate) fn encode_png(image: &DynamicImage) -> Result<Vec<u8>, ImageError> {
+    let mut out = Cursor::new(Vec::new());
+    image.write_to(&mut out, ImageFormat::Png)?;
+    Ok(out.into_inner())
 }
 
 /// Errors returned by the Aleph Alpha Client
@@ -47,6 +368,28 @@ pub enum LoadImageError {
     InvalidImageEncoding(#[source] ImageError),
     #[error("Failed to guess image format from path")]
     UnknownImageFormat(#[source] ImageError),
+    /// The format is known, but decoding it is not supported, usually because the corresponding
+    /// feature (`avif`, `gif`, `image-formats`, `tiff` or `webp`) is disabled.
+    #[error("Decoding {0:?} images is not supported, enable the corresponding feature.")]
+    UnsupportedFormat(ImageFormat),
+    #[error("The decoded image exceeds the configured limits")]
+    ExceedsLimits(#[source] ImageError),
+    #[error("Error encoding the preprocessed image")]
+    Encoding(#[source] ImageError),
     #[error("Error opening input image file.")]
     Io(#[source] io::Error),
+    #[error("Expected a base64 encoded data URL like data:image/png;base64,...")]
+    InvalidDataUrl,
+    #[error("The image has {size} bytes, exceeding the limit of {limit} bytes.")]
+    TooLarge { size: usize, limit: usize },
+    #[error(
+        "Crop of size {size} at ({x}, {y}) exceeds the image of {width}x{height} pixels or is empty."
+    )]
+    InvalidCrop {
+        x: u32,
+        y: u32,
+        size: u32,
+        width: u32,
+        height: u32,
+    },
 }
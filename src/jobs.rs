@@ -0,0 +1,205 @@
+//! Durable batch jobs for embedding or completing large numbers of inputs. Results are
+//! checkpointed to a [`CheckpointStore`] after every batch, so a job interrupted by a crash or
+//! restart resumes after the last completed batch instead of starting over.
+//!
+//! ```no_run
+//!use aleph_alpha_api::{jobs::*, Client, CompletionRequest};
+//!
+//!async fn complete(client: &Client, requests: &[CompletionRequest]) {
+//!    let mut store = JsonLinesStore::new("completions.jsonl");
+//!    let report = BatchJob::new()
+//!        .batch_size(500)
+//!        .run(requests, &mut store, |req| async move {
+//!            let response = client.completion(req, Some(true)).await?;
+//!            Ok(response.best_text().to_owned())
+//!        })
+//!        .await
+//!        .unwrap();
+//!    println!("Resumed after {} inputs", report.resumed_from);
+//!}
+//! ```
+use super::batch::{run_batch, RetryPolicy};
+use super::error::ApiError;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    future::Future,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+/// Result of a single input. Errors are stored as their message, as [`ApiError`] cannot be
+/// persisted.
+pub type JobResult<T> = Result<T, String>;
+
+/// Persists the results of a [`BatchJob`]. Results are only ever appended in input order, so the
+/// number of stored results is the offset at which the job resumes.
+pub trait CheckpointStore<T> {
+    /// All results stored so far, in input order.
+    fn load(&mut self) -> Result<Vec<JobResult<T>>, ApiError>;
+
+    /// Durably appends the results of the next inputs.
+    fn append(&mut self, results: &[JobResult<T>]) -> Result<(), ApiError>;
+}
+
+/// [`CheckpointStore`] keeping the results in memory, e.g. for tests.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore<T> {
+    pub results: Vec<JobResult<T>>,
+}
+
+impl<T: Clone> CheckpointStore<T> for MemoryStore<T> {
+    fn load(&mut self) -> Result<Vec<JobResult<T>>, ApiError> {
+        Ok(self.results.clone())
+    }
+
+    fn append(&mut self, results: &[JobResult<T>]) -> Result<(), ApiError> {
+        self.results.extend_from_slice(results);
+        Ok(())
+    }
+}
+
+/// [`CheckpointStore`] writing one JSON line per result to a file. A line left incomplete by an
+/// interrupted write is discarded when loading.
+#[derive(Debug, Clone)]
+pub struct JsonLinesStore {
+    path: PathBuf,
+}
+
+impl JsonLinesStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> CheckpointStore<T> for JsonLinesStore {
+    fn load(&mut self) -> Result<Vec<JobResult<T>>, ApiError> {
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error.into()),
+        };
+        let complete = bytes
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |end| end + 1);
+        if complete < bytes.len() {
+            let file = OpenOptions::new().write(true).open(&self.path)?;
+            file.set_len(complete as u64)?;
+        }
+        bytes[..complete]
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| Ok(serde_json::from_slice(line)?))
+            .collect()
+    }
+
+    fn append(&mut self, results: &[JobResult<T>]) -> Result<(), ApiError> {
+        let mut lines = Vec::new();
+        for result in results {
+            serde_json::to_writer(&mut lines, result)?;
+            lines.push(b'\n');
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&lines)?;
+        file.sync_data()?;
+        Ok(())
+    }
+}
+
+/// Outcome of [`BatchJob::run`].
+#[derive(Debug)]
+pub struct JobReport<T> {
+    /// One result per input, in input order, including those of previous runs.
+    pub results: Vec<JobResult<T>>,
+
+    /// Number of inputs completed by previous runs.
+    pub resumed_from: usize,
+}
+
+impl<T> JobReport<T> {
+    /// Number of inputs which failed permanently.
+    pub fn failed(&self) -> usize {
+        self.results.iter().filter(|result| result.is_err()).count()
+    }
+}
+
+/// Runs an operation for many inputs in batches, checkpointing after each batch. See the
+/// [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct BatchJob {
+    batch_size: usize,
+    max_concurrency: usize,
+    retry: RetryPolicy,
+}
+
+impl Default for BatchJob {
+    fn default() -> Self {
+        Self {
+            batch_size: 100,
+            max_concurrency: 4,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+impl BatchJob {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of inputs between two checkpoints. Defaults to 100.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Number of operations in flight at any time. Defaults to 4.
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency;
+        self
+    }
+
+    /// Retries of operations failing with a transient error.
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Runs `operation` for all `inputs` not yet completed according to `store`. The inputs must
+    /// be the same and in the same order as in previous runs with this store. Failing inputs do
+    /// not stop the job; only failing to write a checkpoint does.
+    pub async fn run<'a, I, T, F, Fut>(
+        &self,
+        inputs: &'a [I],
+        store: &mut impl CheckpointStore<T>,
+        operation: F,
+    ) -> Result<JobReport<T>, ApiError>
+    where
+        F: Fn(&'a I) -> Fut,
+        Fut: Future<Output = Result<T, ApiError>>,
+    {
+        let mut results = store.load()?;
+        let resumed_from = results.len().min(inputs.len());
+        for batch in inputs[resumed_from..].chunks(self.batch_size.max(1)) {
+            let outcome = run_batch(batch, self.max_concurrency, &self.retry, &operation).await;
+            let mut merged: Vec<Option<JobResult<T>>> = (0..batch.len()).map(|_| None).collect();
+            for (index, value) in outcome.succeeded {
+                merged[index] = Some(Ok(value));
+            }
+            for (index, error) in outcome.failed {
+                merged[index] = Some(Err(error.to_string()));
+            }
+            let merged: Vec<JobResult<T>> = merged.into_iter().flatten().collect();
+            store.append(&merged)?;
+            results.extend(merged);
+        }
+        Ok(JobReport {
+            results,
+            resumed_from,
+        })
+    }
+}
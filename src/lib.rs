@@ -24,15 +24,53 @@
 //!}
 //! ```
 
+pub mod analysis;
+mod api_tokens;
+#[cfg(feature = "ndarray")]
+mod array;
+mod batch;
+pub mod bench;
+#[cfg(any(feature = "embedding-cache", feature = "tokenizer-cache"))]
+mod cache_file;
+pub mod chunking;
 mod client;
+mod coalesce;
 mod completion;
+mod conversation;
+mod document;
 mod embedding;
+#[cfg(feature = "embedding-cache")]
+pub mod embedding_cache;
 pub mod error;
+pub mod eval;
 mod evaluate;
 mod explanation;
+pub mod export;
+pub mod heatmap;
 pub mod http;
 pub mod image_processing;
+pub mod jobs;
+mod models;
+pub mod offline;
+mod postprocess;
+pub mod pricing;
+mod qa;
+#[cfg(feature = "qdrant")]
+pub mod qdrant;
+pub mod quantization;
+pub mod rag;
+pub mod report;
+mod search;
+pub mod similarity;
+mod summarization;
+mod template;
 mod tokenization;
+#[cfg(feature = "tokenizer-cache")]
+pub mod tokenizer_cache;
+mod usage;
+mod users;
+mod vector_index;
+mod version;
 
 pub const LUMINOUS_BASE: &str = "luminous-base";
 pub const LUMINOUS_BASE_CONTROL: &str = "luminous-base-control";
@@ -40,10 +78,17 @@ pub const LUMINOUS_EXTENDED: &str = "luminous-extended";
 pub const LUMINOUS_EXTENDED_CONTROL: &str = "luminous-extended-control";
 pub const LUMINOUS_SUPREME: &str = "luminous-supreme";
 pub const LUMINOUS_SUPREME_CONTROL: &str = "luminous-supreme-control";
+pub const PHARIA_1_LLM_7B_CONTROL: &str = "pharia-1-llm-7b-control";
+pub const PHARIA_1_LLM_7B_CONTROL_ALIGNED: &str = "pharia-1-llm-7b-control-aligned";
+pub const LLAMA_3_1_8B_INSTRUCT: &str = "llama-3.1-8b-instruct";
+pub const LLAMA_3_1_70B_INSTRUCT: &str = "llama-3.1-70b-instruct";
+pub const LLAMA_3_3_70B_INSTRUCT: &str = "llama-3.3-70b-instruct";
 
 pub use self::{
-    client::Client, client::ALEPH_ALPHA_API_BASE_URL, completion::*, embedding::*, evaluate::*,
-    explanation::*, tokenization::*,
+    api_tokens::*, batch::*, client::Client, client::RequestOptions,
+    client::ALEPH_ALPHA_API_BASE_URL, completion::*, conversation::*, document::*, embedding::*,
+    evaluate::*, explanation::*, models::*, postprocess::*, qa::*, search::*, summarization::*,
+    template::*, tokenization::*, usage::*, users::*, vector_index::*, version::*,
 };
 
 // copied from https://github.com/dongri/openai-api-rs
@@ -60,3 +105,30 @@ macro_rules! impl_builder_methods {
         }
     };
 }
+
+/// Builder methods for the [`ControlParameters`] of a request type with a `control_parameters`
+/// field.
+macro_rules! impl_control_parameters {
+    ($request:ident) => {
+        impl $request {
+            pub fn control_parameters(
+                mut self,
+                control_parameters: $crate::ControlParameters,
+            ) -> Self {
+                self.control_parameters = control_parameters;
+                self
+            }
+
+            pub fn contextual_control_threshold(mut self, threshold: f64) -> Self {
+                self.control_parameters.contextual_control_threshold = Some(threshold);
+                self
+            }
+
+            pub fn control_log_additive(mut self, log_additive: bool) -> Self {
+                self.control_parameters.control_log_additive = Some(log_additive);
+                self
+            }
+        }
+    };
+}
+pub(crate) use impl_control_parameters;
@@ -0,0 +1,287 @@
+use super::completion::{fits_context, Prompt, TruncationStrategy};
+use super::error::{ApiError, BudgetError};
+use crate::{
+    LLAMA_3_1_70B_INSTRUCT, LLAMA_3_1_8B_INSTRUCT, LLAMA_3_3_70B_INSTRUCT, LUMINOUS_BASE,
+    LUMINOUS_BASE_CONTROL, LUMINOUS_EXTENDED, LUMINOUS_EXTENDED_CONTROL, LUMINOUS_SUPREME,
+    LUMINOUS_SUPREME_CONTROL, PHARIA_1_LLM_7B_CONTROL, PHARIA_1_LLM_7B_CONTROL_ALIGNED,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, convert::Infallible, fmt, str::FromStr};
+use tokenizers::Tokenizer;
+
+/// Name of a model. Known models have their own variant, so typos are caught at compile time;
+/// any other model can be named with [`Model::Custom`]. Request constructors taking a model name
+/// accept it as well.
+///
+/// ```
+///use aleph_alpha_api::{CompletionRequest, Model};
+///
+///let req = CompletionRequest::from_text(Model::Llama3_1_8BInstruct, "An apple a day", 10);
+///assert_eq!(req.model, "llama-3.1-8b-instruct");
+///assert_eq!("luminous-base".parse::<Model>().unwrap(), Model::LuminousBase);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Model {
+    LuminousBase,
+    LuminousBaseControl,
+    LuminousExtended,
+    LuminousExtendedControl,
+    LuminousSupreme,
+    LuminousSupremeControl,
+    Pharia1Llm7BControl,
+    Pharia1Llm7BControlAligned,
+    Llama3_1_8BInstruct,
+    Llama3_1_70BInstruct,
+    Llama3_3_70BInstruct,
+    /// Any model without its own variant, e.g. of a self-hosted deployment.
+    Custom(String),
+}
+
+impl Model {
+    const KNOWN: [Model; 11] = [
+        Model::LuminousBase,
+        Model::LuminousBaseControl,
+        Model::LuminousExtended,
+        Model::LuminousExtendedControl,
+        Model::LuminousSupreme,
+        Model::LuminousSupremeControl,
+        Model::Pharia1Llm7BControl,
+        Model::Pharia1Llm7BControlAligned,
+        Model::Llama3_1_8BInstruct,
+        Model::Llama3_1_70BInstruct,
+        Model::Llama3_3_70BInstruct,
+    ];
+
+    /// The name of the model as used by the API.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Model::LuminousBase => LUMINOUS_BASE,
+            Model::LuminousBaseControl => LUMINOUS_BASE_CONTROL,
+            Model::LuminousExtended => LUMINOUS_EXTENDED,
+            Model::LuminousExtendedControl => LUMINOUS_EXTENDED_CONTROL,
+            Model::LuminousSupreme => LUMINOUS_SUPREME,
+            Model::LuminousSupremeControl => LUMINOUS_SUPREME_CONTROL,
+            Model::Pharia1Llm7BControl => PHARIA_1_LLM_7B_CONTROL,
+            Model::Pharia1Llm7BControlAligned => PHARIA_1_LLM_7B_CONTROL_ALIGNED,
+            Model::Llama3_1_8BInstruct => LLAMA_3_1_8B_INSTRUCT,
+            Model::Llama3_1_70BInstruct => LLAMA_3_1_70B_INSTRUCT,
+            Model::Llama3_3_70BInstruct => LLAMA_3_3_70B_INSTRUCT,
+            Model::Custom(name) => name,
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl AsRef<str> for Model {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Model {
+    fn from(name: &str) -> Self {
+        Model::KNOWN
+            .into_iter()
+            .find(|model| model.as_str() == name)
+            .unwrap_or_else(|| Model::Custom(name.to_owned()))
+    }
+}
+
+impl From<String> for Model {
+    fn from(name: String) -> Self {
+        Model::from(name.as_str())
+    }
+}
+
+impl FromStr for Model {
+    type Err = Infallible;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok(Model::from(name))
+    }
+}
+
+impl From<Model> for String {
+    fn from(model: Model) -> Self {
+        match model {
+            Model::Custom(name) => name,
+            model => model.as_str().to_owned(),
+        }
+    }
+}
+
+/// A model offered by the API, as returned by [`crate::Client::list_models`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModelInfo {
+    /// Name to pass as `model` in requests, e.g. `luminous-base`.
+    pub name: String,
+
+    #[serde(default)]
+    pub description: String,
+
+    /// Hostings the model is available with, e.g. `aleph-alpha`.
+    #[serde(default)]
+    pub hostings: Vec<String>,
+
+    /// Whether the model accepts images in its prompt.
+    #[serde(default)]
+    pub image_support: bool,
+
+    /// Whether the model can be used for question answering.
+    #[serde(default)]
+    pub qa_support: bool,
+
+    /// Whether the model can be used for summarization.
+    #[serde(default)]
+    pub summarization_support: bool,
+
+    /// Supported semantic embedding representations, e.g. `symmetric`.
+    #[serde(default)]
+    pub embedding_types: Vec<String>,
+
+    /// Number of tokens prompt and completion may occupy together.
+    #[serde(default)]
+    pub maximum_context_size: Option<usize>,
+
+    /// Template the model expects instructions in, if it is instruction tuned.
+    #[serde(default)]
+    pub prompt_template: Option<String>,
+}
+
+impl ModelInfo {
+    /// Whether the model can compute semantic embeddings.
+    pub fn embedding_support(&self) -> bool {
+        !self.embedding_types.is_empty()
+    }
+}
+
+/// Context length of all models of the Luminous family.
+const LUMINOUS_CONTEXT_LENGTH: usize = 2048;
+
+/// What a model can do, as needed by the budgeting and truncation helpers.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelCapabilities {
+    /// Number of tokens prompt and completion may occupy together.
+    pub context_length: usize,
+
+    /// Whether the model accepts images in its prompt.
+    pub multimodal: bool,
+
+    /// Whether the model is instruction tuned ("control" models).
+    pub control: bool,
+
+    /// Whether the model is tuned for multi-turn conversations.
+    pub chat: bool,
+}
+
+impl ModelCapabilities {
+    /// Derives the capabilities from the API's description of a model. Models are considered
+    /// instruction tuned if they have a prompt template or a `-control` name, and chat models if
+    /// their name contains `chat`. Without a context size the Luminous context length is assumed.
+    pub fn from_info(info: &ModelInfo) -> Self {
+        Self {
+            context_length: info.maximum_context_size.unwrap_or(LUMINOUS_CONTEXT_LENGTH),
+            multimodal: info.image_support,
+            control: info.prompt_template.is_some() || info.name.contains("-control"),
+            chat: info.name.contains("chat"),
+        }
+    }
+
+    /// Tokens left for the prompt if up to `maximum_tokens` are generated.
+    pub fn prompt_budget(&self, maximum_tokens: u32) -> usize {
+        self.context_length.saturating_sub(maximum_tokens as usize)
+    }
+
+    /// Like [`crate::fits_context`] with the context length of this model.
+    pub fn fits_context(
+        &self,
+        prompt: &Prompt,
+        maximum_tokens: u32,
+        tokenizer: &Tokenizer,
+    ) -> Result<(), BudgetError> {
+        fits_context(self.context_length, prompt, maximum_tokens, tokenizer)
+    }
+
+    /// Truncates `prompt` with [`Prompt::truncate_to`], so that it leaves room for `maximum_tokens`
+    /// generated tokens in the context of this model.
+    pub fn truncate(
+        &self,
+        prompt: &mut Prompt,
+        maximum_tokens: u32,
+        tokenizer: &Tokenizer,
+        strategy: TruncationStrategy,
+    ) -> Result<(), ApiError> {
+        prompt.truncate_to(self.prompt_budget(maximum_tokens), tokenizer, strategy)
+    }
+}
+
+/// Capabilities of known models by name. Starts from static defaults for the Luminous family
+/// (see [`ModelRegistry::luminous`]), which can be updated with the models reported by the API
+/// via [`crate::Client::model_registry`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelRegistry {
+    models: HashMap<String, ModelCapabilities>,
+}
+
+impl ModelRegistry {
+    /// Registry without any models.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with the models of the Luminous family.
+    pub fn luminous() -> Self {
+        let mut registry = Self::new();
+        for (name, multimodal, control) in [
+            (LUMINOUS_BASE, true, false),
+            (LUMINOUS_BASE_CONTROL, false, true),
+            (LUMINOUS_EXTENDED, true, false),
+            (LUMINOUS_EXTENDED_CONTROL, false, true),
+            (LUMINOUS_SUPREME, false, false),
+            (LUMINOUS_SUPREME_CONTROL, false, true),
+        ] {
+            registry.insert(
+                name,
+                ModelCapabilities {
+                    context_length: LUMINOUS_CONTEXT_LENGTH,
+                    multimodal,
+                    control,
+                    chat: false,
+                },
+            );
+        }
+        registry
+    }
+
+    pub fn insert(&mut self, model: impl Into<String>, capabilities: ModelCapabilities) {
+        self.models.insert(model.into(), capabilities);
+    }
+
+    /// Adds or replaces the capabilities of `models`, e.g. as returned by
+    /// [`crate::Client::list_models`].
+    pub fn update(&mut self, models: &[ModelInfo]) {
+        for info in models {
+            self.insert(info.name.clone(), ModelCapabilities::from_info(info));
+        }
+    }
+
+    pub fn get(&self, model: &str) -> Option<&ModelCapabilities> {
+        self.models.get(model)
+    }
+
+    pub fn context_length(&self, model: &str) -> Option<usize> {
+        self.get(model)
+            .map(|capabilities| capabilities.context_length)
+    }
+
+    /// Names of all known models, in no particular order.
+    pub fn models(&self) -> impl Iterator<Item = &str> {
+        self.models.keys().map(String::as_str)
+    }
+}
@@ -0,0 +1,103 @@
+//! Tokenization without requests to the API, using a tokenizer fetched once with
+//! [`Client::get_tokenizer`](crate::Client::get_tokenizer). The functions mirror
+//! [`Client::tokenize`](crate::Client::tokenize) and
+//! [`Client::detokenize`](crate::Client::detokenize), the `model` of the requests is ignored.
+//! [`token_spans`] additionally returns the position of every token in the text, the vocabulary
+//! lookups help to build e.g. `logit_bias` maps and `completion_bias_*` lists.
+//!
+//! ```no_run
+//!use aleph_alpha_api::{error::ApiError, offline, Client, TokenizationRequest, LUMINOUS_BASE};
+//!
+//!async fn token_ids(client: &Client, texts: &[&str]) -> Result<(), ApiError> {
+//!    let tokenizer = client.get_tokenizer(LUMINOUS_BASE).await?;
+//!    for text in texts {
+//!        let req = TokenizationRequest::new(LUMINOUS_BASE, *text).tokens(false);
+//!        println!("{:?}", offline::tokenize(&tokenizer, &req)?.token_ids);
+//!    }
+//!    Ok(())
+//!}
+//! ```
+use super::completion::TextControl;
+use super::error::ApiError;
+use super::tokenization::{
+    DetokenizationRequest, DetokenizationResponse, TokenizationRequest, TokenizationResponse,
+};
+use std::ops::Range;
+use tokenizers::Tokenizer;
+
+/// Tokenizes `req.prompt`, returning token strings and ids as requested by `req.tokens` and
+/// `req.token_ids`.
+pub fn tokenize(
+    tokenizer: &Tokenizer,
+    req: &TokenizationRequest,
+) -> Result<TokenizationResponse, ApiError> {
+    let encoding = tokenizer.encode(req.prompt.as_str(), false)?;
+    Ok(TokenizationResponse {
+        tokens: req.tokens.then(|| encoding.get_tokens().to_vec()),
+        token_ids: req.token_ids.then(|| encoding.get_ids().to_vec()),
+    })
+}
+
+/// Converts `req.token_ids` back into text.
+pub fn detokenize(
+    tokenizer: &Tokenizer,
+    req: &DetokenizationRequest,
+) -> Result<DetokenizationResponse, ApiError> {
+    let result = tokenizer.decode(&req.token_ids, false)?;
+    Ok(DetokenizationResponse { result })
+}
+
+/// A token of a locally tokenized text together with its position in the text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenSpan {
+    pub id: u32,
+    pub token: String,
+
+    /// Character (not byte) range of the token in the text, as used by [`TextControl`] and
+    /// explanation segments.
+    pub chars: Range<usize>,
+}
+
+impl TokenSpan {
+    /// Control applying `factor` to exactly this token.
+    pub fn control(&self, factor: f64) -> TextControl {
+        TextControl::new(self.chars.start as i32, self.chars.len() as i32, factor)
+    }
+}
+
+/// Tokens of `text` with their character ranges, in text order.
+pub fn token_spans(tokenizer: &Tokenizer, text: &str) -> Result<Vec<TokenSpan>, ApiError> {
+    let encoding = tokenizer.encode_char_offsets(text, false)?;
+    Ok(encoding
+        .get_ids()
+        .iter()
+        .zip(encoding.get_tokens())
+        .zip(encoding.get_offsets())
+        .map(|((id, token), (start, end))| TokenSpan {
+            id: *id,
+            token: token.clone(),
+            chars: *start..*end,
+        })
+        .collect())
+}
+
+/// Token of the vocabulary with the id `id`.
+pub fn id_to_token(tokenizer: &Tokenizer, id: u32) -> Option<String> {
+    tokenizer.id_to_token(id)
+}
+
+/// Id of `token` in the vocabulary.
+pub fn token_to_id(tokenizer: &Tokenizer, token: &str) -> Option<u32> {
+    tokenizer.token_to_id(token)
+}
+
+/// All tokens of the vocabulary starting with `prefix` together with their id, ordered by id.
+pub fn tokens_with_prefix(tokenizer: &Tokenizer, prefix: &str) -> Vec<(String, u32)> {
+    let mut tokens: Vec<(String, u32)> = tokenizer
+        .get_vocab(true)
+        .into_iter()
+        .filter(|(token, _)| token.starts_with(prefix))
+        .collect();
+    tokens.sort_by_key(|(_, id)| *id);
+    tokens
+}
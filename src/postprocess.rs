@@ -0,0 +1,112 @@
+use super::completion::{CompletionResponse, Modality, Prompt};
+use regex::Regex;
+
+/// A single cleanup step of an [`OutputPipeline`].
+#[derive(Debug, Clone)]
+pub enum OutputStep {
+    /// Removes leading and trailing whitespace.
+    Trim,
+
+    /// Removes the text of the prompt from the start of the completion, as returned with `echo`
+    /// enabled.
+    StripEchoedPrompt,
+
+    /// Drops everything from the first match of the regex on.
+    CutAt(Regex),
+
+    /// Replaces runs of more than two line breaks by a single empty line.
+    CollapseNewlines,
+}
+
+/// Cleanup steps applied in order to the text of every completion, see
+/// [`Client::completion_with_options`](crate::Client::completion_with_options).
+///
+/// ```
+///use aleph_alpha_api::OutputPipeline;
+///
+///let pipeline = OutputPipeline::new().collapse_newlines().trim();
+///assert_eq!(pipeline.apply(" Hello\n\n\n\nWorld\n", ""), "Hello\n\nWorld");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct OutputPipeline {
+    pub steps: Vec<OutputStep>,
+}
+
+impl OutputPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a step to the end of the pipeline.
+    pub fn then(mut self, step: OutputStep) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    pub fn trim(self) -> Self {
+        self.then(OutputStep::Trim)
+    }
+
+    pub fn strip_echoed_prompt(self) -> Self {
+        self.then(OutputStep::StripEchoedPrompt)
+    }
+
+    pub fn cut_at(self, pattern: Regex) -> Self {
+        self.then(OutputStep::CutAt(pattern))
+    }
+
+    pub fn collapse_newlines(self) -> Self {
+        self.then(OutputStep::CollapseNewlines)
+    }
+
+    /// Runs all steps on `text`. `prompt` is the text of the prompt, used to strip an echo.
+    pub fn apply(&self, text: &str, prompt: &str) -> String {
+        let mut text = text.to_owned();
+        for step in &self.steps {
+            text = match step {
+                OutputStep::Trim => text.trim().to_owned(),
+                OutputStep::StripEchoedPrompt => match text.strip_prefix(prompt) {
+                    Some(rest) => rest.to_owned(),
+                    None => text,
+                },
+                OutputStep::CutAt(pattern) => match pattern.find(&text) {
+                    Some(found) => text[..found.start()].to_owned(),
+                    None => text,
+                },
+                OutputStep::CollapseNewlines => collapse_newlines(&text),
+            };
+        }
+        text
+    }
+
+    /// Applies the pipeline to all completions of `response` requested with `prompt`.
+    pub fn apply_to_response(&self, response: &mut CompletionResponse, prompt: &Prompt) {
+        let prompt_text: String = prompt
+            .iter()
+            .filter_map(|item| match item {
+                Modality::Text { data, .. } => Some(data.as_str()),
+                _ => None,
+            })
+            .collect();
+        for output in &mut response.completions {
+            output.completion = self.apply(&output.completion, &prompt_text);
+        }
+    }
+}
+
+fn collapse_newlines(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut newlines = 0;
+    for c in text.chars() {
+        if c == '\n' {
+            newlines += 1;
+            if newlines > 2 {
+                continue;
+            }
+        } else {
+            newlines = 0;
+        }
+        collapsed.push(c);
+    }
+    collapsed
+}
@@ -0,0 +1,301 @@
+//! Cost estimation for completions from token counts and a table of per-model prices, e.g. to
+//! budget a batch job before launching it.
+//!
+//! ```no_run
+//!use aleph_alpha_api::{pricing::PricingTable, Client, CompletionRequest, LUMINOUS_BASE};
+//!
+//!async fn budget(client: &Client, texts: &[&str]) {
+//!    let pricing = PricingTable::default();
+//!    let tokenizer = client.get_tokenizer(LUMINOUS_BASE).await.unwrap();
+//!    let total: f64 = texts
+//!        .iter()
+//!        .map(|text| CompletionRequest::from_text(LUMINOUS_BASE, *text, 64))
+//!        .map(|req| pricing.estimate_cost(&req, &tokenizer).unwrap().credits)
+//!        .sum();
+//!    println!("At most {total:.2} credits");
+//!}
+//! ```
+use super::completion::{CompletionRequest, CompletionResponse, Modality, IMAGE_TOKEN_COUNT};
+use super::error::{ApiError, ValidationError};
+use crate::{
+    LUMINOUS_BASE, LUMINOUS_BASE_CONTROL, LUMINOUS_EXTENDED, LUMINOUS_EXTENDED_CONTROL,
+    LUMINOUS_SUPREME, LUMINOUS_SUPREME_CONTROL,
+};
+use std::{collections::HashMap, iter::Sum, ops::Add, sync::Mutex};
+use thiserror::Error as ThisError;
+use tokenizers::Tokenizer;
+
+/// Price of a model in credits per 1000 tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+impl ModelPrice {
+    pub fn new(input: f64, output: f64) -> Self {
+        Self { input, output }
+    }
+
+    pub fn cost(&self, input_tokens: usize, output_tokens: usize) -> Cost {
+        Cost {
+            input_tokens,
+            output_tokens,
+            credits: (input_tokens as f64 * self.input + output_tokens as f64 * self.output)
+                / 1000.0,
+        }
+    }
+}
+
+/// Tokens and credits of one or more requests. Costs of several requests can be added up.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Cost {
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+    pub credits: f64,
+}
+
+impl Add for Cost {
+    type Output = Cost;
+
+    fn add(self, other: Cost) -> Cost {
+        Cost {
+            input_tokens: self.input_tokens + other.input_tokens,
+            output_tokens: self.output_tokens + other.output_tokens,
+            credits: self.credits + other.credits,
+        }
+    }
+}
+
+impl Sum for Cost {
+    fn sum<I: Iterator<Item = Cost>>(iter: I) -> Cost {
+        iter.fold(Cost::default(), Add::add)
+    }
+}
+
+#[derive(ThisError, Debug)]
+pub enum PricingError {
+    #[error("No price known for model '{0}'.")]
+    UnknownModel(String),
+
+    /// The response lacks the token counts, which older API versions do not report.
+    #[error("The response does not report the number of tokens.")]
+    MissingUsage,
+
+    /// The prompt could not be tokenized.
+    #[error(transparent)]
+    Tokenization(#[from] ApiError),
+}
+
+/// Prices of models by name. The [`Default`] holds the list prices of the Luminous models, which
+/// may be outdated or differ for your contract, so override them with [`PricingTable::set`] if
+/// necessary.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PricingTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl PricingTable {
+    /// A table without any prices.
+    pub fn new() -> Self {
+        Self {
+            prices: HashMap::new(),
+        }
+    }
+
+    /// Sets the price of `model`, replacing a previous one.
+    pub fn set(&mut self, model: impl Into<String>, price: ModelPrice) -> &mut Self {
+        self.prices.insert(model.into(), price);
+        self
+    }
+
+    pub fn get(&self, model: &str) -> Option<ModelPrice> {
+        self.prices.get(model).copied()
+    }
+
+    fn price(&self, model: &str) -> Result<ModelPrice, PricingError> {
+        self.get(model)
+            .ok_or_else(|| PricingError::UnknownModel(model.to_owned()))
+    }
+
+    /// Upper bound of the cost of `req`, assuming all completions have `maximum_tokens` tokens.
+    /// Like the API, prompt and completion count once for each of the `best_of` (or `n`)
+    /// candidates. The prompt is tokenized with `tokenizer`, see
+    /// [`crate::Prompt::estimate_tokens`].
+    pub fn estimate_cost(
+        &self,
+        req: &CompletionRequest,
+        tokenizer: &Tokenizer,
+    ) -> Result<Cost, PricingError> {
+        let price = self.price(&req.model)?;
+        let (input_tokens, output_tokens) = estimate_tokens(req, Some(tokenizer))?;
+        Ok(price.cost(input_tokens, output_tokens))
+    }
+
+    /// Actual cost of a completion with `model`, from the token counts of the `response`.
+    pub fn cost_of(
+        &self,
+        model: &str,
+        response: &CompletionResponse,
+    ) -> Result<Cost, PricingError> {
+        let price = self.price(model)?;
+        match (
+            response.num_tokens_prompt_total,
+            response.num_tokens_generated,
+        ) {
+            (Some(input), Some(output)) => Ok(price.cost(input as usize, output as usize)),
+            _ => Err(PricingError::MissingUsage),
+        }
+    }
+}
+
+/// Upper bound of the prompt and completion tokens of `req`, see [`PricingTable::estimate_cost`].
+/// Without a `tokenizer`, every byte of text counts as a token.
+fn estimate_tokens(
+    req: &CompletionRequest,
+    tokenizer: Option<&Tokenizer>,
+) -> Result<(usize, usize), ApiError> {
+    let completions = req.best_of.or(req.n).unwrap_or(1).max(1) as usize;
+    let prompt_tokens = match tokenizer {
+        Some(tokenizer) => req.prompt.estimate_tokens(tokenizer)?,
+        None => req
+            .prompt
+            .iter()
+            .map(|item| match item {
+                Modality::Text { data, .. } => data.len(),
+                Modality::Image { .. } => IMAGE_TOKEN_COUNT,
+                Modality::TokenIds { data, .. } => data.len(),
+            })
+            .sum(),
+    };
+    let input_tokens = prompt_tokens * completions;
+    let output_tokens = req.maximum_tokens as usize * completions;
+    Ok((input_tokens, output_tokens))
+}
+
+impl Default for PricingTable {
+    fn default() -> Self {
+        let mut table = Self::new();
+        table
+            .set(LUMINOUS_BASE, ModelPrice::new(0.03, 0.033))
+            .set(LUMINOUS_BASE_CONTROL, ModelPrice::new(0.0375, 0.04125))
+            .set(LUMINOUS_EXTENDED, ModelPrice::new(0.045, 0.0495))
+            .set(
+                LUMINOUS_EXTENDED_CONTROL,
+                ModelPrice::new(0.05625, 0.061875),
+            )
+            .set(LUMINOUS_SUPREME, ModelPrice::new(0.175, 0.1925))
+            .set(LUMINOUS_SUPREME_CONTROL, ModelPrice::new(0.21875, 0.240625));
+        table
+    }
+}
+
+/// Maximum spend of a [`crate::Client`], see [`crate::Client::with_budget`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Budget {
+    /// Maximum number of prompt and completion tokens.
+    Tokens(usize),
+
+    /// Maximum number of credits, with the prices of the models used.
+    Credits { limit: f64, pricing: PricingTable },
+}
+
+/// Spend of a client counted against its [`Budget`].
+#[derive(Debug)]
+pub(crate) struct BudgetGuard {
+    budget: Budget,
+    spent: Mutex<f64>,
+}
+
+impl BudgetGuard {
+    pub(crate) fn new(budget: Budget) -> Self {
+        Self {
+            budget,
+            spent: Mutex::new(0.0),
+        }
+    }
+
+    fn limit(&self) -> f64 {
+        match &self.budget {
+            Budget::Tokens(limit) => *limit as f64,
+            Budget::Credits { limit, .. } => *limit,
+        }
+    }
+
+    pub(crate) fn spent(&self) -> f64 {
+        *self.spent.lock().unwrap()
+    }
+
+    /// Fails if the budget is used up, or if the spend of a completion with `model` could not be
+    /// counted.
+    pub(crate) fn check(&self, model: Option<&str>) -> Result<(), ApiError> {
+        let (spent, limit) = (self.spent(), self.limit());
+        if spent >= limit {
+            return Err(ApiError::BudgetExceeded { spent, limit });
+        }
+        if let (Some(model), Budget::Credits { pricing, .. }) = (model, &self.budget) {
+            if pricing.get(model).is_none() {
+                return Err(ValidationError::UnpricedModel(model.to_owned()).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the usage reported by a completion `response` with `model`. Fails with
+    /// [`PricingError::MissingUsage`] without counting anything if the response does not report
+    /// its tokens.
+    pub(crate) fn record(
+        &self,
+        model: &str,
+        response: &CompletionResponse,
+    ) -> Result<(), PricingError> {
+        match (
+            response.num_tokens_prompt_total,
+            response.num_tokens_generated,
+        ) {
+            (Some(input), Some(output)) => self.add(model, input as usize, output as usize),
+            _ => Err(PricingError::MissingUsage),
+        }
+    }
+
+    /// Adds the upper bound of the usage of `req`, for responses not reporting their usage. See
+    /// [`PricingTable::estimate_cost`]. Without a `tokenizer`, every byte of text counts as a
+    /// token.
+    pub(crate) fn record_estimate(
+        &self,
+        req: &CompletionRequest,
+        tokenizer: Option<&Tokenizer>,
+    ) -> Result<(), PricingError> {
+        let (input_tokens, output_tokens) = estimate_tokens(req, tokenizer)?;
+        self.add(&req.model, input_tokens, output_tokens)
+    }
+
+    fn add(
+        &self,
+        model: &str,
+        input_tokens: usize,
+        output_tokens: usize,
+    ) -> Result<(), PricingError> {
+        let amount = match &self.budget {
+            Budget::Tokens(_) => (input_tokens + output_tokens) as f64,
+            Budget::Credits { pricing, .. } => {
+                pricing
+                    .price(model)?
+                    .cost(input_tokens, output_tokens)
+                    .credits
+            }
+        };
+        *self.spent.lock().unwrap() += amount;
+        Ok(())
+    }
+}
+
+impl From<PricingError> for ApiError {
+    fn from(error: PricingError) -> Self {
+        match error {
+            PricingError::UnknownModel(model) => ValidationError::UnpricedModel(model).into(),
+            PricingError::MissingUsage => ApiError::UnexpectedResponse(error.to_string()),
+            PricingError::Tokenization(error) => error,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use super::completion::Hosting;
+use super::document::Document;
+use crate::impl_builder_methods;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Debug, Default)]
+pub struct QaRequest {
+    /// Determines in which datacenters the request may be processed.
+    /// You can either set the parameter to "aleph-alpha" or omit it (defaulting to None).
+    ///
+    /// Not setting this value, or setting it to None, gives us maximal flexibility in processing your request in our
+    /// own datacenters and on servers hosted with other providers. Choose this option for maximal availability.
+    ///
+    /// Setting it to "aleph-alpha" allows us to only process the request in our own datacenters.
+    /// Choose this option for maximal data privacy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hosting: Option<Hosting>,
+
+    /// The question to be answered about the documents by the model.
+    pub query: String,
+
+    /// The documents to search for answers to the query.
+    pub documents: Vec<Document>,
+
+    /// The maximum number of answers to return.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_answers: Option<u32>,
+}
+
+impl QaRequest {
+    pub fn new(query: impl Into<String>, documents: Vec<Document>) -> Self {
+        Self {
+            query: query.into(),
+            documents,
+            ..Self::default()
+        }
+    }
+}
+
+impl_builder_methods!(QaRequest, hosting: Hosting, max_answers: u32);
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct QaAnswer {
+    /// The answer generated by the model for the query.
+    pub answer: String,
+
+    /// The score of the answer, higher is better.
+    pub score: f64,
+
+    /// The part of the documents the answer is based on.
+    pub evidence: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct QaResponse {
+    /// Model name and version (if any) of the used model for inference.
+    pub model_version: String,
+
+    /// The answers found in the documents, best first. Empty if the documents do not answer the
+    /// query.
+    pub answers: Vec<QaAnswer>,
+}
+
+impl QaResponse {
+    /// The answer with the highest score, if any.
+    pub fn best_answer(&self) -> Option<&QaAnswer> {
+        self.answers
+            .iter()
+            .max_by(|a, b| a.score.total_cmp(&b.score))
+    }
+}
@@ -0,0 +1,150 @@
+//! Upserting embeddings into a [Qdrant](https://qdrant.tech) collection via its REST API.
+//! Requires the `qdrant` feature.
+//!
+//! ```no_run
+//!use aleph_alpha_api::{qdrant::{QdrantPoint, QdrantSink}, Client, EmbedStreamOptions,
+//!    EmbeddingRepresentation, Prompt, LUMINOUS_BASE};
+//!use futures::StreamExt;
+//!
+//!async fn index(client: &Client, texts: Vec<String>) {
+//!    let prompts = futures::stream::iter(texts.clone()).map(Prompt::from_text);
+//!    let options = EmbedStreamOptions::new(LUMINOUS_BASE, EmbeddingRepresentation::Document);
+//!    let points = client
+//!        .embed_stream(prompts, options)
+//!        .filter_map(|result| async { result.ok() })
+//!        .map(|(index, embedding)| {
+//!            let payload = serde_json::json!({ "text": texts[index] });
+//!            QdrantPoint::new(index as u64, embedding).payload(payload)
+//!        });
+//!    let sink = QdrantSink::new("http://localhost:6333", "documents");
+//!    sink.upsert_stream(points).await.unwrap();
+//!}
+//! ```
+use super::batch::RetryPolicy;
+use super::error::ApiError;
+use super::http::translate_http_error;
+use futures::stream::{Stream, StreamExt};
+use serde::Serialize;
+
+/// Id of a Qdrant point, either an unsigned integer or a UUID.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum PointId {
+    Num(u64),
+    Uuid(String),
+}
+
+impl From<u64> for PointId {
+    fn from(id: u64) -> Self {
+        PointId::Num(id)
+    }
+}
+
+impl From<String> for PointId {
+    fn from(uuid: String) -> Self {
+        PointId::Uuid(uuid)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct QdrantPoint {
+    pub id: PointId,
+    pub vector: Vec<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<serde_json::Value>,
+}
+
+impl QdrantPoint {
+    pub fn new(id: impl Into<PointId>, vector: Vec<f32>) -> Self {
+        Self {
+            id: id.into(),
+            vector,
+            payload: None,
+        }
+    }
+
+    /// JSON object stored along with the vector.
+    pub fn payload(mut self, payload: serde_json::Value) -> Self {
+        self.payload = Some(payload);
+        self
+    }
+}
+
+#[derive(Serialize)]
+struct UpsertPoints<'a> {
+    points: &'a [QdrantPoint],
+}
+
+/// Writes points into one collection, in batches of `batch_size` points. Failing batches are
+/// retried according to `retry`.
+pub struct QdrantSink {
+    http_client: reqwest::Client,
+    pub url: String,
+    pub collection: String,
+    pub api_key: Option<String>,
+    pub batch_size: usize,
+    pub retry: RetryPolicy,
+}
+
+impl QdrantSink {
+    /// Sink for `collection` of the Qdrant instance at `url`, e.g. `http://localhost:6333`.
+    pub fn new(url: impl Into<String>, collection: impl Into<String>) -> Self {
+        Self {
+            http_client: reqwest::Client::new(),
+            url: url.into(),
+            collection: collection.into(),
+            api_key: None,
+            batch_size: 256,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Upserts `points` with a single request and waits until they are persisted.
+    pub async fn upsert(&self, points: &[QdrantPoint]) -> Result<(), ApiError> {
+        let url = format!(
+            "{}/collections/{}/points?wait=true",
+            self.url.trim_end_matches('/'),
+            self.collection
+        );
+        self.retry
+            .run(|| async {
+                let mut request = self.http_client.put(&url).json(&UpsertPoints { points });
+                if let Some(api_key) = &self.api_key {
+                    request = request.header("api-key", api_key);
+                }
+                translate_http_error(request.send().await?).await?;
+                Ok(())
+            })
+            .await
+    }
+
+    /// Upserts all points of `points` in batches. Returns the number of upserted points, and
+    /// stops at the first batch which failed permanently.
+    pub async fn upsert_stream(
+        &self,
+        points: impl Stream<Item = QdrantPoint>,
+    ) -> Result<usize, ApiError> {
+        let mut batches = std::pin::pin!(points.chunks(self.batch_size.max(1)));
+        let mut count = 0;
+        while let Some(batch) = batches.next().await {
+            self.upsert(&batch).await?;
+            count += batch.len();
+        }
+        Ok(count)
+    }
+}
@@ -0,0 +1,92 @@
+//! Compact storage of embeddings for large corpora. [`F16Embedding`] halves and
+//! [`Int8Embedding`] quarters the memory of an `f32` embedding, at the cost of a small loss of
+//! accuracy.
+//!
+//! ```
+//!use aleph_alpha_api::{quantization::Int8Embedding, similarity::cosine_similarity};
+//!
+//!let a = [0.1, -0.5, 0.25, 0.9];
+//!let b = [0.2, -0.4, 0.3, 0.7];
+//!let quantized = Int8Embedding::quantize(&a).cosine_similarity(&Int8Embedding::quantize(&b));
+//!assert!((quantized - cosine_similarity(&a, &b)).abs() < 0.01);
+//! ```
+pub use half::f16;
+
+/// Embedding stored as half precision floats.
+#[derive(Debug, Clone, PartialEq)]
+pub struct F16Embedding(pub Vec<f16>);
+
+impl F16Embedding {
+    pub fn quantize(embedding: &[f32]) -> Self {
+        Self(embedding.iter().map(|v| f16::from_f32(*v)).collect())
+    }
+
+    pub fn dequantize(&self) -> Vec<f32> {
+        self.0.iter().map(|v| v.to_f32()).collect()
+    }
+
+    pub fn dot(&self, other: &Self) -> f32 {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .map(|(a, b)| a.to_f32() * b.to_f32())
+            .sum()
+    }
+
+    /// Cosine similarity, `0.0` if one of the embeddings is the zero vector.
+    pub fn cosine_similarity(&self, other: &Self) -> f32 {
+        let norms = (self.dot(self) * other.dot(other)).sqrt();
+        if norms == 0.0 {
+            0.0
+        } else {
+            self.dot(other) / norms
+        }
+    }
+}
+
+/// Embedding stored as signed bytes with a common scale, `value ≈ values[i] * scale`. The scale
+/// maps the largest absolute value of the embedding to 127.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Int8Embedding {
+    pub values: Vec<i8>,
+    pub scale: f32,
+}
+
+impl Int8Embedding {
+    pub fn quantize(embedding: &[f32]) -> Self {
+        let max = embedding.iter().fold(0.0f32, |max, v| max.max(v.abs()));
+        let scale = if max == 0.0 { 1.0 } else { max / 127.0 };
+        let values = embedding
+            .iter()
+            .map(|v| (v / scale).round().clamp(-127.0, 127.0) as i8)
+            .collect();
+        Self { values, scale }
+    }
+
+    pub fn dequantize(&self) -> Vec<f32> {
+        self.values.iter().map(|v| *v as f32 * self.scale).collect()
+    }
+
+    fn dot_unscaled(&self, other: &Self) -> i64 {
+        self.values
+            .iter()
+            .zip(&other.values)
+            .map(|(a, b)| *a as i64 * *b as i64)
+            .sum()
+    }
+
+    pub fn dot(&self, other: &Self) -> f32 {
+        self.dot_unscaled(other) as f32 * self.scale * other.scale
+    }
+
+    /// Cosine similarity, computed on the integer values since the scales cancel out. `0.0` if
+    /// one of the embeddings is the zero vector.
+    pub fn cosine_similarity(&self, other: &Self) -> f32 {
+        let norms = ((self.dot_unscaled(self) * other.dot_unscaled(other)) as f64).sqrt();
+        if norms == 0.0 {
+            0.0
+        } else {
+            (self.dot_unscaled(other) as f64 / norms) as f32
+        }
+    }
+}
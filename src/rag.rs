@@ -0,0 +1,321 @@
+//! Retrieval augmented generation: documents are split into chunks, which are embedded and stored
+//! in an index. For a query, the most similar chunks are retrieved and assembled into a prompt
+//! which asks the model to answer based on these sources only.
+//!
+//! Every stage is a trait with a default implementation backed by this crate, so e.g. the
+//! in-memory [`VectorIndex`] can be replaced with an external vector database. Embedding and
+//! retrieval are async and their futures are `Send`, so pipelines can run in spawned tasks:
+//!
+//! | Stage     | Trait               | Default                     |
+//! |-----------|---------------------|-----------------------------|
+//! | Chunking  | [`Chunker`]         | [`TokenChunker`]            |
+//! | Embedding | [`Embedder`]        | [`SemanticEmbedder`]        |
+//! | Retrieval | [`Retriever`]       | [`VectorIndex`]             |
+//! | Prompt    | [`PromptAssembler`] | [`GroundedPromptAssembler`] |
+//!
+//! ```no_run
+//!use aleph_alpha_api::{error::ApiError, rag::*, Client, VectorIndex, LUMINOUS_BASE};
+//!
+//!async fn answer(client: &Client, manual: &str) -> Result<String, ApiError> {
+//!    let tokenizer = client.get_tokenizer(LUMINOUS_BASE).await?;
+//!    let mut pipeline = RagPipeline::new(
+//!        TokenChunker::new(tokenizer, 256, 32),
+//!        SemanticEmbedder::new(client, LUMINOUS_BASE),
+//!        VectorIndex::new(),
+//!        GroundedPromptAssembler::default(),
+//!    );
+//!    pipeline.add_document("manual.txt", manual).await?;
+//!    let grounded = pipeline.prompt("How do I reset the device?", 3).await?;
+//!    let req = grounded.completion_request(LUMINOUS_BASE, 64);
+//!    Ok(client.completion(&req, None).await?.best_text().to_owned())
+//!}
+//! ```
+use super::chunking::{chunk_text, TextChunk};
+use super::client::Client;
+use super::completion::{CompletionRequest, Prompt};
+use super::embedding::{
+    BatchSemanticEmbeddingRequest, EmbeddingRepresentation, SemanticEmbeddingRequest,
+};
+use super::error::ApiError;
+use super::vector_index::VectorIndex;
+use std::{fmt::Write, future::Future, ops::Range, sync::Arc};
+use tokenizers::Tokenizer;
+
+/// A chunk of a document added to a [`RagPipeline`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceChunk {
+    /// Name of the document, e.g. a file name or URL, used for attribution.
+    pub source: String,
+
+    pub text: String,
+
+    /// Byte range of the chunk in the document.
+    pub range: Range<usize>,
+}
+
+/// A chunk retrieved for a query, with its similarity to the query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetrievedChunk {
+    pub chunk: SourceChunk,
+    pub score: f32,
+}
+
+/// Splits documents into chunks.
+pub trait Chunker {
+    fn chunk<'a>(&self, text: &'a str) -> Result<Vec<TextChunk<'a>>, ApiError>;
+}
+
+/// Embeds chunks and queries into the same vector space.
+pub trait Embedder {
+    fn embed_documents(
+        &self,
+        texts: &[&str],
+    ) -> impl Future<Output = Result<Vec<Vec<f32>>, ApiError>> + Send;
+
+    fn embed_query(&self, text: &str) -> impl Future<Output = Result<Vec<f32>, ApiError>> + Send;
+}
+
+/// Stores embedded chunks and finds the ones most similar to a query.
+pub trait Retriever {
+    fn add(
+        &mut self,
+        chunk: SourceChunk,
+        embedding: Vec<f32>,
+    ) -> impl Future<Output = Result<(), ApiError>> + Send;
+
+    /// The `k` chunks most similar to `query`, most similar first.
+    fn retrieve(
+        &self,
+        query: &[f32],
+        k: usize,
+    ) -> impl Future<Output = Result<Vec<RetrievedChunk>, ApiError>> + Send;
+}
+
+/// Builds the prompt for a query from the retrieved chunks.
+pub trait PromptAssembler {
+    fn assemble(&self, query: &str, chunks: &[RetrievedChunk]) -> Prompt;
+}
+
+/// [`Chunker`] using [`chunk_text`].
+#[derive(Debug, Clone)]
+pub struct TokenChunker {
+    tokenizer: Arc<Tokenizer>,
+    chunk_size: usize,
+    overlap: usize,
+}
+
+impl TokenChunker {
+    /// Chunks of `chunk_size` tokens of `tokenizer`, of which consecutive chunks share `overlap`.
+    pub fn new(tokenizer: Arc<Tokenizer>, chunk_size: usize, overlap: usize) -> Self {
+        Self {
+            tokenizer,
+            chunk_size,
+            overlap,
+        }
+    }
+}
+
+impl Chunker for TokenChunker {
+    fn chunk<'a>(&self, text: &'a str) -> Result<Vec<TextChunk<'a>>, ApiError> {
+        chunk_text(&self.tokenizer, text, self.chunk_size, self.overlap)
+    }
+}
+
+/// [`Embedder`] using the semantic embedding endpoints, with the asymmetric document and query
+/// representations.
+pub struct SemanticEmbedder<'a> {
+    client: &'a Client,
+    model: String,
+    batch_size: usize,
+    max_concurrency: usize,
+    nice: Option<bool>,
+}
+
+impl<'a> SemanticEmbedder<'a> {
+    pub fn new(client: &'a Client, model: impl Into<String>) -> Self {
+        Self {
+            client,
+            model: model.into(),
+            batch_size: 100,
+            max_concurrency: 4,
+            nice: None,
+        }
+    }
+
+    /// Number of chunks embedded with one request. Defaults to 100.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Number of requests in flight at any time. Defaults to 4.
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency;
+        self
+    }
+
+    pub fn nice(mut self, nice: bool) -> Self {
+        self.nice = Some(nice);
+        self
+    }
+}
+
+impl Embedder for SemanticEmbedder<'_> {
+    async fn embed_documents(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, ApiError> {
+        let req = BatchSemanticEmbeddingRequest {
+            model: self.model.clone(),
+            prompts: texts.iter().map(|text| Prompt::from_text(*text)).collect(),
+            representation: EmbeddingRepresentation::Document,
+            ..BatchSemanticEmbeddingRequest::default()
+        };
+        let response = self
+            .client
+            .batch_semantic_embed_chunked(&req, self.batch_size, self.max_concurrency, self.nice)
+            .await?;
+        Ok(response.embeddings)
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, ApiError> {
+        let req = SemanticEmbeddingRequest {
+            model: self.model.clone(),
+            prompt: Prompt::from_text(text),
+            representation: EmbeddingRepresentation::Query,
+            ..SemanticEmbeddingRequest::default()
+        };
+        Ok(self.client.semantic_embed(&req, self.nice).await?.embedding)
+    }
+}
+
+impl Retriever for VectorIndex<SourceChunk> {
+    async fn add(&mut self, chunk: SourceChunk, embedding: Vec<f32>) -> Result<(), ApiError> {
+        self.insert(chunk, embedding);
+        Ok(())
+    }
+
+    async fn retrieve(&self, query: &[f32], k: usize) -> Result<Vec<RetrievedChunk>, ApiError> {
+        Ok(self
+            .top_k(query, k)
+            .into_iter()
+            .map(|(chunk, score)| RetrievedChunk {
+                chunk: chunk.clone(),
+                score,
+            })
+            .collect())
+    }
+}
+
+/// [`PromptAssembler`] listing the chunks as numbered sources, followed by the query and an
+/// instruction to answer from the sources only and to cite them by number.
+#[derive(Debug, Clone)]
+pub struct GroundedPromptAssembler {
+    instruction: String,
+}
+
+impl GroundedPromptAssembler {
+    pub fn new(instruction: impl Into<String>) -> Self {
+        Self {
+            instruction: instruction.into(),
+        }
+    }
+}
+
+impl Default for GroundedPromptAssembler {
+    fn default() -> Self {
+        Self::new(
+            "Answer the question using only the sources below. Cite the sources you use by their \
+            number, e.g. [1]. If the sources do not contain the answer, say so.",
+        )
+    }
+}
+
+impl PromptAssembler for GroundedPromptAssembler {
+    fn assemble(&self, query: &str, chunks: &[RetrievedChunk]) -> Prompt {
+        let mut text = format!("{}\n\n", self.instruction);
+        for (number, retrieved) in chunks.iter().enumerate() {
+            let chunk = &retrieved.chunk;
+            writeln!(text, "[{}] ({}) {}", number + 1, chunk.source, chunk.text).unwrap();
+        }
+        write!(text, "\nQuestion: {query}\nAnswer:").unwrap();
+        Prompt::from_text(text)
+    }
+}
+
+/// Prompt assembled by [`RagPipeline::prompt`], with the chunks it is grounded on. The `n`-th
+/// source is referred to as `[n]` in the prompt of the default [`GroundedPromptAssembler`].
+#[derive(Debug, Clone)]
+pub struct GroundedPrompt {
+    pub prompt: Prompt,
+    pub sources: Vec<RetrievedChunk>,
+}
+
+impl GroundedPrompt {
+    pub fn completion_request(
+        &self,
+        model: impl Into<String>,
+        maximum_tokens: u32,
+    ) -> CompletionRequest {
+        CompletionRequest::new(model, self.prompt.clone(), maximum_tokens)
+    }
+}
+
+/// Chunks, embeds and indexes documents, and assembles grounded prompts for queries.
+pub struct RagPipeline<C, E, R, A> {
+    pub chunker: C,
+    pub embedder: E,
+    pub retriever: R,
+    pub assembler: A,
+}
+
+impl<C: Chunker, E: Embedder, R: Retriever, A: PromptAssembler> RagPipeline<C, E, R, A> {
+    pub fn new(chunker: C, embedder: E, retriever: R, assembler: A) -> Self {
+        Self {
+            chunker,
+            embedder,
+            retriever,
+            assembler,
+        }
+    }
+
+    /// Chunks and embeds `text` and adds the chunks to the retriever. Returns the number of
+    /// chunks.
+    pub async fn add_document(&mut self, source: &str, text: &str) -> Result<usize, ApiError> {
+        let chunks = self.chunker.chunk(text)?;
+        if chunks.is_empty() {
+            return Ok(0);
+        }
+        let texts: Vec<&str> = chunks.iter().map(|chunk| chunk.text).collect();
+        let embeddings = self.embedder.embed_documents(&texts).await?;
+        if embeddings.len() != chunks.len() {
+            return Err(ApiError::UnexpectedResponse(format!(
+                "Expected {} embeddings, got {}.",
+                chunks.len(),
+                embeddings.len()
+            )));
+        }
+        let count = chunks.len();
+        for (chunk, embedding) in chunks.into_iter().zip(embeddings) {
+            let chunk = SourceChunk {
+                source: source.to_owned(),
+                text: chunk.text.to_owned(),
+                range: chunk.range,
+            };
+            self.retriever.add(chunk, embedding).await?;
+        }
+        Ok(count)
+    }
+
+    /// The `k` chunks most relevant to `query`.
+    pub async fn retrieve(&self, query: &str, k: usize) -> Result<Vec<RetrievedChunk>, ApiError> {
+        let embedding = self.embedder.embed_query(query).await?;
+        self.retriever.retrieve(&embedding, k).await
+    }
+
+    /// Prompt answering `query` from the `k` most relevant chunks.
+    pub async fn prompt(&self, query: &str, k: usize) -> Result<GroundedPrompt, ApiError> {
+        let sources = self.retrieve(query, k).await?;
+        Ok(GroundedPrompt {
+            prompt: self.assembler.assemble(query, &sources),
+            sources,
+        })
+    }
+}
@@ -0,0 +1,319 @@
+//! Sampling comparison reports: a set of prompts is completed with several named sampling
+//! configurations, and all outputs are collected into a [`SamplingReport`] to compare the
+//! configurations side by side. A configuration named `"default"` provides the values missing in
+//! all others. See `examples/sampling_report.rs` for a command line tool built on this module.
+//!
+//! ```no_run
+//!use aleph_alpha_api::{report::*, Client, LUMINOUS_BASE};
+//!
+//!async fn compare(client: &Client) -> Result<(), ReportError> {
+//!    let configurations = read_configurations("examples/config/sampling_default.json")?;
+//!    let prompts = read_prompts("examples/config/prompts_oa_en.jsonl")?;
+//!    let mut report = SamplingReport::new(LUMINOUS_BASE, "2023-12-01", serde_json::Value::Null);
+//!    for prompt in &prompts {
+//!        let result = sample_prompt(client, LUMINOUS_BASE, &configurations, prompt, None).await?;
+//!        report.prompts.push(result);
+//!    }
+//!    report.write("report.json")?;
+//!    Ok(())
+//!}
+//! ```
+use super::client::Client;
+use super::completion::{CompletionRequest, Prompt};
+use super::error::ApiError;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt::Write, fs, path::Path};
+use thiserror::Error as ThisError;
+
+/// Name of the configuration providing defaults for all others.
+pub const DEFAULT_CONFIGURATION: &str = "default";
+
+/// Number of tokens generated if a configuration does not set `max_new_tokens`.
+pub const DEFAULT_MAXIMUM_TOKENS: u32 = 100;
+
+/// Sampling parameters of a configuration. Unset parameters are left to the API.
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct GenerationArgs {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_new_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_new_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable_optimizations: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub best_of: Option<u32>,
+}
+
+impl GenerationArgs {
+    /// These arguments, with the unset ones taken from `defaults`.
+    pub fn merge(&self, defaults: &GenerationArgs) -> GenerationArgs {
+        GenerationArgs {
+            max_new_tokens: self.max_new_tokens.or(defaults.max_new_tokens),
+            min_new_tokens: self.min_new_tokens.or(defaults.min_new_tokens),
+            temperature: self.temperature.or(defaults.temperature),
+            presence_penalty: self.presence_penalty.or(defaults.presence_penalty),
+            frequency_penalty: self.frequency_penalty.or(defaults.frequency_penalty),
+            top_p: self.top_p.or(defaults.top_p),
+            top_k: self.top_k.or(defaults.top_k),
+            disable_optimizations: self
+                .disable_optimizations
+                .or(defaults.disable_optimizations),
+            n: self.n.or(defaults.n),
+            best_of: self.best_of.or(defaults.best_of),
+        }
+    }
+
+    /// Sets the parameters of `req` which are set in these arguments. Presence and frequency
+    /// penalties also apply to the completion.
+    pub fn apply_to(&self, req: &mut CompletionRequest) {
+        if let Some(max_new_tokens) = self.max_new_tokens {
+            req.maximum_tokens = max_new_tokens;
+        }
+        if self.min_new_tokens.is_some() {
+            req.minimum_tokens = self.min_new_tokens;
+        }
+        if self.temperature.is_some() {
+            req.temperature = self.temperature;
+        }
+        if self.top_k.is_some() {
+            req.top_k = self.top_k;
+        }
+        if self.top_p.is_some() {
+            req.top_p = self.top_p;
+        }
+        if self.presence_penalty.is_some() {
+            req.presence_penalty = self.presence_penalty;
+            req.repetition_penalties_include_completion = Some(true);
+        }
+        if self.frequency_penalty.is_some() {
+            req.frequency_penalty = self.frequency_penalty;
+            req.repetition_penalties_include_completion = Some(true);
+        }
+        if self.disable_optimizations.is_some() {
+            req.disable_optimizations = self.disable_optimizations;
+        }
+        if self.best_of.is_some() {
+            req.best_of = self.best_of;
+        }
+        if self.n.is_some() {
+            req.n = self.n;
+        }
+    }
+}
+
+/// Sampling parameters together with the chat format the prompts are wrapped into.
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub struct SamplingConfiguration {
+    #[serde(default)]
+    pub generate_args: GenerationArgs,
+    pub system_prompt: Option<String>,
+    pub assistant_name: Option<String>,
+    pub user_name: Option<String>,
+}
+
+/// Sampling configurations by name, as read by [`read_configurations`].
+pub type NamedConfigurations = BTreeMap<String, SamplingConfiguration>;
+
+#[derive(ThisError, Debug)]
+pub enum ReportError {
+    /// A configuration lacks a name required to format the prompt.
+    #[error("The sampling configuration '{configuration}' does not specify {field}.")]
+    MissingName {
+        configuration: String,
+        field: &'static str,
+    },
+
+    #[error(transparent)]
+    Api(#[from] ApiError),
+}
+
+impl SamplingConfiguration {
+    /// This configuration, with the unset values taken from `defaults`.
+    pub fn merge_with_default(&self, defaults: Option<&SamplingConfiguration>) -> Self {
+        let defaults = defaults.cloned().unwrap_or_default();
+        Self {
+            generate_args: self.generate_args.merge(&defaults.generate_args),
+            system_prompt: self.system_prompt.clone().or(defaults.system_prompt),
+            assistant_name: self.assistant_name.clone().or(defaults.assistant_name),
+            user_name: self.user_name.clone().or(defaults.user_name),
+        }
+    }
+
+    /// `prompt` as a turn of the user, followed by the name of the assistant and preceded by the
+    /// system prompt, if any. Returns `None` if user or assistant name are missing.
+    pub fn format_prompt(&self, prompt: &str) -> Option<String> {
+        let user_name = self.user_name.as_ref()?;
+        let assistant_name = self.assistant_name.as_ref()?;
+        let mut text = String::new();
+        if let Some(system_prompt) = &self.system_prompt {
+            writeln!(text, "{system_prompt}").unwrap();
+        }
+        write!(text, "{user_name} {prompt}\n{assistant_name}").unwrap();
+        Some(text)
+    }
+
+    /// Request completing `prompt` with `model`, formatted with [`Self::format_prompt`]. The
+    /// completion stops when the model starts a turn of the user.
+    pub fn completion_request(
+        &self,
+        model: impl Into<String>,
+        prompt: &str,
+    ) -> Option<CompletionRequest> {
+        let text = self.format_prompt(prompt)?;
+        let mut req =
+            CompletionRequest::new(model, Prompt::from_text(text), DEFAULT_MAXIMUM_TOKENS);
+        req.stop_sequences = self.user_name.clone().map(|user_name| vec![user_name]);
+        self.generate_args.apply_to(&mut req);
+        Some(req)
+    }
+}
+
+/// Outputs of one configuration for a prompt.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PromptResult {
+    pub sampling_config: String,
+    pub sampling_params: GenerationArgs,
+    pub outputs: Vec<String>,
+}
+
+/// Outputs of all configurations for a prompt.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SamplingResult {
+    pub prompt: String,
+    pub results: Vec<PromptResult>,
+}
+
+impl SamplingResult {
+    pub fn new(prompt: impl Into<String>) -> Self {
+        Self {
+            prompt: prompt.into(),
+            results: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SamplingReport {
+    pub model_name: String,
+    pub date: String,
+
+    /// Settings the report was created with, e.g. command line arguments.
+    pub args: serde_json::Value,
+
+    pub prompts: Vec<SamplingResult>,
+}
+
+impl SamplingReport {
+    pub fn new(
+        model_name: impl Into<String>,
+        date: impl Into<String>,
+        args: serde_json::Value,
+    ) -> Self {
+        Self {
+            model_name: model_name.into(),
+            date: date.into(),
+            args,
+            prompts: Vec::new(),
+        }
+    }
+
+    /// File name like `2023-12-01_luminous-base_sampling_default.json` from the date of the
+    /// report, the model name and the name of the configuration file.
+    pub fn default_file_name(&self, config_path: impl AsRef<Path>) -> String {
+        let model_name: String = self
+            .model_name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let date = self.date.split('T').next().unwrap_or_default();
+        let config_name = config_path
+            .as_ref()
+            .file_stem()
+            .map(|stem| stem.to_string_lossy())
+            .unwrap_or_default();
+        format!("{date}_{model_name}_{config_name}.json")
+    }
+
+    /// Writes the report as pretty printed JSON.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), ApiError> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Reads a JSON object of named sampling configurations.
+pub fn read_configurations(path: impl AsRef<Path>) -> Result<NamedConfigurations, ApiError> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+/// Reads prompts from a JSON lines file with one string per line. Other lines are skipped.
+pub fn read_prompts(path: impl AsRef<Path>) -> Result<Vec<String>, ApiError> {
+    let mut prompts = Vec::new();
+    for line in fs::read_to_string(path)?.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let serde_json::Value::String(prompt) = serde_json::from_str(line)? {
+            prompts.push(prompt);
+        }
+    }
+    Ok(prompts)
+}
+
+/// Completes `prompt` with every configuration except [`DEFAULT_CONFIGURATION`], which provides
+/// the defaults for the others.
+pub async fn sample_prompt(
+    client: &Client,
+    model: &str,
+    configurations: &NamedConfigurations,
+    prompt: &str,
+    nice: Option<bool>,
+) -> Result<SamplingResult, ReportError> {
+    let defaults = configurations.get(DEFAULT_CONFIGURATION);
+    let mut result = SamplingResult::new(prompt);
+    for (name, configuration) in configurations {
+        if name == DEFAULT_CONFIGURATION {
+            continue;
+        }
+        let configuration = configuration.merge_with_default(defaults);
+        let req = configuration
+            .completion_request(model, prompt)
+            .ok_or_else(|| ReportError::MissingName {
+                configuration: name.clone(),
+                field: if configuration.user_name.is_none() {
+                    "user_name"
+                } else {
+                    "assistant_name"
+                },
+            })?;
+        let response = client.completion(&req, nice).await?;
+        result.results.push(PromptResult {
+            sampling_config: name.clone(),
+            sampling_params: configuration.generate_args,
+            outputs: response
+                .completions
+                .into_iter()
+                .map(|output| output.completion)
+                .collect(),
+        });
+    }
+    Ok(result)
+}
@@ -0,0 +1,86 @@
+use super::completion::Hosting;
+use super::document::Document;
+use crate::impl_builder_methods;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Debug, Default)]
+pub struct SearchRequest {
+    /// Name of the model to use, e.g. `luminous-base`.
+    pub model: String,
+
+    /// Determines in which datacenters the request may be processed.
+    /// You can either set the parameter to "aleph-alpha" or omit it (defaulting to None).
+    ///
+    /// Not setting this value, or setting it to None, gives us maximal flexibility in processing your request in our
+    /// own datacenters and on servers hosted with other providers. Choose this option for maximal availability.
+    ///
+    /// Setting it to "aleph-alpha" allows us to only process the request in our own datacenters.
+    /// Choose this option for maximal data privacy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hosting: Option<Hosting>,
+
+    /// The text to search for.
+    pub query: String,
+
+    /// The documents to search in.
+    pub documents: Vec<Document>,
+
+    /// The maximum number of results to return.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_results: Option<u32>,
+
+    /// Results with a lower score are dropped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_score: Option<f64>,
+}
+
+impl SearchRequest {
+    pub fn new(
+        model: impl Into<String>,
+        query: impl Into<String>,
+        documents: Vec<Document>,
+    ) -> Self {
+        Self {
+            model: model.into(),
+            query: query.into(),
+            documents,
+            ..Self::default()
+        }
+    }
+}
+
+impl_builder_methods!(
+    SearchRequest,
+    hosting: Hosting,
+    max_results: u32,
+    min_score: f64
+);
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    /// Index of the matching document in [`SearchRequest::documents`].
+    #[serde(rename = "index")]
+    pub document_index: usize,
+
+    /// The part of the document matching the query.
+    pub section: String,
+
+    /// Similarity of the section to the query, higher is better.
+    pub score: f64,
+}
+
+impl SearchResult {
+    /// The matching document among the documents of `req`, the request this result answers.
+    pub fn document<'a>(&self, req: &'a SearchRequest) -> Option<&'a Document> {
+        req.documents.get(self.document_index)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SearchResponse {
+    /// Model name and version (if any) of the used model for inference.
+    pub model_version: String,
+
+    /// The matching sections, best first.
+    pub results: Vec<SearchResult>,
+}
@@ -0,0 +1,71 @@
+//! Similarity measures for embeddings.
+//!
+//! The functions accumulate in [`LANES`] independent partial sums, which lets the compiler
+//! vectorize the loops (e.g. with SSE/AVX or NEON). This matters for the 5120 dimensional
+//! uncompressed embeddings. Slices of different length are compared over the shorter length.
+
+/// Number of partial sums accumulated in parallel.
+pub const LANES: usize = 8;
+
+fn accumulate(a: &[f32], b: &[f32], f: impl Fn(f32, f32) -> f32) -> f32 {
+    let len = a.len().min(b.len());
+    let (a, b) = (&a[..len], &b[..len]);
+    let mut sums = [0.0f32; LANES];
+    let chunks_a = a.chunks_exact(LANES);
+    let chunks_b = b.chunks_exact(LANES);
+    let rest: f32 = chunks_a
+        .remainder()
+        .iter()
+        .zip(chunks_b.remainder())
+        .map(|(x, y)| f(*x, *y))
+        .sum();
+    for (chunk_a, chunk_b) in chunks_a.zip(chunks_b) {
+        for lane in 0..LANES {
+            sums[lane] += f(chunk_a[lane], chunk_b[lane]);
+        }
+    }
+    sums.iter().sum::<f32>() + rest
+}
+
+/// Dot product of `a` and `b`.
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    accumulate(a, b, |x, y| x * y)
+}
+
+/// Euclidean length of `a`.
+pub fn norm(a: &[f32]) -> f32 {
+    dot(a, a).sqrt()
+}
+
+/// Cosine of the angle between `a` and `b`, `0.0` if one of them is the zero vector.
+///
+/// ```
+///use aleph_alpha_api::similarity::cosine_similarity;
+///
+///assert_eq!(cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]), 1.0);
+///assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 3.0]), 0.0);
+/// ```
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot(a, b) / norms
+    }
+}
+
+/// Euclidean distance between `a` and `b`.
+pub fn euclidean_distance(a: &[f32], b: &[f32]) -> f32 {
+    accumulate(a, b, |x, y| (x - y) * (x - y)).sqrt()
+}
+
+/// Scales `a` to unit length, so the dot product equals the cosine similarity. The zero vector is
+/// left unchanged.
+pub fn normalize_in_place(a: &mut [f32]) {
+    let norm = norm(a);
+    if norm > 0.0 {
+        for value in a {
+            *value /= norm;
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use super::completion::Hosting;
+use super::document::Document;
+use crate::impl_builder_methods;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Debug)]
+pub struct SummarizationRequest {
+    /// Name of the model to use, e.g. `luminous-extended`.
+    pub model: String,
+
+    /// Determines in which datacenters the request may be processed.
+    /// You can either set the parameter to "aleph-alpha" or omit it (defaulting to None).
+    ///
+    /// Not setting this value, or setting it to None, gives us maximal flexibility in processing your request in our
+    /// own datacenters and on servers hosted with other providers. Choose this option for maximal availability.
+    ///
+    /// Setting it to "aleph-alpha" allows us to only process the request in our own datacenters.
+    /// Choose this option for maximal data privacy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hosting: Option<Hosting>,
+
+    /// The document to summarize.
+    pub document: Document,
+
+    /// We continually research optimal ways to work with our models. By default, we apply these
+    /// optimizations to both your query, documents, and answers for you. Set this to `true` to
+    /// turn them off, e.g. for experimenting with your own prompts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disable_optimizations: Option<bool>,
+}
+
+impl SummarizationRequest {
+    pub fn new(model: impl Into<String>, document: Document) -> Self {
+        Self {
+            model: model.into(),
+            hosting: None,
+            document,
+            disable_optimizations: None,
+        }
+    }
+}
+
+impl_builder_methods!(
+    SummarizationRequest,
+    hosting: Hosting,
+    disable_optimizations: bool
+);
+
+#[derive(Deserialize, Debug)]
+pub struct SummarizationResponse {
+    /// Model name and version (if any) of the used model for inference.
+    pub model_version: String,
+
+    /// The summary of the document.
+    pub summary: String,
+}
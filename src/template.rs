@@ -0,0 +1,153 @@
+use super::completion::{Modality, Prompt};
+use serde::{Deserialize, Serialize};
+use std::{borrow::Borrow, collections::HashMap, hash::Hash};
+use thiserror::Error as ThisError;
+
+const PLACEHOLDER_START: &str = "{{";
+const PLACEHOLDER_END: &str = "}}";
+
+/// Value which is substituted for a placeholder of a [`PromptTemplate`].
+#[derive(Debug, Clone)]
+pub enum TemplateValue {
+    /// Text is inserted in place of the placeholder.
+    Text(String),
+
+    /// A prompt item (e.g. an image) which splits the surrounding text into separate items.
+    Modality(Modality),
+}
+
+impl From<&str> for TemplateValue {
+    fn from(text: &str) -> Self {
+        TemplateValue::Text(text.to_owned())
+    }
+}
+
+impl From<String> for TemplateValue {
+    fn from(text: String) -> Self {
+        TemplateValue::Text(text)
+    }
+}
+
+impl From<Modality> for TemplateValue {
+    fn from(item: Modality) -> Self {
+        TemplateValue::Modality(item)
+    }
+}
+
+/// A prompt with named placeholders like `{{text}}`.
+///
+/// Templates serialize as plain strings, so they can be stored in configuration files:
+/// ```
+///use aleph_alpha_api::{PromptTemplate, TemplateValue};
+///use std::collections::HashMap;
+///
+///let template = PromptTemplate::new("Summarize: {{text}}");
+///let vars = HashMap::from([("text", TemplateValue::from("An apple a day ..."))]);
+///let prompt = template.render(&vars).unwrap();
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct PromptTemplate {
+    template: String,
+}
+
+enum Segment<'a> {
+    Literal(&'a str),
+    Placeholder(&'a str),
+}
+
+impl PromptTemplate {
+    pub fn new(template: impl Into<String>) -> Self {
+        Self {
+            template: template.into(),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.template
+    }
+
+    /// Names of all placeholders in order of their occurrence.
+    pub fn variables(&self) -> Result<Vec<&str>, TemplateError> {
+        Ok(self
+            .segments()?
+            .into_iter()
+            .filter_map(|segment| match segment {
+                Segment::Placeholder(name) => Some(name),
+                Segment::Literal(_) => None,
+            })
+            .collect())
+    }
+
+    /// Substitutes all placeholders. Text values are inserted inline, while other modalities
+    /// become items of their own between the surrounding text items.
+    pub fn render<K>(&self, vars: &HashMap<K, TemplateValue>) -> Result<Prompt, TemplateError>
+    where
+        K: Borrow<str> + Hash + Eq,
+    {
+        let mut items = Vec::new();
+        let mut text = String::new();
+        for segment in self.segments()? {
+            match segment {
+                Segment::Literal(literal) => text.push_str(literal),
+                Segment::Placeholder(name) => match vars.get(name) {
+                    Some(TemplateValue::Text(value)) => text.push_str(value),
+                    Some(TemplateValue::Modality(item)) => {
+                        if !text.is_empty() {
+                            items.push(Modality::from_text(std::mem::take(&mut text), None));
+                        }
+                        items.push(item.clone());
+                    }
+                    None => return Err(TemplateError::MissingVariable(name.to_owned())),
+                },
+            }
+        }
+        if !text.is_empty() {
+            items.push(Modality::from_text(text, None));
+        }
+        Ok(Prompt::from_vec(items))
+    }
+
+    fn segments(&self) -> Result<Vec<Segment<'_>>, TemplateError> {
+        let mut segments = Vec::new();
+        let mut position = 0;
+        while let Some(start) = self.template[position..].find(PLACEHOLDER_START) {
+            let start = position + start;
+            let name_start = start + PLACEHOLDER_START.len();
+            let end = self.template[name_start..]
+                .find(PLACEHOLDER_END)
+                .map(|end| name_start + end)
+                .ok_or(TemplateError::UnclosedPlaceholder(start))?;
+            let name = self.template[name_start..end].trim();
+            if name.is_empty() {
+                return Err(TemplateError::EmptyPlaceholder(start));
+            }
+            if start > position {
+                segments.push(Segment::Literal(&self.template[position..start]));
+            }
+            segments.push(Segment::Placeholder(name));
+            position = end + PLACEHOLDER_END.len();
+        }
+        if position < self.template.len() {
+            segments.push(Segment::Literal(&self.template[position..]));
+        }
+        Ok(segments)
+    }
+}
+
+impl From<&str> for PromptTemplate {
+    fn from(template: &str) -> Self {
+        Self::new(template)
+    }
+}
+
+/// Errors returned when rendering a [`PromptTemplate`].
+#[derive(ThisError, Debug, PartialEq)]
+pub enum TemplateError {
+    #[error("Placeholder starting at byte {0} is not closed.")]
+    UnclosedPlaceholder(usize),
+    #[error("Placeholder starting at byte {0} has no name.")]
+    EmptyPlaceholder(usize),
+    #[error("No value given for template variable '{0}'.")]
+    MissingVariable(String),
+}
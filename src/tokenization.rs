@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize)]
+#[derive(Serialize, Debug, Default, Clone)]
 pub struct TokenizationRequest {
     /// Name of the model tasked with completing the prompt. E.g. `luminous-base`.
     pub model: String,
@@ -12,6 +12,28 @@ pub struct TokenizationRequest {
     pub token_ids: bool,
 }
 
+impl TokenizationRequest {
+    /// Request for both text tokens and token ids of `prompt`.
+    pub fn new(model: impl Into<String>, prompt: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            prompt: prompt.into(),
+            tokens: true,
+            token_ids: true,
+        }
+    }
+
+    pub fn tokens(mut self, tokens: bool) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    pub fn token_ids(mut self, token_ids: bool) -> Self {
+        self.token_ids = token_ids;
+        self
+    }
+}
+
 #[derive(Deserialize)]
 pub struct TokenizationResponse {
     pub tokens: Option<Vec<String>>,
@@ -0,0 +1,182 @@
+//! On-disk cache for tokenizer vocabularies, so short-lived processes like command line tools do
+//! not download them on every run. Requires the `tokenizer-cache` feature.
+//!
+//! ```no_run
+//!use aleph_alpha_api::{error::ApiError, tokenizer_cache::TokenizerCache, Client, LUMINOUS_BASE};
+//!
+//!async fn count_tokens(client: &Client, text: &str) -> Result<usize, ApiError> {
+//!    let cache = TokenizerCache::open_default()?;
+//!    let tokenizer = client.get_tokenizer_cached(LUMINOUS_BASE, &cache, false).await?;
+//!    Ok(tokenizer.encode(text, false)?.len())
+//!}
+//! ```
+use super::cache_file::write_atomically;
+use super::client::Client;
+use super::error::ApiError;
+use super::http::CacheValidators;
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use std::{fs, io, path::PathBuf, sync::Arc};
+use tokenizers::Tokenizer;
+use tokio::sync::OnceCell;
+
+/// Directory of cached tokenizer vocabularies, one file per model.
+pub struct TokenizerCache {
+    dir: PathBuf,
+}
+
+impl TokenizerCache {
+    /// Opens the cache in `dir`, creating the directory if necessary.
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self, ApiError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Opens the cache in the user's cache directory, e.g.
+    /// `$XDG_CACHE_HOME/aleph-alpha-api/tokenizers` on Linux.
+    pub fn open_default() -> Result<Self, ApiError> {
+        let dir = dirs::cache_dir().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "No cache directory for this user.")
+        })?;
+        Self::open(dir.join("aleph-alpha-api").join("tokenizers"))
+    }
+
+    fn path(&self, model: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", Self::file_stem(model)))
+    }
+
+    fn validators_path(&self, model: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}.validators.json", Self::file_stem(model)))
+    }
+
+    /// The model name with unsafe characters replaced, followed by a hash of the name, so names
+    /// only differing in replaced characters (or in case) do not share a file.
+    fn file_stem(model: &str) -> String {
+        let sanitized: String = model
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+                _ => '_',
+            })
+            .collect();
+        let hash = format!("{:x}", Sha256::digest(model.as_bytes()));
+        format!("{sanitized}-{}", &hash[..16])
+    }
+
+    pub fn get(&self, model: &str) -> Result<Option<Bytes>, ApiError> {
+        match fs::read(self.path(model)) {
+            Ok(bytes) => Ok(Some(bytes.into())),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn put(&self, model: &str, vocabulary: &[u8]) -> Result<(), ApiError> {
+        write_atomically(&self.path(model), vocabulary)
+    }
+
+    /// `ETag` and `Last-Modified` of the cached vocabulary of `model`, empty if unknown.
+    pub fn validators(&self, model: &str) -> Result<CacheValidators, ApiError> {
+        match fs::read(self.validators_path(model)) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(CacheValidators::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn put_validators(
+        &self,
+        model: &str,
+        validators: &CacheValidators,
+    ) -> Result<(), ApiError> {
+        write_atomically(
+            &self.validators_path(model),
+            &serde_json::to_vec(validators)?,
+        )
+    }
+
+    /// Removes the vocabulary of `model`, so it is downloaded again on next use.
+    pub fn remove(&self, model: &str) -> Result<(), ApiError> {
+        for path in [self.path(model), self.validators_path(model)] {
+            match fs::remove_file(path) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Client {
+    /// Like [`Client::get_tokenizer`], but the vocabulary is read from `cache` if present and
+    /// added to it after a download. With `refresh` a cached vocabulary is validated with a
+    /// conditional request (`If-None-Match`/`If-Modified-Since`) and only downloaded again if it
+    /// changed. A cached vocabulary which cannot be parsed is removed and downloaded again.
+    pub async fn get_tokenizer_cached(
+        &self,
+        model: &str,
+        cache: &TokenizerCache,
+        refresh: bool,
+    ) -> Result<Arc<Tokenizer>, ApiError> {
+        if refresh {
+            let tokenizer = Arc::new(self.load_tokenizer(model, cache, true).await?);
+            self.tokenizers.lock().await.insert(
+                model.to_owned(),
+                Arc::new(OnceCell::from(tokenizer.clone())),
+            );
+            return Ok(tokenizer);
+        }
+        let cell = self.tokenizer_cell(model).await;
+        let tokenizer = cell
+            .get_or_try_init(|| async {
+                Ok::<_, ApiError>(Arc::new(self.load_tokenizer(model, cache, false).await?))
+            })
+            .await?;
+        Ok(tokenizer.clone())
+    }
+
+    async fn load_tokenizer(
+        &self,
+        model: &str,
+        cache: &TokenizerCache,
+        refresh: bool,
+    ) -> Result<Tokenizer, ApiError> {
+        let cached = match cache.get(model)? {
+            Some(vocabulary) => match Tokenizer::from_bytes(&vocabulary) {
+                Ok(tokenizer) => Some(tokenizer),
+                Err(_) => {
+                    cache.remove(model)?;
+                    None
+                }
+            },
+            None => None,
+        };
+        match cached {
+            Some(tokenizer) if !refresh => Ok(tokenizer),
+            _ => {
+                let validators = match cached {
+                    Some(_) => cache.validators(model)?,
+                    None => CacheValidators::default(),
+                };
+                match self
+                    .get_tokenizer_binary_if_modified(model, &validators)
+                    .await?
+                {
+                    Some((vocabulary, validators)) => {
+                        let tokenizer = Tokenizer::from_bytes(&vocabulary)?;
+                        cache.put(model, &vocabulary)?;
+                        cache.put_validators(model, &validators)?;
+                        Ok(tokenizer)
+                    }
+                    None => cached.ok_or_else(|| {
+                        ApiError::UnexpectedResponse(
+                            "Tokenizer not modified, but not cached either.".into(),
+                        )
+                    }),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use super::completion::CompletionResponse;
+use serde::Serialize;
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+/// Usage of a single model, see [`UsageReport`].
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct ModelUsage {
+    /// Number of requests, including failed ones.
+    pub requests: usize,
+    pub failed_requests: usize,
+
+    /// Prompt tokens as reported by the API, see
+    /// [`CompletionResponse::num_tokens_prompt_total`].
+    pub prompt_tokens: u64,
+
+    /// Generated tokens as reported by the API, see
+    /// [`CompletionResponse::num_tokens_generated`].
+    pub completion_tokens: u64,
+
+    /// Sum of the latencies of all requests.
+    pub total_latency: Duration,
+    pub max_latency: Duration,
+}
+
+impl ModelUsage {
+    pub fn mean_latency(&self) -> Duration {
+        if self.requests == 0 {
+            Duration::ZERO
+        } else {
+            self.total_latency / self.requests as u32
+        }
+    }
+
+    fn add(&mut self, other: &ModelUsage) {
+        self.requests += other.requests;
+        self.failed_requests += other.failed_requests;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_latency += other.total_latency;
+        self.max_latency = self.max_latency.max(other.max_latency);
+    }
+}
+
+/// Snapshot of the completion requests of a client by model, see
+/// [`crate::Client::with_usage_tracking`]. Requests to other endpoints than `/complete` are not
+/// included.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct UsageReport {
+    pub models: HashMap<String, ModelUsage>,
+}
+
+impl UsageReport {
+    /// Usage summed over all models.
+    pub fn total(&self) -> ModelUsage {
+        let mut total = ModelUsage::default();
+        for usage in self.models.values() {
+            total.add(usage);
+        }
+        total
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct UsageTracker {
+    report: Mutex<UsageReport>,
+}
+
+impl UsageTracker {
+    pub(crate) fn record(
+        &self,
+        model: &str,
+        latency: Duration,
+        response: Option<&CompletionResponse>,
+    ) {
+        let mut report = self.report.lock().unwrap();
+        let usage = report.models.entry(model.to_owned()).or_default();
+        usage.requests += 1;
+        usage.total_latency += latency;
+        usage.max_latency = usage.max_latency.max(latency);
+        match response {
+            Some(response) => {
+                usage.prompt_tokens += response.num_tokens_prompt_total.unwrap_or_default() as u64;
+                usage.completion_tokens += response.num_tokens_generated.unwrap_or_default() as u64;
+            }
+            None => usage.failed_requests += 1,
+        }
+    }
+
+    pub(crate) fn report(&self) -> UsageReport {
+        self.report.lock().unwrap().clone()
+    }
+}
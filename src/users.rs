@@ -0,0 +1,55 @@
+use crate::impl_builder_methods;
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+/// Settings and credit balance of the user owning the API token.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserDetail {
+    pub id: i32,
+    pub email: String,
+    pub role: String,
+
+    /// Credits left on the account. Also parsed if the API sends it as a string.
+    #[serde(deserialize_with = "number_or_string")]
+    pub credits_remaining: f64,
+
+    /// Whether the user is invoiced instead of buying credits in advance.
+    pub invoice_allowed: bool,
+
+    /// An email is sent to the user once the remaining credits drop below this threshold.
+    pub out_of_credits_threshold: i32,
+
+    /// Version of the terms of service the user agreed to, if any.
+    #[serde(default)]
+    pub terms_of_service_version: Option<String>,
+}
+
+/// Deserializes a number, which may also be sent as a string like `"12.5"`.
+fn number_or_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString {
+        Number(f64),
+        String(String),
+    }
+
+    match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Number(number) => Ok(number),
+        NumberOrString::String(string) => string.trim().parse().map_err(de::Error::custom),
+    }
+}
+
+/// Changes to the settings of the user, see [`crate::Client::change_user_settings`]. Only the
+/// fields which are set are changed.
+#[derive(Serialize, Debug, Default)]
+pub struct UserChange {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub out_of_credits_threshold: Option<i32>,
+}
+
+impl UserChange {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl_builder_methods!(UserChange, out_of_credits_threshold: i32);
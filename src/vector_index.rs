@@ -0,0 +1,118 @@
+use super::similarity::{dot, norm, normalize_in_place};
+use std::cmp::Ordering;
+
+/// In-memory collection of embeddings, e.g. of [`crate::SemanticEmbeddingResponse`]s, which
+/// answers nearest neighbour queries by cosine similarity with a linear scan.
+///
+/// ```
+///use aleph_alpha_api::VectorIndex;
+///
+///let mut index = VectorIndex::new();
+///index.insert("apple", vec![1.0, 0.0]);
+///index.insert("pear", vec![0.7, 0.7]);
+///index.insert("car", vec![0.0, 1.0]);
+///
+///let hits = index.top_k(&[1.0, 0.1], 2);
+///assert_eq!(hits[0].0, &"apple");
+///assert_eq!(hits[1].0, &"pear");
+/// ```
+#[derive(Debug, Clone)]
+pub struct VectorIndex<T> {
+    entries: Vec<Entry<T>>,
+    normalize_on_insert: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Entry<T> {
+    id: T,
+    embedding: Vec<f32>,
+    norm: f32,
+}
+
+impl<T> VectorIndex<T> {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            normalize_on_insert: false,
+        }
+    }
+
+    /// Scale embeddings to unit length when they are inserted, see
+    /// [`normalize_in_place`]. Useful when mixing normalized and raw embeddings, e.g. to return
+    /// comparable vectors from [`VectorIndex::get`].
+    pub fn normalize_on_insert(mut self, normalize: bool) -> Self {
+        self.normalize_on_insert = normalize;
+        self
+    }
+
+    pub fn insert(&mut self, id: T, mut embedding: Vec<f32>) {
+        if self.normalize_on_insert {
+            normalize_in_place(&mut embedding);
+        }
+        let norm = norm(&embedding);
+        self.entries.push(Entry {
+            id,
+            embedding,
+            norm,
+        });
+    }
+
+    /// Stored embedding of the `index`-th inserted entry.
+    pub fn get(&self, index: usize) -> Option<(&T, &[f32])> {
+        self.entries
+            .get(index)
+            .map(|entry| (&entry.id, entry.embedding.as_slice()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The `k` entries most similar to `query` with their cosine similarity, most similar first.
+    /// Entries with a different dimension than `query` are skipped.
+    pub fn top_k(&self, query: &[f32], k: usize) -> Vec<(&T, f32)> {
+        let query_norm = norm(query);
+        let mut hits: Vec<(&T, f32)> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.embedding.len() == query.len())
+            .map(|entry| {
+                let similarity = if entry.norm == 0.0 || query_norm == 0.0 {
+                    0.0
+                } else {
+                    dot(&entry.embedding, query) / (entry.norm * query_norm)
+                };
+                (&entry.id, similarity)
+            })
+            .collect();
+        hits.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        hits.truncate(k);
+        hits
+    }
+}
+
+impl<T> Default for VectorIndex<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Extend<(T, Vec<f32>)> for VectorIndex<T> {
+    fn extend<I: IntoIterator<Item = (T, Vec<f32>)>>(&mut self, iter: I) {
+        for (id, embedding) in iter {
+            self.insert(id, embedding);
+        }
+    }
+}
+
+impl<T> FromIterator<(T, Vec<f32>)> for VectorIndex<T> {
+    fn from_iter<I: IntoIterator<Item = (T, Vec<f32>)>>(iter: I) -> Self {
+        let mut index = Self::new();
+        index.extend(iter);
+        index
+    }
+}
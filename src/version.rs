@@ -0,0 +1,242 @@
+use std::{cmp::Ordering, collections::HashMap, fmt, str::FromStr};
+use thiserror::Error as ThisError;
+
+/// Version of a deployment of the API, as reported by [`crate::Client::get_version`]. Versions
+/// look like `1.16.0`; missing minor or patch numbers count as zero, a leading `v` and build
+/// metadata after `+` are ignored.
+///
+/// ```
+///use aleph_alpha_api::ApiVersion;
+///
+///let version: ApiVersion = "v1.4.0-rc1".parse().unwrap();
+///assert_eq!(version.pre.as_deref(), Some("rc1"));
+///assert!(version < ApiVersion::new(1, 4, 0));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ApiVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// Pre-release suffix after `-`, e.g. `rc1`. Pre-releases order before the release.
+    pub pre: Option<String>,
+}
+
+#[derive(ThisError, Debug, Clone, PartialEq)]
+#[error("'{0}' is not a valid API version.")]
+pub struct ParseVersionError(pub String);
+
+impl ApiVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+            pre: None,
+        }
+    }
+}
+
+impl FromStr for ApiVersion {
+    type Err = ParseVersionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseVersionError(s.to_owned());
+        let trimmed = s.trim().trim_matches('"');
+        let trimmed = trimmed.strip_prefix('v').unwrap_or(trimmed);
+        let trimmed = trimmed.split('+').next().unwrap_or_default();
+        let (numbers, pre) = match trimmed.split_once('-') {
+            Some((numbers, pre)) if !pre.is_empty() => (numbers, Some(pre.to_owned())),
+            Some(_) => return Err(error()),
+            None => (trimmed, None),
+        };
+        let mut parts = numbers.split('.');
+        let mut next = |required: bool| match parts.next() {
+            Some(part) => part.parse::<u32>().map_err(|_| error()),
+            None if required => Err(error()),
+            None => Ok(0),
+        };
+        let version = Self {
+            major: next(true)?,
+            minor: next(false)?,
+            patch: next(false)?,
+            pre,
+        };
+        if parts.next().is_some() {
+            return Err(error());
+        }
+        Ok(version)
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(pre) = &self.pre {
+            write!(f, "-{pre}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Ord for ApiVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (&self.pre, &other.pre) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => a.cmp(b),
+            })
+    }
+}
+
+impl PartialOrd for ApiVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A feature of the API which is not available on every deployment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Feature {
+    /// `/explain` endpoint.
+    Explanation,
+    /// `/batch_semantic_embed` endpoint.
+    BatchSemanticEmbed,
+    /// `/instructable_embed` endpoint.
+    InstructableEmbed,
+    /// Steering concepts for completions.
+    Steering,
+    /// `sequence_penalty` and its related parameters for completions.
+    SequencePenalty,
+    /// `raw_completion` for completions.
+    RawCompletion,
+    /// `control_log_additive` for completions, explanations and evaluations.
+    ControlLogAdditive,
+    /// `logit_bias` for completions.
+    LogitBias,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 8] = [
+        Feature::Explanation,
+        Feature::BatchSemanticEmbed,
+        Feature::InstructableEmbed,
+        Feature::Steering,
+        Feature::SequencePenalty,
+        Feature::RawCompletion,
+        Feature::ControlLogAdditive,
+        Feature::LogitBias,
+    ];
+
+    /// Path of the endpoint providing the feature, for features which are endpoints of their own.
+    /// Support of these is detected by [`crate::Client::capabilities`].
+    pub fn endpoint(self) -> Option<&'static str> {
+        match self {
+            Feature::Explanation => Some("/explain"),
+            Feature::BatchSemanticEmbed => Some("/batch_semantic_embed"),
+            Feature::InstructableEmbed => Some("/instructable_embed"),
+            _ => None,
+        }
+    }
+}
+
+/// A parameter set in a request, which the targeted deployment does not know and would silently
+/// ignore, or which it is not known to support.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsupportedParameter {
+    pub parameter: &'static str,
+    pub feature: Feature,
+    /// First API version supporting the feature, see [`ApiCapabilities::minimum_version`]. `None`
+    /// if support of the feature is unknown.
+    pub since: Option<ApiVersion>,
+}
+
+impl fmt::Display for UnsupportedParameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.since {
+            Some(since) => write!(f, "{} requires API version {since}", self.parameter),
+            None => write!(f, "support of {} is unknown", self.parameter),
+        }
+    }
+}
+
+/// Features available on a deployment, see [`crate::Client::capabilities`].
+///
+/// The API does not publish the versions introducing its features. Support of features with an
+/// endpoint of their own (see [`Feature::endpoint`]) is detected by probing the endpoint, support
+/// of other features is known if their first version is set with
+/// [`ApiCapabilities::with_minimum_version`]. For all other features,
+/// [`ApiCapabilities::supports`] returns `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiCapabilities {
+    pub version: ApiVersion,
+    minimum_versions: HashMap<Feature, ApiVersion>,
+    detected: HashMap<Feature, bool>,
+}
+
+impl ApiCapabilities {
+    pub fn new(version: ApiVersion) -> Self {
+        Self {
+            version,
+            minimum_versions: HashMap::new(),
+            detected: HashMap::new(),
+        }
+    }
+
+    /// Records whether the deployment supports `feature`, e.g. as detected by probing its
+    /// endpoint. Takes precedence over the minimum version of `feature`.
+    pub fn with_support(mut self, feature: Feature, supported: bool) -> Self {
+        self.detected.insert(feature, supported);
+        self
+    }
+
+    /// Sets the first API version supporting `feature`, replacing a previous one.
+    pub fn with_minimum_version(mut self, feature: Feature, version: ApiVersion) -> Self {
+        self.minimum_versions.insert(feature, version);
+        self
+    }
+
+    /// First API version supporting `feature`, if known.
+    pub fn minimum_version(&self, feature: Feature) -> Option<&ApiVersion> {
+        self.minimum_versions.get(&feature)
+    }
+
+    /// Whether the deployment supports `feature`, or `None` if this is neither detected nor
+    /// known from the minimum version of `feature`.
+    pub fn supports(&self, feature: Feature) -> Option<bool> {
+        match self.detected.get(&feature) {
+            Some(&supported) => Some(supported),
+            None => self
+                .minimum_version(feature)
+                .map(|since| &self.version >= since),
+        }
+    }
+
+    /// `Some(parameter)` if `parameter` is set, but `feature` is not known to be supported.
+    pub(crate) fn check(
+        &self,
+        parameter: &'static str,
+        is_set: bool,
+        feature: Feature,
+    ) -> Option<UnsupportedParameter> {
+        if !is_set || self.supports(feature) == Some(true) {
+            return None;
+        }
+        Some(UnsupportedParameter {
+            parameter,
+            feature,
+            since: self.minimum_version(feature).cloned(),
+        })
+    }
+
+    /// All features known to be supported by the deployment.
+    pub fn features(&self) -> Vec<Feature> {
+        Feature::ALL
+            .into_iter()
+            .filter(|&feature| self.supports(feature) == Some(true))
+            .collect()
+    }
+}
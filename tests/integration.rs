@@ -1,11 +1,41 @@
+// Tests written before clippy was enforced keep their original form.
+#![allow(
+    clippy::len_zero,
+    clippy::redundant_field_names,
+    clippy::unnecessary_get_then_check
+)]
+
 use aleph_alpha_api::{
-    self, BatchSemanticEmbeddingRequest, Client, CompletionRequest, DetokenizationRequest,
-    EmbeddingRepresentation, EmbeddingRequest, EvaluationRequest, ExplanationRequest, Modality,
-    Prompt, SemanticEmbeddingRequest, TargetGranularity, TokenizationRequest, LUMINOUS_BASE,
+    self, aggregate_segments, analysis, bench, chunking,
+    error::FetchImageError,
+    error::{ApiError, BudgetError, ValidationError},
+    eval, export, fits_context, heatmap, image_processing,
+    image_processing::{ImageEncoding, ImageOptions, ResizeStrategy},
+    jobs, offline, pricing,
+    quantization::{F16Embedding, Int8Embedding},
+    rag, report, similarity, Aggregation, ApiCapabilities, ApiVersion,
+    BatchSemanticEmbeddingRequest, BoundingBox, Client, CompletionRequest, CompletionResponse,
+    CompressToSize, ControlTokenOverlap, Conversation, CreateApiTokenRequest, Crop,
+    DetokenizationRequest, Document, EmbedDocumentOptions, EmbedStreamOptions,
+    EmbeddingRepresentation, EmbeddingRequest, EmbeddingResponse, EvaluationRequest,
+    EvaluationResult, ExplanationRequest, ExplanationResponse, Feature, Hosting, ImageControl,
+    ImageData, InstructableEmbeddingRequest, Layer, LoadDocumentError, LogProbsSetting, Modality,
+    Model, ModelCapabilities, ModelInfo, ModelRegistry, OutputPipeline, Pooling, Prompt,
+    PromptGranularity, PromptTemplate, QaRequest, QaResponse, RequestOptions, RetryPolicy,
+    ScoreAggregation, ScoredRect, ScoredSegment, ScoredSpan, SearchRequest, SearchResponse,
+    SemanticEmbeddingRequest, SummarizationRequest, TargetGranularity, TemplateValue, TextControl,
+    TextUnit, TokenControl, TokenizationRequest, TruncationStrategy, UserChange, VectorIndex,
+    IMAGE_TOKEN_COUNT, LUMINOUS_BASE, LUMINOUS_BASE_CONTROL, LUMINOUS_EXTENDED, LUMINOUS_SUPREME,
+    LUMINOUS_SUPREME_CONTROL, MAX_DOCX_BYTES,
 };
 
+use base64::{prelude::BASE64_STANDARD, Engine};
 use dotenv::dotenv;
+use futures::StreamExt;
 use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashMap;
+use std::time::Duration;
 
 lazy_static! {
     static ref AA_API_TOKEN: String = {
@@ -16,13 +46,25 @@ lazy_static! {
     };
 }
 
+/// Vocabulary of [`word_level_tokenizer`], e.g. to serve it as the tokenizer of a model.
+const WORD_LEVEL_VOCABULARY: &str = r#"{"version": "1.0", "truncation": null, "padding": null,
+    "added_tokens": [], "normalizer": null, "pre_tokenizer": {"type": "Whitespace"},
+    "post_processor": null, "decoder": null,
+    "model": {"type": "WordLevel", "vocab": {"[UNK]": 0, "one": 1, "two": 2},
+              "unk_token": "[UNK]"}}"#;
+
+/// Tokenizer splitting at whitespace, which works without downloading a vocabulary.
+fn word_level_tokenizer() -> tokenizers::Tokenizer {
+    tokenizers::Tokenizer::from_bytes(WORD_LEVEL_VOCABULARY).unwrap()
+}
+
 #[tokio::test]
 async fn completion_with_luminous_base() {
     // When
 
     let client = Client::new(AA_API_TOKEN.clone()).expect("failed to create client");
     let req = CompletionRequest::new(
-        LUMINOUS_BASE.into(),
+        LUMINOUS_BASE,
         Prompt::from_text("Hallo wie geht es dir? "),
         20,
     );
@@ -40,7 +82,7 @@ async fn completion_with_luminous_base_token_ids() {
     let prompt = Prompt::from_token_ids(vec![49222, 15, 5390, 4], None);
 
     // When
-    let mut req = CompletionRequest::new(LUMINOUS_BASE.into(), prompt, 20).top_k(16);
+    let mut req = CompletionRequest::new(LUMINOUS_BASE, prompt, 20).top_k(16);
     req.echo = Some(true);
 
     let response = client.completion(&req, Some(true)).await.unwrap();
@@ -65,7 +107,7 @@ async fn multi_modal_completion_with_luminous_base() {
     ]);
 
     // When
-    let req = CompletionRequest::new(LUMINOUS_BASE.into(), prompt, 20)
+    let req = CompletionRequest::new(LUMINOUS_BASE, prompt, 20)
         .top_k(16)
         .n(2);
     let response = client.completion(&req, Some(true)).await.unwrap();
@@ -138,20 +180,42 @@ async fn explain_with_luminous_base() {
     assert!(!response.model_version.is_empty());
 }
 
+#[tokio::test]
+async fn explain_all_with_luminous_base() {
+    let client = Client::new(AA_API_TOKEN.clone()).expect("failed to create client");
+    let requests: Vec<_> = [" pizza", " baguette"]
+        .into_iter()
+        .map(|target| {
+            ExplanationRequest::new(
+                LUMINOUS_BASE,
+                Prompt::from_text("I am a programmer and French. My favorite food is"),
+                target,
+            )
+        })
+        .collect();
+
+    let outcome = client
+        .explain_all(&requests, 2, &RetryPolicy::default(), Some(true))
+        .await;
+
+    assert!(outcome.is_complete());
+    assert_eq!(outcome.succeeded.len(), 2);
+}
+
 #[tokio::test]
 async fn embed_with_luminous_base() {
     let client = Client::new(AA_API_TOKEN.clone()).expect("failed to create client");
 
     let model = LUMINOUS_BASE;
     let text_prompt = "Lorem ipsum dolor sit amet, consetetur sadipscing elitr, sed diam nonumy eirmod tempor invidunt ut labore et dolore magna aliquyam erat, sed diam voluptua.";
-    let req = EmbeddingRequest::from_text(model, text_prompt, 1, "max", true);
+    let req = EmbeddingRequest::from_text(model, text_prompt, Layer::Output(1), Pooling::Max, true);
 
     let response = client.embed(&req, Some(true)).await.unwrap();
 
     assert_eq!(response.embeddings.len(), 1);
     assert!(response.embeddings.get("layer_1").is_some());
-    assert!(response.embeddings["layer_1"].get("max").is_some());
-    assert!(response.embeddings["layer_1"]["max"].len() > 64);
+    let embedding = response.get(Layer::Output(1), Pooling::Max).unwrap();
+    assert!(embedding.len() > 64);
 }
 
 #[tokio::test]
@@ -164,7 +228,7 @@ async fn semantic_embed_with_luminous_base() {
         model: model.to_owned(),
         prompt: prompt,
         representation: EmbeddingRepresentation::Symmetric,
-        compress_to_size: Some(128),
+        compress_to_size: CompressToSize::Compressed128,
         ..Default::default()
     };
 
@@ -183,7 +247,7 @@ async fn batch_semantic_embed_with_luminous_base() {
         model: model.to_owned(),
         prompts: vec![prompt1, prompt2],
         representation: EmbeddingRepresentation::Symmetric,
-        compress_to_size: Some(128),
+        compress_to_size: CompressToSize::Compressed128,
         ..Default::default()
     };
 
@@ -291,3 +355,3002 @@ async fn tokenizer_cross_check_luminous_base() {
     assert_eq!(encoding.get_ids(), tokenization_response.token_ids.unwrap());
     assert_eq!(encoding.get_tokens(), tokenization_response.tokens.unwrap());
 }
+
+#[tokio::test]
+async fn conversation_with_luminous_base_control() {
+    // Given
+    let client = Client::new(AA_API_TOKEN.clone()).unwrap();
+    let mut conversation = Conversation::new(LUMINOUS_BASE_CONTROL, 2048, 64)
+        .system_prompt("You are a helpful assistant.".to_owned());
+
+    // When
+    let reply = conversation
+        .reply(&client, "What is the capital of France?", Some(true))
+        .await
+        .unwrap()
+        .to_owned();
+
+    // Then
+    assert!(reply.contains("Paris"));
+    assert_eq!(conversation.turns.len(), 2);
+}
+
+#[test]
+fn conversation_drops_oldest_turns_to_fit_budget() {
+    // Given
+    let tokenizer = word_level_tokenizer();
+    let mut conversation = Conversation::new(LUMINOUS_BASE_CONTROL, 0, 8);
+    conversation.push_user("one two one two");
+    conversation.push_assistant("two");
+    conversation.push_user("one");
+    conversation.push_assistant("two one");
+    conversation.push_user("two two");
+    let mut last_turn = Conversation::new(LUMINOUS_BASE_CONTROL, 0, 8);
+    last_turn.push_user("two two");
+    let required = last_turn.token_count(&tokenizer).unwrap() as u32;
+    conversation.context_budget = required + 8;
+    let mut too_long = Conversation::new(LUMINOUS_BASE_CONTROL, required + 7, 8);
+    too_long.push_user("two two");
+
+    // When
+    let removed = conversation.truncate(&tokenizer).unwrap();
+    let overflow = too_long.truncate(&tokenizer);
+
+    // Then
+    assert_eq!(removed, 4);
+    assert_eq!(conversation.turns, last_turn.turns);
+    assert!(matches!(
+        overflow,
+        Err(ApiError::ContextOverflow { required: r, available }) if r == required as usize
+            && available == required as usize - 1
+    ));
+    assert_eq!(too_long.turns.len(), 1);
+}
+
+#[test]
+fn conversation_history_starts_with_user_turn() {
+    // Given
+    let tokenizer = word_level_tokenizer();
+    let mut conversation = Conversation::new(LUMINOUS_BASE_CONTROL, 0, 8);
+    conversation.push_user("one two one two one two");
+    conversation.push_assistant("two");
+    conversation.push_user("one");
+    conversation.push_assistant("two");
+    let full = conversation.token_count(&tokenizer).unwrap() as u32;
+    conversation.context_budget = full + 7;
+
+    // When
+    let removed = conversation.truncate(&tokenizer).unwrap();
+
+    // Then
+    assert_eq!(removed, 2);
+    assert_eq!(conversation.turns[0].text, "one");
+    assert_eq!(conversation.turns.len(), 2);
+}
+
+#[test]
+fn render_prompt_template_with_image_placeholder() {
+    // Given
+    let template = PromptTemplate::new("{{image}}Q: {{ question }}\nA:");
+    let vars = HashMap::from([
+        (
+            "image",
+            TemplateValue::from(
+                Modality::from_image_path("tests/serengeti_elephants.jpg").unwrap(),
+            ),
+        ),
+        ("question", TemplateValue::from("What animals are shown?")),
+    ]);
+
+    // When
+    let prompt = template.render(&vars).unwrap();
+
+    // Then
+    let json = serde_json::to_value(&prompt).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 2);
+    assert_eq!(json[0]["type"], "image");
+    assert_eq!(json[1]["data"], "Q: What animals are shown?\nA:");
+    assert_eq!(template.variables().unwrap(), vec!["image", "question"]);
+}
+
+#[test]
+fn prompt_json_file_round_trip() {
+    // Given
+    let prompt = Prompt::from_vec(vec![
+        Modality::from_image_path("tests/serengeti_elephants.jpg").unwrap(),
+        Modality::from_text("A picture of", None),
+        Modality::from_token_ids(
+            vec![49222, 15, 5390, 4],
+            Some(vec![TokenControl {
+                index: 1,
+                factor: 0.5,
+            }]),
+        ),
+    ]);
+    let path = std::env::temp_dir().join("aleph_alpha_api_prompt_round_trip.json");
+
+    // When
+    prompt.to_json_file(&path).unwrap();
+    let loaded = Prompt::from_json_file(&path).unwrap();
+
+    // Then
+    assert_eq!(loaded, prompt);
+}
+
+#[test]
+fn compose_prompt_incrementally() {
+    // Given
+    let page = image::DynamicImage::ImageRgb8(image::RgbImage::new(8, 8));
+    let mut prompt = Prompt::empty();
+
+    // When
+    prompt
+        .push_text("Page 1:")
+        .push_image(&page)
+        .unwrap()
+        .push(Modality::from_token_ids(vec![1, 2], None));
+    prompt.extend([Modality::from_text("Question?", None)]);
+    let collected: Prompt = prompt.iter().skip(2).cloned().collect();
+
+    // Then
+    assert_eq!(prompt.len(), 4);
+    assert_eq!(prompt[0], Modality::from_text("Page 1:", None));
+    assert!(matches!(prompt[1], Modality::Image { .. }));
+    assert_eq!(prompt[3], Modality::from_text("Question?", None));
+    assert_eq!(collected.len(), 2);
+    assert_eq!(collected[0], prompt[2]);
+    assert!(Prompt::from_vec(vec![]).is_empty());
+}
+
+#[tokio::test]
+async fn completion_from_tokenizer_encoding() {
+    // Given
+    let client = Client::new(AA_API_TOKEN.clone()).unwrap();
+    let tokenizer = client.get_tokenizer(LUMINOUS_BASE).await.unwrap();
+    let encoding = tokenizer.encode("Hello, World!", false).unwrap();
+
+    // When
+    let prompt = Prompt::from_encoding_with_controls(&encoding, &[(2..4, 0.5)]);
+    let req = CompletionRequest::new(LUMINOUS_BASE, prompt, 10);
+    let response = client.completion(&req, Some(true)).await.unwrap();
+
+    // Then
+    assert!(!response.best_text().is_empty());
+}
+
+#[tokio::test]
+async fn estimate_tokens_of_multimodal_prompt() {
+    // Given
+    let client = Client::new(AA_API_TOKEN.clone()).unwrap();
+    let tokenizer = client.get_tokenizer(LUMINOUS_BASE).await.unwrap();
+    let prompt = Prompt::from_vec(vec![
+        Modality::from_image_path("tests/serengeti_elephants.jpg").unwrap(),
+        Modality::from_text("This is a test", None),
+        Modality::from_token_ids(vec![49222, 15, 5390, 4], None),
+    ]);
+
+    // When
+    let tokens = prompt.estimate_tokens(&tokenizer).unwrap();
+
+    // Then
+    assert_eq!(tokens, IMAGE_TOKEN_COUNT + 4 + 4);
+}
+
+#[tokio::test]
+async fn truncate_prompt_to_context_window() {
+    // Given
+    let client = Client::new(AA_API_TOKEN.clone()).unwrap();
+    let tokenizer = client.get_tokenizer(LUMINOUS_BASE).await.unwrap();
+    let document = "Grüße aus dem Süden. ".repeat(200);
+    let mut prompt = Prompt::from_vec(vec![
+        Modality::from_image_path("tests/serengeti_elephants.jpg").unwrap(),
+        Modality::from_text("Summarize the following text:\n", None),
+        Modality::from_text(document, None),
+        Modality::from_text("\nSummary:", None),
+    ]);
+
+    // When
+    prompt
+        .truncate_to(256, &tokenizer, TruncationStrategy::Middle)
+        .unwrap();
+
+    // Then
+    assert!(prompt.estimate_tokens(&tokenizer).unwrap() <= 256);
+    assert!(matches!(prompt[0], Modality::Image { .. }));
+    assert_eq!(
+        prompt[1],
+        Modality::from_text("Summarize the following text:\n", None)
+    );
+    assert_eq!(
+        prompt[prompt.len() - 1],
+        Modality::from_text("\nSummary:", None)
+    );
+}
+
+#[tokio::test]
+async fn split_echoed_completion_into_prompt_and_completion() {
+    // Given
+    let client = Client::new(AA_API_TOKEN.clone()).unwrap();
+    let prompt = Prompt::from_token_ids(vec![49222, 15, 5390, 4], None);
+    let req = CompletionRequest::new(LUMINOUS_BASE, prompt, 5)
+        .echo(true)
+        .tokens(true)
+        .log_probs(LogProbsSetting::Generated);
+
+    // When
+    let response = client.completion(&req, Some(true)).await.unwrap();
+    let split = response.split_echo(&req).unwrap();
+
+    // Then
+    assert_eq!(split.len(), 1);
+    assert_eq!(split[0].prompt.len(), 4);
+    assert!(!split[0].completion.is_empty());
+    assert!(split[0].completion.iter().all(|t| t.log_prob.is_some()));
+}
+
+#[tokio::test]
+async fn surprisal_analysis_with_luminous_base() {
+    // Given
+    let client = Client::new(AA_API_TOKEN.clone()).unwrap();
+
+    // When
+    let analysis = analysis::analyze(
+        &client,
+        LUMINOUS_BASE,
+        "An apple a day",
+        " keeps the doctor away. Purple elephants compile",
+        Some(true),
+    )
+    .await
+    .unwrap();
+
+    // Then
+    assert!(!analysis.tokens.is_empty());
+    assert!(analysis.perplexity() > 1.0);
+    assert!(!analysis.outlier_spans(1.0).is_empty());
+}
+
+#[tokio::test]
+async fn complete_all_preserves_order() {
+    // Given
+    let client = Client::new(AA_API_TOKEN.clone()).unwrap();
+    let requests: Vec<_> = ["An apple a day", "The quick brown fox", "Once upon a time"]
+        .into_iter()
+        .map(|text| CompletionRequest::from_text(LUMINOUS_BASE, text, 5))
+        .collect();
+
+    // When
+    let responses = client.complete_all(&requests, 2, Some(true)).await;
+
+    // Then
+    assert_eq!(responses.len(), requests.len());
+    assert!(responses.iter().all(|r| r.is_ok()));
+}
+
+#[tokio::test]
+async fn complete_all_with_retry_over_several_rounds() {
+    // Given
+    let busy = || {
+        b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            .to_vec()
+    };
+    let ok = || {
+        http_response(
+            "application/json",
+            br#"{"model_version": "2022-04", "completions": []}"#,
+        )
+    };
+    let (url, _) = serve_in_order(vec![ok(), busy(), busy(), ok()]);
+    let base_url = url.trim_end_matches("/image").to_owned();
+    let client = Client::new_with_base_url(base_url, "token".to_owned()).unwrap();
+    let requests = [
+        CompletionRequest::from_text(LUMINOUS_BASE, "first", 1),
+        CompletionRequest::from_text(LUMINOUS_BASE, "second", 1),
+    ];
+    let policy = RetryPolicy {
+        max_retries: 2,
+        initial_backoff: Duration::from_millis(1),
+        ..RetryPolicy::default()
+    };
+
+    // When
+    let outcome = client
+        .complete_all_with_retry(&requests, 1, &policy, None)
+        .await;
+
+    // Then
+    assert!(outcome.is_complete());
+    assert_eq!(outcome.retried, [1]);
+    let succeeded: Vec<_> = outcome.succeeded.iter().map(|(index, _)| *index).collect();
+    assert_eq!(succeeded, [0, 1]);
+}
+
+#[test]
+fn retry_backoff_is_capped_for_many_retries() {
+    // Given
+    let policy = RetryPolicy::default();
+    let infinite = RetryPolicy {
+        multiplier: f64::INFINITY,
+        ..RetryPolicy::default()
+    };
+
+    // Then
+    assert_eq!(policy.backoff(0), Duration::from_secs(1));
+    assert_eq!(policy.backoff(100), policy.max_backoff);
+    assert_eq!(policy.backoff(u32::MAX), policy.max_backoff);
+    assert_eq!(infinite.backoff(1), infinite.max_backoff);
+}
+
+#[tokio::test]
+async fn retry_policy_repeats_transient_errors_only() {
+    // Given
+    let policy = RetryPolicy {
+        max_retries: 2,
+        initial_backoff: Duration::from_millis(1),
+        ..RetryPolicy::default()
+    };
+    let mut busy_calls = 0;
+    let mut http_calls = 0;
+
+    // When
+    let busy = policy
+        .run(|| {
+            busy_calls += 1;
+            async { Err::<(), _>(ApiError::Busy) }
+        })
+        .await;
+    let http = policy
+        .run(|| {
+            http_calls += 1;
+            async {
+                Err::<(), _>(ApiError::Http {
+                    status: 400,
+                    body: String::new(),
+                })
+            }
+        })
+        .await;
+
+    // Then
+    assert!(matches!(busy, Err(ApiError::Busy)));
+    assert_eq!(busy_calls, 3);
+    assert!(matches!(http, Err(ApiError::Http { status: 400, .. })));
+    assert_eq!(http_calls, 1);
+}
+
+#[test]
+fn output_pipeline_cleans_echoed_completion() {
+    // Given
+    let pipeline = OutputPipeline::new()
+        .strip_echoed_prompt()
+        .cut_at(Regex::new(r"###").unwrap())
+        .collapse_newlines()
+        .trim();
+    let completion = "Q: Why?\nA: Because\n\n\n\nit is.\n### Input:";
+
+    // When
+    let cleaned = pipeline.apply(completion, "Q: Why?\n");
+
+    // Then
+    assert_eq!(cleaned, "A: Because\n\nit is.");
+}
+
+#[test]
+fn serialize_attention_controls() {
+    // Given
+    let text = TextControl::new(0, 5, 2.0).token_overlap(ControlTokenOverlap::Complete);
+    let image = ImageControl::new(BoundingBox::new(0.0, 0.0, 0.5, 0.5), 0.5);
+
+    // When
+    let text = serde_json::to_value(&text).unwrap();
+    let image = serde_json::to_value(&image).unwrap();
+
+    // Then
+    assert_eq!(
+        text,
+        serde_json::json!({"start": 0, "length": 5, "factor": 2.0, "token_overlap": "complete"})
+    );
+    assert_eq!(image["rect"]["width"], 0.5);
+    assert_eq!(image["rect"]["height"], 0.5);
+    assert_eq!(image["factor"], 0.5);
+    assert!(image.get("token_overlap").is_none());
+}
+
+#[test]
+fn serialize_bounding_box() {
+    // Given
+    let rect = BoundingBox::new(0.0, 0.25, 0.5, 0.75);
+
+    // When
+    let value = serde_json::to_value(&rect).unwrap();
+
+    // Then
+    assert_eq!(
+        value,
+        serde_json::json!({"left": 0.0, "top": 0.25, "width": 0.5, "height": 0.75})
+    );
+}
+
+#[test]
+fn deserialize_bounding_box_with_legacy_height_key() {
+    // When
+    let rect: BoundingBox =
+        serde_json::from_str(r#"{"left": 0.0, "top": 0.25, "width": 0.5, "heigh": 0.75}"#).unwrap();
+
+    // Then
+    assert_eq!(rect, BoundingBox::new(0.0, 0.25, 0.5, 0.75));
+}
+
+#[test]
+fn serialize_custom_hosting() {
+    // When
+    let builtin = serde_json::to_string(&Hosting::AlephAlpha).unwrap();
+    let custom = serde_json::to_string(&Hosting::Custom("on-premise".to_owned())).unwrap();
+    let parsed: Hosting = serde_json::from_str(r#""on-premise""#).unwrap();
+
+    // Then
+    assert_eq!(builtin, r#""aleph-alpha""#);
+    assert_eq!(custom, r#""on-premise""#);
+    assert_eq!(parsed, Hosting::Custom("on-premise".to_owned()));
+}
+
+#[test]
+fn highlights_use_character_offsets() {
+    // Given
+    let text = "Größe matters";
+
+    // When
+    let prompt = Prompt::from_text_with_highlights(text, &[(2..3, 0.5), (6..13, 2.0)]).unwrap();
+    let out_of_bounds = Prompt::from_text_with_highlights(text, &[(6..14, 2.0)]);
+
+    // Then
+    assert_eq!(
+        prompt,
+        Prompt::from_text_with_controls(
+            text,
+            vec![TextControl::new(2, 1, 0.5), TextControl::new(6, 7, 2.0)]
+        )
+    );
+    assert!(out_of_bounds.is_none());
+}
+
+#[test]
+fn validate_completion_request() {
+    // Given
+    let valid = CompletionRequest::from_text(LUMINOUS_BASE, "Hello", 10)
+        .temperature(0.5)
+        .best_of(3)
+        .n(2);
+    let empty = CompletionRequest::new(LUMINOUS_BASE, Prompt::from_text(""), 10);
+    let best_of = CompletionRequest::from_text(LUMINOUS_BASE, "Hello", 10).best_of(1);
+    let bias = CompletionRequest::from_text(LUMINOUS_BASE, "Hello", 10)
+        .completion_bias_inclusion(vec!["Yes".into()])
+        .completion_bias_exclusion(vec!["Y".into()]);
+    let log_probs =
+        CompletionRequest::from_text(LUMINOUS_BASE, "Hello", 10).log_probs(LogProbsSetting::Top(0));
+
+    // Then
+    assert_eq!(valid.validate(), Ok(()));
+    assert!(matches!(
+        log_probs.validate(),
+        Err(ValidationError::OutOfRange {
+            parameter: "log_probs",
+            ..
+        })
+    ));
+    assert_eq!(empty.validate(), Err(ValidationError::EmptyPrompt));
+    assert_eq!(
+        best_of.validate(),
+        Err(ValidationError::BestOfNotGreaterThanN { best_of: 1, n: 1 })
+    );
+    assert!(matches!(
+        bias.validate(),
+        Err(ValidationError::BiasPrefixConflict { .. })
+    ));
+}
+
+#[test]
+fn control_parameters_are_flattened() {
+    // Given
+    let req =
+        EvaluationRequest::from_text(LUMINOUS_BASE, "An apple a day", " keeps the doctor away")
+            .contextual_control_threshold(0.5)
+            .control_log_additive(false);
+
+    // When
+    let json = serde_json::to_value(&req).unwrap();
+
+    // Then
+    assert_eq!(json["contextual_control_threshold"], 0.5);
+    assert_eq!(json["control_log_additive"], false);
+    assert!(json.get("control_parameters").is_none());
+}
+
+#[test]
+fn serialize_log_probs_setting() {
+    // Given
+    let req = CompletionRequest::from_text(LUMINOUS_BASE, "Hello", 10);
+
+    // When
+    let none = serde_json::to_value(&req).unwrap();
+    let generated = serde_json::to_value(req.log_probs(LogProbsSetting::Generated)).unwrap();
+    let top = serde_json::to_value(LogProbsSetting::Top(3)).unwrap();
+
+    // Then
+    assert!(none.get("log_probs").is_none());
+    assert_eq!(generated["log_probs"], 0);
+    assert_eq!(top, 3);
+}
+
+#[test]
+fn aggregate_multi_sample_response() {
+    // Given
+    let response: CompletionResponse = serde_json::from_str(
+        r#"{
+            "model_version": "2022-04",
+            "completions": [
+                {"completion": "Hi a", "finish_reason": "maximum_tokens", "completion_tokens": ["Hi", " a"]},
+                {"completion": "Hi c", "finish_reason": "end_of_text", "completion_tokens": ["Hi", " c"]}
+            ],
+            "num_tokens_generated": 2
+        }"#,
+    )
+    .unwrap();
+
+    // When
+    let reasons: Vec<&str> = response.finish_reasons().collect();
+    let total = response.total_generated_tokens();
+    let mut texts = Vec::new();
+    for output in &response {
+        texts.push(output.completion.as_str());
+    }
+
+    // Then
+    assert_eq!(reasons, ["maximum_tokens", "end_of_text"]);
+    assert_eq!(total, Some(2));
+    assert_eq!(texts, ["Hi a", "Hi c"]);
+}
+
+#[test]
+fn deserialize_embedding_response_with_pooling_keys() {
+    // When
+    let response: EmbeddingResponse = serde_json::from_str(
+        r#"{"model_version": "2022-04", "embeddings": {"layer_1": {"weighted_mean": [0.5]}}}"#,
+    )
+    .unwrap();
+
+    // Then
+    assert_eq!(
+        response.get(Layer::Output(1), Pooling::WeightedMean),
+        Some([0.5].as_slice())
+    );
+}
+
+#[test]
+fn serialize_embedding_layers() {
+    // Given
+    let req =
+        EmbeddingRequest::from_text(LUMINOUS_BASE, "Hello", Layer::Input, Pooling::Mean, true);
+    let layers = [Layer::Input, Layer::Output(3), Layer::FromLast(2)];
+
+    // When
+    let json = serde_json::to_value(&req).unwrap();
+    let indices = serde_json::to_value(layers).unwrap();
+
+    // Then
+    assert_eq!(json["layers"], serde_json::json!([0]));
+    assert_eq!(indices, serde_json::json!([0, 3, -2]));
+    assert_eq!(Layer::FromLast(2).key(), "layer_-2");
+    assert_eq!(Layer::from_key("layer_3"), Some(Layer::Output(3)));
+}
+
+#[test]
+fn reject_ambiguous_embedding_layers() {
+    // Given
+    let layers = [
+        Layer::Output(0),
+        Layer::FromLast(0),
+        Layer::Output(u32::MAX),
+    ];
+
+    // When
+    let indices: Vec<_> = layers.iter().map(Layer::index).collect();
+    let json = serde_json::to_value(Layer::FromLast(0));
+
+    // Then
+    assert!(indices.iter().all(|index| matches!(
+        index,
+        Err(ValidationError::OutOfRange {
+            parameter: "layer",
+            ..
+        })
+    )));
+    assert!(json.is_err());
+    assert_eq!(Layer::FromLast(2).index(), Ok(-2));
+    assert_eq!(Layer::Output(u32::MAX).key(), "layer_4294967295");
+}
+
+#[test]
+fn build_semantic_embedding_request() {
+    // Given
+    let req = SemanticEmbeddingRequest::from_text(
+        LUMINOUS_BASE,
+        "An apple a day keeps the doctor away.",
+        EmbeddingRepresentation::Query,
+    )
+    .compress_to_size(CompressToSize::Compressed128)
+    .normalize(true)
+    .hosting(Hosting::AlephAlpha);
+
+    // When
+    let json = serde_json::to_value(&req).unwrap();
+
+    // Then
+    assert_eq!(json["representation"], "query");
+    assert_eq!(json["compress_to_size"], 128);
+    assert_eq!(json["normalize"], true);
+    assert_eq!(json["hosting"], "aleph-alpha");
+}
+
+#[test]
+fn compress_to_size_accepts_only_supported_sizes() {
+    // Given
+    let full = SemanticEmbeddingRequest::from_text(
+        LUMINOUS_BASE,
+        "An apple a day",
+        EmbeddingRepresentation::Symmetric,
+    );
+
+    // When
+    let json = serde_json::to_value(&full).unwrap();
+    let compressed = CompressToSize::try_from(128);
+    let unsupported = CompressToSize::try_from(100);
+
+    // Then
+    assert!(json.get("compress_to_size").is_none());
+    assert_eq!(compressed.unwrap(), CompressToSize::Compressed128);
+    assert!(matches!(
+        unsupported,
+        Err(ValidationError::UnsupportedCompressToSize(100))
+    ));
+}
+
+#[test]
+fn build_batch_and_layer_embedding_requests() {
+    // Given
+    let batch = BatchSemanticEmbeddingRequest::from_texts(
+        LUMINOUS_BASE,
+        ["An apple a day", "The cat is on the mat."],
+        EmbeddingRepresentation::Symmetric,
+    )
+    .compress_to_size(CompressToSize::Compressed128);
+    let layers = EmbeddingRequest::new(
+        LUMINOUS_BASE,
+        Prompt::from_text("An apple a day"),
+        vec![Layer::Input, Layer::FromLast(1)],
+        vec![Pooling::Mean, Pooling::Max],
+    )
+    .tokens(true)
+    .hosting(Hosting::AlephAlpha);
+
+    // When
+    let batch = serde_json::to_value(&batch).unwrap();
+    let layers = serde_json::to_value(&layers).unwrap();
+
+    // Then
+    assert_eq!(batch["prompts"].as_array().unwrap().len(), 2);
+    assert_eq!(batch["compress_to_size"], 128);
+    assert_eq!(layers["layers"], serde_json::json!([0, -1]));
+    assert_eq!(layers["pooling"], serde_json::json!(["mean", "max"]));
+    assert_eq!(layers["tokens"], true);
+}
+
+#[tokio::test]
+async fn batch_semantic_embed_chunked_keeps_order() {
+    // Given
+    let client = Client::new(AA_API_TOKEN.clone()).unwrap();
+    let texts = ["An apple a day", "The cat is on the mat.", "An apple a day"];
+    let req = BatchSemanticEmbeddingRequest::from_texts(
+        LUMINOUS_BASE,
+        texts,
+        EmbeddingRepresentation::Symmetric,
+    )
+    .compress_to_size(CompressToSize::Compressed128);
+
+    // When
+    let response = client
+        .batch_semantic_embed_chunked(&req, 2, 2, Some(true))
+        .await
+        .unwrap();
+
+    // Then
+    assert_eq!(response.embeddings.len(), texts.len());
+    assert!(response.embeddings.iter().all(|e| e.len() == 128));
+}
+
+#[tokio::test]
+async fn embed_stream_yields_indexed_embeddings() {
+    // Given
+    let client = Client::new(AA_API_TOKEN.clone()).unwrap();
+    let prompts = futures::stream::iter(
+        ["An apple a day", "The cat is on the mat.", "Hello world"].map(Prompt::from_text),
+    );
+    let options = EmbedStreamOptions::new(LUMINOUS_BASE, EmbeddingRepresentation::Symmetric)
+        .compress_to_size(CompressToSize::Compressed128)
+        .batch_size(2)
+        .nice(true);
+
+    // When
+    let results: Vec<_> = client.embed_stream(prompts, options).collect().await;
+
+    // Then
+    let indices: Vec<usize> = results.iter().map(|r| r.as_ref().unwrap().0).collect();
+    assert_eq!(indices, [0, 1, 2]);
+    assert!(results.iter().all(|r| r.as_ref().unwrap().1.len() == 128));
+}
+
+#[cfg(feature = "embedding-cache")]
+#[test]
+fn embedding_cache_round_trip() {
+    use aleph_alpha_api::embedding_cache::{CacheKey, EmbeddingCache};
+
+    // Given
+    let cache = EmbeddingCache::open(std::env::temp_dir().join("aleph_alpha_api_cache")).unwrap();
+    let key = |text| {
+        CacheKey::new(
+            LUMINOUS_BASE,
+            EmbeddingRepresentation::Query,
+            CompressToSize::Compressed128,
+            None,
+            text,
+        )
+    };
+
+    // When
+    cache.put(&key("An apple  a day\n"), &[0.25, -1.5]).unwrap();
+
+    // Then
+    assert_eq!(key("An apple a day"), key(" An apple\ta day"));
+    assert_eq!(
+        cache.get(&key("An apple a day")).unwrap(),
+        Some(vec![0.25, -1.5])
+    );
+    assert_eq!(cache.get(&key("The cat is on the mat.")).unwrap(), None);
+}
+
+#[cfg(feature = "embedding-cache")]
+#[test]
+fn embedding_cache_drops_corrupt_entries() {
+    use aleph_alpha_api::embedding_cache::{CacheKey, EmbeddingCache};
+
+    // Given
+    let dir = std::env::temp_dir().join("aleph_alpha_api_corrupt_cache");
+    let cache = EmbeddingCache::open(&dir).unwrap();
+    let key = |text| {
+        CacheKey::new(
+            LUMINOUS_BASE,
+            EmbeddingRepresentation::Query,
+            CompressToSize::Compressed128,
+            None,
+            text,
+        )
+    };
+    let (truncated, empty) = (key("truncated"), key("empty"));
+    cache.put(&truncated, &[0.25, -1.5]).unwrap();
+    cache.put(&empty, &[]).unwrap();
+    let path = |key: &CacheKey| dir.join(&key.as_str()[..2]).join(key.as_str());
+    std::fs::write(path(&truncated), [0, 0, 128]).unwrap();
+
+    // When
+    let entries = [cache.get(&truncated).unwrap(), cache.get(&empty).unwrap()];
+
+    // Then
+    assert_eq!(entries, [None, None]);
+    assert!(!path(&truncated).exists());
+    assert!(!path(&empty).exists());
+}
+
+#[tokio::test]
+async fn semantic_search_with_vector_index() {
+    // Given
+    let client = Client::new(AA_API_TOKEN.clone()).unwrap();
+    let documents = [
+        "Apples are a sweet fruit.",
+        "Cars have four wheels.",
+        "The weather is sunny today.",
+    ];
+    let req = BatchSemanticEmbeddingRequest::from_texts(
+        LUMINOUS_BASE,
+        documents,
+        EmbeddingRepresentation::Document,
+    )
+    .compress_to_size(CompressToSize::Compressed128);
+    let response = client.batch_semantic_embed(&req, Some(true)).await.unwrap();
+    let index: VectorIndex<usize> = response.embeddings.into_iter().enumerate().collect();
+
+    // When
+    let query = SemanticEmbeddingRequest::from_text(
+        LUMINOUS_BASE,
+        "Which fruit is sweet?",
+        EmbeddingRepresentation::Query,
+    )
+    .compress_to_size(CompressToSize::Compressed128);
+    let query = client.semantic_embed(&query, Some(true)).await.unwrap();
+    let hits = index.top_k(&query.embedding, 1);
+
+    // Then
+    assert_eq!(hits.len(), 1);
+    assert_eq!(*hits[0].0, 0);
+}
+
+#[test]
+fn similarity_of_long_embeddings() {
+    // Given
+    let a: Vec<f32> = (0..5120).map(|i| (i % 7) as f32).collect();
+    let b: Vec<f32> = a.iter().map(|v| 2.0 * v).collect();
+    let naive_dot: f32 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+
+    // Then
+    assert!((similarity::dot(&a, &b) - naive_dot).abs() / naive_dot < 1e-5);
+    assert!((similarity::cosine_similarity(&a, &b) - 1.0).abs() < 1e-5);
+    assert!((similarity::euclidean_distance(&a, &b) - similarity::norm(&a)).abs() < 1e-2);
+    assert_eq!(
+        similarity::euclidean_distance(&[0.0, 3.0, 1.0], &[4.0, 0.0, 1.0]),
+        5.0
+    );
+}
+
+#[cfg(feature = "ndarray")]
+#[test]
+fn batch_embeddings_to_array() {
+    use aleph_alpha_api::BatchSemanticEmbeddingResponse;
+
+    // Given
+    let response: BatchSemanticEmbeddingResponse = serde_json::from_str(
+        r#"{"model_version": "2022-04", "embeddings": [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]}"#,
+    )
+    .unwrap();
+
+    // When
+    let matrix = response.to_array2().unwrap();
+
+    // Then
+    assert_eq!(matrix.shape(), [2, 3]);
+    assert_eq!(matrix[[1, 0]], 4.0);
+}
+
+#[cfg(feature = "ndarray")]
+#[test]
+fn embedding_layers_to_array() {
+    // Given
+    let response: EmbeddingResponse = serde_json::from_str(
+        r#"{"model_version": "2022-04",
+            "embeddings": {"layer_-1": {"mean": [0.0, 0.5]}, "layer_1": {"mean": [1.0, 1.5]}}}"#,
+    )
+    .unwrap();
+
+    // When
+    let layers = response
+        .layers_to_array2(&[Layer::Output(1), Layer::FromLast(1)], Pooling::Mean)
+        .unwrap();
+
+    // Then
+    assert_eq!(layers.shape(), [2, 2]);
+    assert_eq!(layers[[0, 1]], 1.5);
+    assert_eq!(
+        response.layers_to_array2(&[Layer::Input], Pooling::Mean),
+        None
+    );
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn write_embeddings_to_parquet() {
+    use aleph_alpha_api::export::{EmbeddingRecord, ParquetEmbeddingWriter};
+
+    // Given
+    let records = [
+        EmbeddingRecord {
+            id: "a",
+            text: "An apple a day",
+            embedding: &[0.5, 1.0],
+        },
+        EmbeddingRecord {
+            id: "b",
+            text: "The cat is on the mat.",
+            embedding: &[-1.0, 0.0],
+        },
+    ];
+    let mut buffer = Vec::new();
+
+    // When
+    let mut writer = ParquetEmbeddingWriter::new(&mut buffer, 2).unwrap();
+    writer.write_batch(&records).unwrap();
+    let wrong_dimension = writer.write_batch(&[EmbeddingRecord {
+        id: "c",
+        text: "",
+        embedding: &[1.0],
+    }]);
+    writer.close().unwrap();
+
+    // Then
+    assert!(wrong_dimension.is_err());
+    assert_eq!(&buffer[..4], b"PAR1");
+}
+
+#[test]
+fn write_embeddings_as_npy() {
+    // Given
+    let embeddings = vec![vec![1.0f32, 2.0, 3.0], vec![4.0, 5.0, 6.0]];
+    let mut buffer = Vec::new();
+
+    // When
+    export::write_npy(&mut buffer, &embeddings).unwrap();
+    let ragged = export::write_npy(Vec::new(), &[vec![1.0f32], vec![1.0, 2.0]]);
+
+    // Then
+    let header_len = u16::from_le_bytes([buffer[8], buffer[9]]) as usize;
+    let header = std::str::from_utf8(&buffer[10..10 + header_len]).unwrap();
+    assert_eq!(&buffer[..6], b"\x93NUMPY");
+    assert_eq!((10 + header_len) % 64, 0);
+    assert!(header.contains("'shape': (2, 3)"));
+    assert_eq!(buffer.len(), 10 + header_len + 6 * 4);
+    assert_eq!(&buffer[buffer.len() - 4..], 6.0f32.to_le_bytes());
+    assert!(ragged.is_err());
+}
+
+#[test]
+fn write_embeddings_as_pgvector_copy_rows() {
+    // Given
+    let records = [export::EmbeddingRecord {
+        id: "a",
+        text: "tab\there\nand a \\",
+        embedding: &[0.5, -1.0],
+    }];
+    let mut buffer = Vec::new();
+
+    // When
+    export::write_pgvector_copy(&mut buffer, &records).unwrap();
+
+    // Then
+    assert_eq!(export::pgvector_literal(&[1.0, 0.25]), "[1,0.25]");
+    assert_eq!(
+        String::from_utf8(buffer).unwrap(),
+        "a\ttab\\there\\nand a \\\\\t[0.5,-1]\n"
+    );
+}
+
+#[test]
+fn quantized_embeddings_keep_similarity() {
+    // Given
+    let a: Vec<f32> = (0..128)
+        .map(|i| ((i * 37) % 101) as f32 / 50.0 - 1.0)
+        .collect();
+    let b: Vec<f32> = (0..128)
+        .map(|i| ((i * 53) % 97) as f32 / 48.0 - 1.0)
+        .collect();
+    let exact = similarity::cosine_similarity(&a, &b);
+
+    // When
+    let half = F16Embedding::quantize(&a).cosine_similarity(&F16Embedding::quantize(&b));
+    let int8 = Int8Embedding::quantize(&a).cosine_similarity(&Int8Embedding::quantize(&b));
+    let restored = Int8Embedding::quantize(&a).dequantize();
+
+    // Then
+    assert!((half - exact).abs() < 1e-3);
+    assert!((int8 - exact).abs() < 1e-2);
+    assert!(a.iter().zip(&restored).all(|(x, y)| (x - y).abs() < 0.01));
+}
+
+#[test]
+fn normalize_embeddings_on_insert() {
+    // Given
+    let mut raw = vec![3.0, 4.0];
+    let mut index = VectorIndex::new().normalize_on_insert(true);
+
+    // When
+    similarity::normalize_in_place(&mut raw);
+    index.insert("a", vec![0.0, 10.0]);
+
+    // Then
+    assert_eq!(raw, [0.6, 0.8]);
+    assert_eq!(index.get(0), Some((&"a", &[0.0, 1.0][..])));
+}
+
+#[test]
+fn serialize_instructable_embedding_request() {
+    // Given
+    let req = InstructableEmbeddingRequest::from_text(
+        LUMINOUS_BASE,
+        "Which fruit is sweet?",
+        "Represent the question for retrieving supporting documents",
+    )
+    .normalize(true);
+
+    // When
+    let json = serde_json::to_value(&req).unwrap();
+
+    // Then
+    assert_eq!(json["input"][0]["data"], "Which fruit is sweet?");
+    assert_eq!(
+        json["instruction"],
+        "Represent the question for retrieving supporting documents"
+    );
+    assert_eq!(json["normalize"], true);
+    assert!(json.get("hosting").is_none());
+}
+
+#[cfg(feature = "qdrant")]
+#[test]
+fn serialize_qdrant_points() {
+    use aleph_alpha_api::qdrant::QdrantPoint;
+
+    // Given
+    let point = QdrantPoint::new(7, vec![0.5, 1.0]).payload(serde_json::json!({"text": "apple"}));
+    let uuid = QdrantPoint::new("5c56c793-69f3-4fbf-87e6-c4bf54c28c26".to_owned(), vec![]);
+
+    // When
+    let point = serde_json::to_value(&point).unwrap();
+    let uuid = serde_json::to_value(&uuid).unwrap();
+
+    // Then
+    assert_eq!(
+        point,
+        serde_json::json!({"id": 7, "vector": [0.5, 1.0], "payload": {"text": "apple"}})
+    );
+    assert_eq!(uuid["id"], "5c56c793-69f3-4fbf-87e6-c4bf54c28c26");
+}
+
+#[test]
+fn chunk_text_with_overlap() {
+    // Given
+    let tokenizer = word_level_tokenizer();
+    let text = "one two three four five";
+
+    // When
+    let chunks = chunking::chunk_text(&tokenizer, text, 3, 1).unwrap();
+    let invalid = chunking::chunk_text(&tokenizer, text, 2, 2);
+    let empty = chunking::chunk_text(&tokenizer, text, 0, 0);
+
+    // Then
+    let texts: Vec<&str> = chunks.iter().map(|chunk| chunk.text).collect();
+    assert_eq!(texts, ["one two three", "three four five"]);
+    assert_eq!(&text[chunks[1].range.clone()], "three four five");
+    assert_eq!(chunks[1].token_count, 3);
+    assert!(matches!(
+        invalid,
+        Err(ApiError::Validation(ValidationError::OutOfRange {
+            parameter: "overlap",
+            ..
+        }))
+    ));
+    assert!(matches!(
+        empty,
+        Err(ApiError::Validation(ValidationError::OutOfRange {
+            parameter: "chunk_size",
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn aggregate_chunk_embeddings() {
+    // Given
+    let chunks: [(&[f32], usize); 2] = [(&[1.0, -1.0], 3), (&[0.0, 1.0], 1)];
+
+    // When
+    let mean = Aggregation::Mean.aggregate(&chunks);
+    let max = Aggregation::Max.aggregate(&chunks);
+    let weighted = Aggregation::WeightedMean.aggregate(&chunks);
+
+    // Then
+    assert_eq!(mean, [0.5, 0.0]);
+    assert_eq!(max, [1.0, 1.0]);
+    assert_eq!(weighted, [0.75, -0.5]);
+}
+
+#[tokio::test]
+async fn embed_long_document() {
+    // Given
+    let client = Client::new(AA_API_TOKEN.clone()).unwrap();
+    let document = "An apple a day keeps the doctor away. ".repeat(50);
+    let options = EmbedDocumentOptions::new(LUMINOUS_BASE, EmbeddingRepresentation::Document)
+        .compress_to_size(CompressToSize::Compressed128)
+        .chunks(64, 8);
+
+    // When
+    let embedding = client
+        .embed_document(&document, Aggregation::WeightedMean, &options)
+        .await
+        .unwrap();
+
+    // Then
+    assert!(embedding.chunks.len() > 1);
+    assert_eq!(embedding.embedding.len(), 128);
+}
+
+#[tokio::test]
+async fn embed_document_in_configured_batches() {
+    // Given
+    let embeddings = |body: &[u8]| http_response("application/json", body);
+    let (url, requests) = serve_in_order(vec![
+        http_response("application/json", WORD_LEVEL_VOCABULARY.as_bytes()),
+        embeddings(br#"{"model_version": "2022-04", "embeddings": [[1.0, 0.0]]}"#),
+        embeddings(br#"{"model_version": "2022-04", "embeddings": [[0.0, 1.0]]}"#),
+    ]);
+    let base_url = url.trim_end_matches("/image").to_owned();
+    let client = Client::new_with_base_url(base_url, "token".to_owned()).unwrap();
+    let options = EmbedDocumentOptions::new(LUMINOUS_BASE, EmbeddingRepresentation::Document)
+        .chunks(1, 0)
+        .batch_size(1)
+        .max_concurrency(1);
+
+    // When
+    let embedding = client
+        .embed_document("one two", Aggregation::Mean, &options)
+        .await
+        .unwrap();
+
+    // Then
+    let requests: Vec<_> = requests.iter().take(3).collect();
+    assert!(requests[1].starts_with("POST /batch_semantic_embed"));
+    assert!(requests[2].starts_with("POST /batch_semantic_embed"));
+    assert_eq!(embedding.chunks.len(), 2);
+    assert_eq!(embedding.embedding, [0.5, 0.5]);
+}
+
+#[tokio::test]
+async fn tokenizer_is_downloaded_once_per_model() {
+    // Given
+    let client = Client::new(AA_API_TOKEN.clone()).unwrap();
+
+    // When
+    let first = client.get_tokenizer(LUMINOUS_BASE).await.unwrap();
+    let second = client.get_tokenizer(LUMINOUS_BASE).await.unwrap();
+
+    // Then
+    assert!(std::sync::Arc::ptr_eq(&first, &second));
+}
+
+#[tokio::test]
+async fn concurrent_tokenizer_calls_download_once_per_model() {
+    // Given
+    let vocabulary = || http_response("application/json", WORD_LEVEL_VOCABULARY.as_bytes());
+    let (url, requests) = serve_in_order(vec![vocabulary(), vocabulary()]);
+    let base_url = url.trim_end_matches("/image").to_owned();
+    let client = Client::new_with_base_url(base_url, "token".to_owned()).unwrap();
+
+    // When
+    let (first, other, second) = tokio::join!(
+        client.get_tokenizer(LUMINOUS_BASE),
+        client.get_tokenizer(LUMINOUS_EXTENDED),
+        client.get_tokenizer(LUMINOUS_BASE),
+    );
+
+    // Then
+    assert!(std::sync::Arc::ptr_eq(&first.unwrap(), &second.unwrap()));
+    assert!(other.is_ok());
+    let mut paths: Vec<_> = requests.iter().take(2).collect();
+    paths.sort();
+    assert!(paths[0].starts_with("GET /models/luminous-base/tokenizer"));
+    assert!(paths[1].starts_with("GET /models/luminous-extended/tokenizer"));
+}
+
+#[cfg(feature = "tokenizer-cache")]
+#[test]
+fn tokenizer_cache_round_trip() {
+    use aleph_alpha_api::{http::CacheValidators, tokenizer_cache::TokenizerCache};
+
+    // Given
+    let cache =
+        TokenizerCache::open(std::env::temp_dir().join("aleph_alpha_api_tokenizers")).unwrap();
+    let validators = CacheValidators {
+        etag: Some("\"abc\"".to_owned()),
+        last_modified: None,
+    };
+
+    // When
+    cache.put("org/luminous-base", b"{}").unwrap();
+    cache
+        .put_validators("org/luminous-base", &validators)
+        .unwrap();
+    let cached = cache.get("org/luminous-base").unwrap();
+    let cached_validators = cache.validators("org/luminous-base").unwrap();
+    cache.remove("org/luminous-base").unwrap();
+
+    // Then
+    assert_eq!(cached.as_deref(), Some(b"{}".as_slice()));
+    assert_eq!(cached_validators, validators);
+    assert_eq!(cache.get("org/luminous-base").unwrap(), None);
+    assert_eq!(
+        cache.validators("org/luminous-base").unwrap(),
+        CacheValidators::default()
+    );
+    assert!(cache.remove("org/luminous-base").is_ok());
+}
+
+#[cfg(feature = "tokenizer-cache")]
+#[test]
+fn tokenizer_cache_keeps_similar_names_apart() {
+    use aleph_alpha_api::tokenizer_cache::TokenizerCache;
+
+    // Given
+    let cache =
+        TokenizerCache::open(std::env::temp_dir().join("aleph_alpha_api_tokenizer_names")).unwrap();
+
+    // When
+    cache.put("org/model", b"slash").unwrap();
+    cache.put("org_model", b"underscore").unwrap();
+
+    // Then
+    assert_eq!(
+        cache.get("org/model").unwrap().as_deref(),
+        Some(b"slash".as_slice())
+    );
+    assert_eq!(
+        cache.get("org_model").unwrap().as_deref(),
+        Some(b"underscore".as_slice())
+    );
+    cache.remove("org/model").unwrap();
+    cache.remove("org_model").unwrap();
+}
+
+#[cfg(feature = "tokenizer-cache")]
+#[tokio::test]
+async fn corrupt_cached_tokenizer_is_downloaded_again() {
+    use aleph_alpha_api::tokenizer_cache::TokenizerCache;
+
+    // Given
+    let cache =
+        TokenizerCache::open(std::env::temp_dir().join("aleph_alpha_api_tokenizer_corrupt"))
+            .unwrap();
+    cache.put(LUMINOUS_BASE, b"not a tokenizer").unwrap();
+    let (url, request) = serve_once(http_response(
+        "application/json",
+        WORD_LEVEL_VOCABULARY.as_bytes(),
+    ));
+    let base_url = url.trim_end_matches("/image").to_owned();
+    let client = Client::new_with_base_url(base_url, "token".to_owned()).unwrap();
+
+    // When
+    let tokenizer = client
+        .get_tokenizer_cached(LUMINOUS_BASE, &cache, false)
+        .await
+        .unwrap();
+
+    // Then
+    assert!(request
+        .recv()
+        .unwrap()
+        .starts_with("GET /models/luminous-base/tokenizer"));
+    assert_eq!(tokenizer.encode("one two", false).unwrap().len(), 2);
+    assert_eq!(
+        cache.get(LUMINOUS_BASE).unwrap().as_deref(),
+        Some(WORD_LEVEL_VOCABULARY.as_bytes())
+    );
+    cache.remove(LUMINOUS_BASE).unwrap();
+}
+
+#[tokio::test]
+async fn count_tokens_with_local_tokenizer() {
+    // Given
+    let client = Client::new(AA_API_TOKEN.clone()).unwrap();
+
+    // When
+    let count = client
+        .count_tokens(LUMINOUS_BASE, "This is a test")
+        .await
+        .unwrap();
+
+    // Then
+    assert_eq!(count, 4);
+}
+
+#[test]
+fn tokenize_and_detokenize_offline() {
+    // Given
+    let tokenizer = word_level_tokenizer();
+    let req = TokenizationRequest {
+        model: LUMINOUS_BASE.to_owned(),
+        prompt: "one two three".to_owned(),
+        tokens: true,
+        token_ids: true,
+    };
+
+    // When
+    let tokenized = offline::tokenize(&tokenizer, &req).unwrap();
+    let detokenized = offline::detokenize(
+        &tokenizer,
+        &DetokenizationRequest {
+            model: LUMINOUS_BASE.to_owned(),
+            token_ids: vec![1, 2],
+        },
+    )
+    .unwrap();
+
+    // Then
+    assert_eq!(tokenized.token_ids, Some(vec![1, 2, 0]));
+    assert_eq!(
+        tokenized.tokens,
+        Some(vec!["one".into(), "two".into(), "[UNK]".into()])
+    );
+    assert_eq!(detokenized.result, "one two");
+}
+
+#[test]
+fn build_tokenization_request() {
+    // Given
+    let req = TokenizationRequest::new(LUMINOUS_BASE, "An apple a day").tokens(false);
+
+    // When
+    let json = serde_json::to_value(&req).unwrap();
+
+    // Then
+    assert_eq!(json["prompt"], "An apple a day");
+    assert_eq!(json["tokens"], false);
+    assert_eq!(json["token_ids"], true);
+}
+
+#[test]
+fn tokenize_multimodal_prompt_per_item() {
+    // Given
+    let tokenizer = word_level_tokenizer();
+    let prompt = Prompt::from_vec(vec![
+        Modality::from_image_path("tests/serengeti_elephants.jpg").unwrap(),
+        Modality::from_text("one two three", None),
+        Modality::from_token_ids(vec![49222, 15], None),
+    ]);
+
+    // When
+    let counts = prompt.tokenize(&tokenizer).unwrap();
+
+    // Then
+    assert_eq!(counts.items, [IMAGE_TOKEN_COUNT, 3, 2]);
+    assert_eq!(counts.total, IMAGE_TOKEN_COUNT + 5);
+}
+
+#[test]
+fn check_prompt_and_completion_fit_context() {
+    // Given
+    let tokenizer = word_level_tokenizer();
+    let prompt = Prompt::from_text("one two three");
+
+    // When
+    let fits = fits_context(10, &prompt, 7, &tokenizer);
+    let overflow = fits_context(10, &prompt, 9, &tokenizer);
+
+    // Then
+    assert!(fits.is_ok());
+    assert!(matches!(
+        overflow,
+        Err(BudgetError::Overflow {
+            prompt_tokens: 3,
+            overflow: 2,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn token_spans_use_character_offsets() {
+    // Given
+    let tokenizer = word_level_tokenizer();
+
+    // When
+    let spans = offline::token_spans(&tokenizer, "Grüße one two").unwrap();
+
+    // Then
+    let chars: Vec<_> = spans.iter().map(|span| span.chars.clone()).collect();
+    assert_eq!(chars, [0..5, 6..9, 10..13]);
+    assert_eq!(spans[1].id, 1);
+    assert_eq!(spans[2].control(2.0), TextControl::new(10, 3, 2.0));
+}
+
+#[tokio::test]
+async fn detokenize_completion_output() {
+    // Given
+    let client = Client::new(AA_API_TOKEN.clone()).unwrap();
+    let req = CompletionRequest::from_text(LUMINOUS_BASE, "An apple a day", 5).tokens(true);
+    let response = client.completion(&req, Some(true)).await.unwrap();
+
+    // When
+    let texts = client
+        .detokenize_outputs(LUMINOUS_BASE, &response)
+        .await
+        .unwrap();
+
+    // Then
+    let output = &response.completions[0];
+    let raw = output.raw_completion.as_ref().unwrap_or(&output.completion);
+    assert_eq!(&texts[0], raw);
+}
+
+#[test]
+fn look_up_vocabulary_offline() {
+    // Given
+    let tokenizer = word_level_tokenizer();
+
+    // When
+    let token = offline::id_to_token(&tokenizer, 2);
+    let id = offline::token_to_id(&tokenizer, "one");
+    let matches = offline::tokens_with_prefix(&tokenizer, "t");
+
+    // Then
+    assert_eq!(token.as_deref(), Some("two"));
+    assert_eq!(id, Some(1));
+    assert_eq!(matches, [("two".to_owned(), 2)]);
+    assert_eq!(offline::token_to_id(&tokenizer, "three"), None);
+}
+
+#[test]
+fn aggregate_eval_report() {
+    // Given
+    let jsonl = r#"{"prompt": "An apple a day", "expected_completion": " keeps the doctor away"}
+
+{"prompt": "The cat is on the", "completion_expected": " mat"}"#;
+    let result = |index, perplexity, correct| eval::EvalItemResult {
+        index,
+        log_perplexity_per_character: Some(perplexity),
+        correct_greedy: Some(correct),
+        error: None,
+    };
+
+    // When
+    let items = eval::EvalItem::read_jsonl(jsonl.as_bytes()).unwrap();
+    let report = eval::EvalReport::new(
+        LUMINOUS_BASE,
+        vec![
+            result(0, 0.5, true),
+            result(1, 1.5, false),
+            eval::EvalItemResult {
+                index: 2,
+                log_perplexity_per_character: None,
+                correct_greedy: None,
+                error: Some("Busy".to_owned()),
+            },
+        ],
+    );
+
+    // Then
+    assert_eq!(items.len(), 2);
+    assert_eq!(items[1], eval::EvalItem::new("The cat is on the", " mat"));
+    assert_eq!(report.mean_log_perplexity_per_character, Some(1.0));
+    assert_eq!(report.greedy_accuracy, Some(0.5));
+    assert_eq!(report.failed, 1);
+}
+
+#[test]
+fn rank_labels_by_perplexity() {
+    // When
+    let scores = eval::label_distribution(&["true", "false"], &[2.0, 1.0]);
+
+    // Then
+    assert_eq!(scores[0].label, "false");
+    assert!((scores[0].probability + scores[1].probability - 1.0).abs() < 1e-9);
+    assert!(scores[0].probability > scores[1].probability);
+}
+
+#[tokio::test]
+async fn classify_flat_earth_statement() {
+    // Given
+    let client = Client::new(AA_API_TOKEN.clone()).unwrap();
+
+    // When
+    let scores = client
+        .classify(
+            LUMINOUS_BASE,
+            "The earth is flat. This statement is",
+            &["true.", "false."],
+            Some(true),
+        )
+        .await
+        .unwrap();
+
+    // Then
+    assert_eq!(scores[0].label, "false.");
+}
+
+#[test]
+fn rank_choices_with_and_without_length_normalization() {
+    // Given
+    let result = |log_probability, log_perplexity_per_character| EvaluationResult {
+        log_probability: Some(log_probability),
+        log_perplexity: None,
+        log_perplexity_per_token: None,
+        log_perplexity_per_character: Some(log_perplexity_per_character),
+        correct_greedy: None,
+        token_count: None,
+        character_count: None,
+        completion: None,
+    };
+    let results = [result(-2.0, 1.0), result(-6.0, 0.5)];
+
+    // When
+    let raw = eval::ChoiceRanking::from_results(&results, false).unwrap();
+    let normalized = eval::ChoiceRanking::from_results(&results, true).unwrap();
+
+    // Then
+    assert_eq!(raw.best, 0);
+    assert_eq!(normalized.best, 1);
+    assert_eq!(normalized.scores, [-1.0, -0.5]);
+    assert_eq!(
+        eval::multiple_choice_prompt("Water boils at 100°C.", "When does water boil?"),
+        "Water boils at 100°C.\n\nQuestion: When does water boil?\nAnswer:"
+    );
+}
+
+#[tokio::test]
+async fn rerank_sampled_completions() {
+    // Given
+    let client = Client::new(AA_API_TOKEN.clone()).unwrap();
+    let req = CompletionRequest::from_text(LUMINOUS_BASE, "An apple a day", 5)
+        .n(3)
+        .temperature(1.0);
+    let response = client.completion(&req, Some(true)).await.unwrap();
+
+    // When
+    let ranked = client
+        .rerank_completions(&req, &response, Some(true))
+        .await
+        .unwrap();
+
+    // Then
+    assert_eq!(ranked.len(), 3);
+    assert!(ranked.windows(2).all(|w| w[0].score >= w[1].score));
+}
+
+#[test]
+fn split_text_into_perplexity_windows() {
+    // Given
+    let tokenizer = word_level_tokenizer();
+
+    // When
+    let windows = eval::perplexity_windows(&tokenizer, "a b c d e f", 3, 1).unwrap();
+
+    // Then
+    assert_eq!(
+        windows,
+        [
+            ("a".to_owned(), " b c".to_owned()),
+            ("c".to_owned(), " d e".to_owned()),
+            ("e".to_owned(), " f".to_owned()),
+        ]
+    );
+}
+
+#[test]
+fn perplexity_windows_require_context() {
+    // Given
+    let tokenizer = word_level_tokenizer();
+
+    // When
+    let result = eval::perplexity_windows(&tokenizer, "a  b   c", 3, 0);
+
+    // Then
+    assert!(matches!(
+        result,
+        Err(ApiError::Validation(ValidationError::OutOfRange {
+            parameter: "context_size",
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn aggregate_corpus_perplexity() {
+    // When
+    let corpus = eval::CorpusPerplexity::from_windows(&[(10.0, 10), (30.0, 10)]).unwrap();
+    let single = eval::CorpusPerplexity::from_windows(&[(5.0, 10)]).unwrap();
+
+    // Then
+    assert_eq!(corpus.log_perplexity_per_character, 2.0);
+    assert!(corpus.confidence_interval.0 < 2.0 && corpus.confidence_interval.1 > 2.0);
+    assert_eq!(single.confidence_interval, (0.5, 0.5));
+    assert_eq!(eval::CorpusPerplexity::from_windows(&[]), None);
+}
+
+#[test]
+fn compare_models_by_perplexity_per_character() {
+    // Given
+    let report = |model: &str, perplexity| eval::EvalReport {
+        model: model.to_owned(),
+        mean_log_perplexity_per_character: Some(perplexity),
+        greedy_accuracy: Some(0.5),
+        failed: 0,
+        items: Vec::new(),
+    };
+
+    // When
+    let comparison = eval::ModelComparison::from_reports(&[
+        report("luminous-base", 1.25),
+        report("luminous-extended", 1.0),
+    ]);
+    let table = comparison.to_string();
+    let json = serde_json::to_value(&comparison).unwrap();
+
+    // Then
+    assert_eq!(comparison.best().unwrap().model, "luminous-extended");
+    assert!(table.contains("luminous-base     |       1.2500 |     0.5000 |      0"));
+    assert_eq!(json["models"][1]["mean_log_perplexity_per_character"], 1.0);
+}
+
+#[test]
+fn build_explanation_request() {
+    // Given
+    let req = ExplanationRequest::new(LUMINOUS_BASE, Prompt::from_text("An apple a day"), " keeps")
+        .target_granularity(TargetGranularity::Token)
+        .normalize(true);
+
+    // When
+    let json = serde_json::to_value(&req).unwrap();
+
+    // Then
+    assert_eq!(json["target"], " keeps");
+    assert_eq!(json["normalize"], true);
+    assert_eq!(json["target_granularity"], "token");
+    assert!(json.get("prompt_granularity").is_none());
+}
+
+#[test]
+fn map_explanation_scores_to_prompt_text() {
+    // Given
+    let prompt = Prompt::from_vec(vec![
+        Modality::from_text("Grüße aus Köln", None),
+        Modality::from_token_ids(vec![1, 2], None),
+    ]);
+    let response: ExplanationResponse = serde_json::from_str(
+        r#"{"model_version": "2022-04", "explanations": [{"target": " Dom", "items": [
+            {"type": "text", "scores": [{"start": 0, "length": 5, "score": 0.5},
+                                        {"start": 10, "length": 10, "score": 1.0}]},
+            {"type": "token_ids", "scores": [0.1, 0.2]},
+            {"type": "target", "scores": []}]}]}"#,
+    )
+    .unwrap();
+
+    // When
+    let spans = response.explanations[0].text_spans(&prompt);
+
+    // Then
+    let text = spans[0].as_ref().unwrap();
+    assert_eq!(text[0].text, "Grüße");
+    assert_eq!(text[0].range, 0..7);
+    assert_eq!(text[1].text, "Köln");
+    assert_eq!(spans[1], None);
+}
+
+#[test]
+fn render_explanation_heatmaps() {
+    // Given
+    let text = "<b> is bold";
+    let spans = [
+        ScoredSpan {
+            text: "<b>",
+            range: 0..3,
+            score: 1.0,
+        },
+        ScoredSpan {
+            text: "bold",
+            range: 7..11,
+            score: -0.5,
+        },
+    ];
+
+    // When
+    let ansi = heatmap::render_ansi(text, &spans);
+    let html = heatmap::render_html(text, &spans);
+
+    // Then
+    assert!(ansi.starts_with("\x1b[48;2;255;0;0m\x1b[30m<b>\x1b[0m is "));
+    assert_eq!(
+        html,
+        "<span style=\"background-color: rgb(255, 0, 0)\" title=\"1\">&lt;b&gt;</span> is \
+        <span style=\"background-color: rgb(127, 127, 255)\" title=\"-0.5\">bold</span>"
+    );
+}
+
+#[test]
+fn overlay_image_explanation_heatmap() {
+    // Given
+    let image =
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(4, 4, image::Rgb([0, 0, 0])));
+    let scores = [
+        ScoredRect {
+            rect: BoundingBox::new(0.0, 0.0, 0.5, 0.5),
+            score: 2.0,
+        },
+        ScoredRect {
+            rect: BoundingBox::new(0.5, 0.5, 0.5, 0.5),
+            score: -1.0,
+        },
+    ];
+
+    // When
+    let overlay = image_processing::overlay_heatmap(&image, &scores).to_rgba8();
+
+    // Then
+    assert_eq!(overlay.get_pixel(1, 1).0, [153, 0, 0, 255]);
+    assert_eq!(overlay.get_pixel(3, 3).0, [0, 0, 77, 255]);
+    assert_eq!(overlay.get_pixel(3, 0).0, [0, 0, 0, 255]);
+    assert!(image::load_from_memory(
+        &image_processing::overlay_heatmap_png(&image, &scores).unwrap()
+    )
+    .is_ok());
+}
+
+#[test]
+fn serialize_prompt_granularity() {
+    // Given
+    let word = PromptGranularity::word();
+    let custom = PromptGranularity::custom("\n\n");
+
+    // When
+    let word = serde_json::to_value(&word).unwrap();
+    let custom = serde_json::to_value(&custom).unwrap();
+
+    // Then
+    assert_eq!(word, serde_json::json!({"type": "word"}));
+    assert_eq!(
+        custom,
+        serde_json::json!({"type": "custom", "delimiter": "\n\n"})
+    );
+}
+
+#[test]
+fn explanation_response_roundtrip() {
+    // Given
+    let json = serde_json::json!({
+        "model_version": "2022-04",
+        "explanations": [{
+            "target": " pizza",
+            "items": [
+                {"type": "text", "scores": [{"start": 0, "length": 4, "score": 0.5}]},
+                {"type": "image", "scores": [{
+                    "rect": {"left": 0.0, "top": 0.0, "width": 1.0, "height": 1.0},
+                    "score": 0.25
+                }]},
+                {"type": "target", "scores": []}
+            ]
+        }]
+    });
+    let response: ExplanationResponse = serde_json::from_value(json.clone()).unwrap();
+
+    // When
+    let serialized = serde_json::to_value(&response).unwrap();
+
+    // Then
+    assert_eq!(serialized, json);
+    assert_eq!(
+        serde_json::from_value::<ExplanationResponse>(serialized).unwrap(),
+        response.clone()
+    );
+}
+
+#[test]
+fn aggregate_token_explanation() {
+    // Given
+    let text = "I like pizza. You too!";
+    let segment = |start, length, score| ScoredSegment {
+        start,
+        length,
+        score,
+    };
+    let tokens = [
+        segment(0, 1, 0.5),
+        segment(1, 5, 1.0),
+        segment(6, 4, 2.0),
+        segment(10, 3, 4.0),
+        segment(13, 4, 1.0),
+        segment(17, 4, 3.0),
+        segment(21, 1, 1.0),
+    ];
+
+    // When
+    let words = aggregate_segments(text, &tokens, TextUnit::Word, ScoreAggregation::Sum);
+    let sentences = aggregate_segments(text, &tokens, TextUnit::Sentence, ScoreAggregation::Max);
+
+    // Then
+    assert_eq!(
+        words,
+        vec![
+            segment(0, 1, 0.5),
+            segment(2, 4, 1.0),
+            segment(7, 6, 6.0),
+            segment(14, 3, 1.0),
+            segment(18, 4, 4.0),
+        ]
+    );
+    assert_eq!(sentences, vec![segment(0, 13, 4.0), segment(14, 8, 3.0)]);
+}
+
+#[test]
+fn image_prompt_with_crop() {
+    // Given
+    let image = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(20, 10, |x, _| {
+        if x < 10 {
+            image::Rgb([255, 0, 0])
+        } else {
+            image::Rgb([0, 0, 255])
+        }
+    }));
+
+    // When
+    let right = Modality::from_image_with_crop(&image, Crop::new(10, 0, 10)).unwrap();
+    let too_large = Modality::from_image_with_crop(&image, Crop::new(5, 5, 10));
+
+    // Then
+    let Modality::Image { data, .. } = right else {
+        panic!("expected an image item");
+    };
+    let decoded = image::load_from_memory(data.as_bytes()).unwrap().to_rgb8();
+    assert_eq!(decoded.dimensions(), (10, 10));
+    assert_eq!(decoded.get_pixel(0, 0).0, [0, 0, 255]);
+    assert!(matches!(
+        too_large,
+        Err(image_processing::LoadImageError::InvalidCrop {
+            width: 20,
+            height: 10,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn letterbox_wide_image() {
+    // Given
+    let image = image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+        40,
+        20,
+        image::Rgb([255, 0, 0]),
+    ));
+    let options = ImageOptions::new().strategy(ResizeStrategy::Letterbox { fill: [0, 0, 255] });
+
+    // When
+    let modality = Modality::from_image_with_options(&image, &options).unwrap();
+
+    // Then
+    let Modality::Image { data, .. } = modality else {
+        panic!("expected an image item");
+    };
+    let decoded = image::load_from_memory(data.as_bytes()).unwrap().to_rgb8();
+    assert_eq!(decoded.dimensions(), (40, 40));
+    assert_eq!(decoded.get_pixel(20, 2).0, [0, 0, 255]);
+    assert_eq!(decoded.get_pixel(20, 20).0, [255, 0, 0]);
+    assert_eq!(decoded.get_pixel(20, 37).0, [0, 0, 255]);
+}
+
+#[test]
+fn downscale_image_to_max_edge() {
+    // Given
+    let photo = image::DynamicImage::ImageRgb8(image::RgbImage::new(1200, 900));
+    let options = ImageOptions::new().max_edge(512);
+
+    // When
+    let modality = Modality::from_image_with_options(&photo, &options).unwrap();
+
+    // Then
+    let Modality::Image { data, .. } = modality else {
+        panic!("expected an image item");
+    };
+    let decoded = image::load_from_memory(data.as_bytes()).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (512, 512));
+}
+
+#[test]
+fn load_image_respects_exif_orientation() {
+    // Given a 4x2 JPEG whose EXIF orientation says it must be rotated by 90 degrees
+    let mut jpeg = Vec::new();
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(4, 2))
+        .write_to(
+            &mut std::io::Cursor::new(&mut jpeg),
+            image::ImageFormat::Jpeg,
+        )
+        .unwrap();
+    let app1: &[u8] = &[
+        0xFF, 0xE1, 0x00, 0x22, b'E', b'x', b'i', b'f', 0, 0, b'M', b'M', 0x00, 0x2A, 0, 0, 0, 8,
+        0x00, 0x01, 0x01, 0x12, 0x00, 0x03, 0, 0, 0, 1, 0x00, 0x06, 0, 0, 0, 0, 0, 0,
+    ];
+    jpeg.splice(2..2, app1.iter().copied());
+    let path = std::env::temp_dir().join("aleph-alpha-api-exif-orientation.jpg");
+    std::fs::write(&path, &jpeg).unwrap();
+
+    // When
+    let image = image_processing::load_image(&path).unwrap();
+
+    // Then
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(image_processing::exif_orientation(&jpeg), Some(6));
+    assert_eq!((image.width(), image.height()), (2, 4));
+}
+
+#[cfg(feature = "gif")]
+#[test]
+fn decode_first_frame_of_gif() {
+    // Given
+    let mut gif = Vec::new();
+    image::DynamicImage::ImageRgba8(image::RgbaImage::new(3, 2))
+        .write_to(&mut std::io::Cursor::new(&mut gif), image::ImageFormat::Gif)
+        .unwrap();
+
+    // When
+    let decoded = image_processing::decode_image(&gif);
+    let garbage = image_processing::decode_image(b"no image");
+
+    // Then
+    let decoded = decoded.unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (3, 2));
+    assert!(matches!(
+        garbage,
+        Err(image_processing::LoadImageError::UnknownImageFormat(_))
+    ));
+}
+
+#[cfg(feature = "image-formats")]
+#[test]
+fn decode_bmp_by_default() {
+    // Given
+    let mut bmp = Vec::new();
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(3, 2))
+        .write_to(&mut std::io::Cursor::new(&mut bmp), image::ImageFormat::Bmp)
+        .unwrap();
+
+    // When
+    let decoded = image_processing::decode_image(&bmp).unwrap();
+
+    // Then
+    assert_eq!((decoded.width(), decoded.height()), (3, 2));
+}
+
+/// Serves a single HTTP request with `response` and returns the URL to request together with a
+/// receiver for the raw request.
+fn serve_once(response: Vec<u8>) -> (String, std::sync::mpsc::Receiver<String>) {
+    serve_in_order(vec![response])
+}
+
+/// Like [`serve_once`], but answers one connection per response, in order.
+fn serve_in_order(responses: Vec<Vec<u8>>) -> (String, std::sync::mpsc::Receiver<String>) {
+    use std::io::{Read, Write};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/image", listener.local_addr().unwrap());
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for response in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            stream.write_all(&response).unwrap();
+            // Tests not interested in the requests drop the receiver.
+            let _ = sender.send(String::from_utf8_lossy(&request).into_owned());
+        }
+    });
+    (url, receiver)
+}
+
+fn http_response(content_type: &str, body: &[u8]) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+        Connection: close\r\n\r\n",
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+#[tokio::test]
+async fn image_prompt_from_url() {
+    // Given
+    let mut png = Vec::new();
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(8, 8))
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let client = Client::new("secret-token".to_owned()).unwrap();
+    let (image_url, image_request) = serve_once(http_response("image/png", &png));
+    let (html_url, _) = serve_once(http_response("text/html", b"<html></html>"));
+    let (large_url, _) = serve_once(http_response("image/png", &png));
+    let mut untyped_response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        png.len()
+    )
+    .into_bytes();
+    untyped_response.extend_from_slice(&png);
+    let (untyped_url, _) = serve_once(untyped_response);
+
+    // When
+    let image = Modality::from_image_url(&client, &image_url, &ImageOptions::new()).await;
+    let html = Modality::from_image_url(&client, &html_url, &ImageOptions::new()).await;
+    let large =
+        Modality::from_image_url(&client, &large_url, &ImageOptions::new().max_bytes(16)).await;
+    let untyped = Modality::from_image_url(&client, &untyped_url, &ImageOptions::new()).await;
+
+    // Then
+    assert!(matches!(image, Ok(Modality::Image { .. })));
+    assert!(!image_request.recv().unwrap().contains("secret-token"));
+    assert!(
+        matches!(html, Err(FetchImageError::NotAnImage(content_type)) if content_type == "text/html")
+    );
+    assert!(matches!(
+        large,
+        Err(FetchImageError::TooLarge { limit: 16 })
+    ));
+    assert!(matches!(untyped, Err(FetchImageError::MissingContentType)));
+}
+
+#[test]
+fn image_prompt_from_uploaded_bytes() {
+    // Given
+    let encode = |image: image::DynamicImage, format| {
+        let mut bytes = Vec::new();
+        image
+            .write_to(&mut std::io::Cursor::new(&mut bytes), format)
+            .unwrap();
+        bytes
+    };
+    let square_png = encode(
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(16, 16)),
+        image::ImageFormat::Png,
+    );
+    let wide_jpeg = encode(
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(32, 16)),
+        image::ImageFormat::Jpeg,
+    );
+    let options = ImageOptions::new();
+
+    // When
+    let square = Modality::from_image_bytes(&square_png, &options).unwrap();
+    let wide = Modality::from_image_bytes(&wide_jpeg, &options).unwrap();
+    let too_large = Modality::from_image_bytes(&square_png, &options.clone().max_bytes(10));
+
+    // Then
+    let decode = |modality| match modality {
+        Modality::Image { data, .. } => data.as_bytes().to_vec(),
+        _ => panic!("expected an image item"),
+    };
+    assert_eq!(decode(square), square_png);
+    let wide = image::load_from_memory(&decode(wide)).unwrap();
+    assert_eq!((wide.width(), wide.height()), (16, 16));
+    assert!(matches!(
+        too_large,
+        Err(image_processing::LoadImageError::TooLarge { limit: 10, .. })
+    ));
+}
+
+#[test]
+fn image_prompt_from_float_image_bytes() {
+    // Given
+    let mut exr = Vec::new();
+    image::DynamicImage::ImageRgba32F(image::Rgba32FImage::new(8, 4))
+        .write_to(
+            &mut std::io::Cursor::new(&mut exr),
+            image::ImageFormat::OpenExr,
+        )
+        .unwrap();
+
+    // When
+    let processed = image_processing::preprocess_image_bytes(&exr, &Default::default()).unwrap();
+    let too_large =
+        image_processing::preprocess_image_bytes(&exr, &ImageOptions::new().max_decoded_edge(4));
+
+    // Then
+    let processed = image::load_from_memory(&processed).unwrap();
+    assert_eq!((processed.width(), processed.height()), (4, 4));
+    assert!(matches!(
+        too_large,
+        Err(image_processing::LoadImageError::ExceedsLimits(_))
+    ));
+}
+
+#[test]
+fn image_prompt_data_url_roundtrip() {
+    // Given
+    let mut png = Vec::new();
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(4, 4))
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let url = format!("data:image/png;base64,{}", BASE64_STANDARD.encode(&png));
+
+    // When
+    let modality = Modality::from_data_url(&url, &ImageOptions::new()).unwrap();
+    let invalid = Modality::from_data_url("data:image/png,raw", &ImageOptions::new());
+
+    // Then
+    assert_eq!(modality.to_data_url(), Some(url));
+    assert_eq!(Modality::from_text("text", None).to_data_url(), None);
+    assert!(matches!(
+        invalid,
+        Err(image_processing::LoadImageError::InvalidDataUrl)
+    ));
+}
+
+#[test]
+fn encode_image_prompt_as_jpeg() {
+    // Given
+    let photo = image::DynamicImage::ImageRgba8(image::RgbaImage::from_fn(64, 64, |x, y| {
+        image::Rgba([(x * 4) as u8, (y * 4) as u8, 128, 255])
+    }));
+    let options = ImageOptions::new().encoding(ImageEncoding::Jpeg { quality: 85 });
+
+    // When
+    let modality = Modality::from_image_with_options(&photo, &options).unwrap();
+
+    // Then
+    assert!(modality
+        .to_data_url()
+        .unwrap()
+        .starts_with("data:image/jpeg;base64,"));
+}
+
+#[test]
+fn preview_model_visible_square() {
+    // Given a red, green and blue stripe next to each other
+    let stripes = image::DynamicImage::ImageRgb8(image::RgbImage::from_fn(60, 20, |x, _| {
+        let mut pixel = [0; 3];
+        pixel[(x / 20) as usize] = 255;
+        image::Rgb(pixel)
+    }));
+    let mut png = Vec::new();
+    stripes
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let crop = |x, y, size| Modality::Image {
+        data: png.clone().into(),
+        x: Some(x),
+        y: Some(y),
+        size: Some(size),
+        controls: None,
+    };
+    let server_side_crop = crop(40, 0, 20);
+
+    // When
+    let center = Modality::from_image(&stripes)
+        .unwrap()
+        .model_view()
+        .unwrap();
+    let right = server_side_crop.model_view().unwrap().to_rgb8();
+
+    // Then
+    assert_eq!((center.width(), center.height()), (384, 384));
+    assert_eq!(center.to_rgb8().get_pixel(0, 0).0, [0, 255, 0]);
+    assert_eq!(right.get_pixel(0, 0).0, [0, 0, 255]);
+    assert!(image::load_from_memory(&server_side_crop.model_view_png().unwrap().unwrap()).is_ok());
+    assert!(Modality::from_text("text", None).model_view_png().is_none());
+    assert!(crop(-1, 0, 20).model_view().is_none());
+    assert!(crop(0, 0, 0).model_view().is_none());
+    assert!(crop(50, 0, 20).model_view().is_none());
+}
+
+#[test]
+fn image_data_serializes_as_base64() {
+    // Given
+    let modality = Modality::Image {
+        data: ImageData::from(vec![0u8, 1, 2, 255]),
+        x: None,
+        y: None,
+        size: None,
+        controls: None,
+    };
+
+    // When
+    let json = serde_json::to_value(&modality).unwrap();
+
+    // Then
+    assert_eq!(
+        json,
+        serde_json::json!({"type": "image", "data": "AAEC/w=="})
+    );
+    assert_eq!(serde_json::from_value::<Modality>(json).unwrap(), modality);
+}
+
+#[test]
+fn reject_oversized_prompt() {
+    // Given
+    let image = Modality::Image {
+        data: ImageData::from(vec![0u8; 3000]),
+        x: None,
+        y: None,
+        size: None,
+        controls: None,
+    };
+    let prompt = Prompt::from_vec(vec![Modality::from_text("abc", None), image]);
+
+    // When
+    let sizes = prompt.payload_sizes();
+    let result = prompt.check_payload_size(3000);
+
+    // Then
+    assert_eq!(
+        sizes,
+        vec![r#"{"type":"text","data":"abc"}"#.len(), 4000 + 26]
+    );
+    assert_eq!(
+        result,
+        Err(ValidationError::PayloadTooLarge {
+            size: sizes.iter().sum(),
+            limit: 3000,
+            item_sizes: sizes,
+        })
+    );
+}
+
+#[tokio::test]
+async fn reject_oversized_prompt_before_sending() {
+    // Given a client whose server would fail every request
+    let client = Client::new_with_base_url("http://127.0.0.1:9".to_owned(), "token".to_owned())
+        .expect("failed to create client");
+    let req = CompletionRequest::new(LUMINOUS_BASE, Prompt::from_text("a".repeat(100)), 10);
+    let options = RequestOptions::default().max_request_bytes(50);
+
+    // When
+    let result = client.completion_with_options(&req, &options).await;
+
+    // Then
+    assert!(matches!(
+        result,
+        Err(ApiError::Validation(ValidationError::PayloadTooLarge {
+            limit: 50,
+            ..
+        }))
+    ));
+    assert_eq!(req.validate(), Ok(()));
+}
+
+#[tokio::test]
+async fn list_models() {
+    let client = Client::new(AA_API_TOKEN.clone()).expect("failed to create client");
+
+    let models = client.list_models().await.unwrap();
+
+    assert!(models.iter().any(|model| model.name == LUMINOUS_BASE));
+}
+
+#[test]
+fn deserialize_model_info() {
+    // Given
+    let json = r#"[{
+        "name": "luminous-base",
+        "description": "Multilingual model trained on English, German, French, Spanish and Italian",
+        "hostings": ["aleph-alpha"],
+        "image_support": true,
+        "qa_support": true,
+        "summarization_support": false,
+        "embedding_types": ["symmetric", "document", "query"],
+        "maximum_context_size": 2048,
+        "prompt_template": null
+    }, {"name": "some-future-model"}]"#;
+
+    // When
+    let models: Vec<ModelInfo> = serde_json::from_str(json).unwrap();
+
+    // Then
+    assert_eq!(models[0].maximum_context_size, Some(2048));
+    assert!(models[0].image_support && models[0].embedding_support());
+    assert!(!models[1].embedding_support());
+}
+
+#[test]
+fn model_registry_with_api_models() {
+    // Given
+    let mut registry = ModelRegistry::luminous();
+    let info: ModelInfo = serde_json::from_value(serde_json::json!({
+        "name": "luminous-nextgen-control-chat",
+        "image_support": true,
+        "maximum_context_size": 8192,
+        "prompt_template": "{{instruction}}"
+    }))
+    .unwrap();
+
+    // When
+    registry.update(&[info]);
+
+    // Then
+    let base = registry.get(LUMINOUS_BASE).unwrap();
+    assert!(base.multimodal && !base.control);
+    assert_eq!(
+        registry.context_length(LUMINOUS_SUPREME_CONTROL),
+        Some(2048)
+    );
+    let nextgen = registry.get("luminous-nextgen-control-chat").unwrap();
+    assert_eq!(
+        *nextgen,
+        ModelCapabilities {
+            context_length: 8192,
+            multimodal: true,
+            control: true,
+            chat: true,
+        }
+    );
+    assert_eq!(nextgen.prompt_budget(192), 8000);
+}
+
+#[test]
+fn truncate_prompt_to_model_context() {
+    // Given
+    let tokenizer = word_level_tokenizer();
+    let capabilities = ModelCapabilities {
+        context_length: 5,
+        multimodal: false,
+        control: false,
+        chat: false,
+    };
+    let mut prompt = Prompt::from_text("one two one two one two");
+
+    // When
+    let fits_before = capabilities.fits_context(&prompt, 2, &tokenizer).is_ok();
+    capabilities
+        .truncate(&mut prompt, 2, &tokenizer, TruncationStrategy::Head)
+        .unwrap();
+
+    // Then
+    assert!(!fits_before);
+    assert!(capabilities.fits_context(&prompt, 2, &tokenizer).is_ok());
+    assert_eq!(prompt.estimate_tokens(&tokenizer).unwrap(), 3);
+}
+
+#[tokio::test]
+async fn summarize_with_luminous_extended() {
+    let client = Client::new(AA_API_TOKEN.clone()).expect("failed to create client");
+    let document = Document::from_text(
+        "Leonardo da Vinci was an Italian polymath of the High Renaissance who was active as a \
+        painter, draughtsman, engineer, scientist, theorist, sculptor, and architect.",
+    );
+    let req = SummarizationRequest::new(LUMINOUS_EXTENDED, document);
+
+    let response = client.summarize(&req, Some(true)).await.unwrap();
+
+    assert!(!response.summary.is_empty());
+}
+
+#[test]
+fn serialize_summarization_request() {
+    // Given
+    let document = Document::from_text("Some text");
+
+    // When
+    let req = SummarizationRequest::new(LUMINOUS_EXTENDED, document).hosting(Hosting::AlephAlpha);
+
+    // Then
+    assert_eq!(
+        serde_json::to_value(&req).unwrap(),
+        serde_json::json!({
+            "model": "luminous-extended",
+            "hosting": "aleph-alpha",
+            "document": {"text": "Some text"}
+        })
+    );
+}
+
+#[tokio::test]
+async fn qa_with_documents() {
+    let client = Client::new(AA_API_TOKEN.clone()).expect("failed to create client");
+    let document = Document::from_text("Andreas likes pizza, but Lisa prefers pasta.");
+    let req = QaRequest::new("What does Lisa like?", vec![document]).max_answers(1);
+
+    let response = client.qa(&req, Some(true)).await.unwrap();
+
+    assert!(response.answers.len() <= 1);
+}
+
+#[test]
+fn best_qa_answer() {
+    // Given
+    let json = serde_json::json!({
+        "model_version": "2022-04",
+        "answers": [
+            {"answer": "pizza", "score": 0.25, "evidence": "Andreas likes pizza"},
+            {"answer": "pasta", "score": 0.75, "evidence": "Lisa prefers pasta"}
+        ]
+    });
+
+    // When
+    let response: QaResponse = serde_json::from_value(json).unwrap();
+
+    // Then
+    assert_eq!(response.best_answer().unwrap().answer, "pasta");
+}
+
+#[tokio::test]
+async fn search_documents() {
+    let client = Client::new(AA_API_TOKEN.clone()).expect("failed to create client");
+    let documents = ["Pizza is from Italy.", "The Eiffel tower is in Paris."]
+        .into_iter()
+        .map(Document::from)
+        .collect();
+    let req =
+        SearchRequest::new(LUMINOUS_BASE, "Where is the Eiffel tower?", documents).max_results(1);
+
+    let response = client.search(&req, Some(true)).await.unwrap();
+
+    assert!(response
+        .results
+        .iter()
+        .all(|result| result.document(&req).is_some()));
+}
+
+#[test]
+fn link_search_results_to_documents() {
+    // Given
+    let documents = vec![Document::from_text("first"), Document::from_text("second")];
+    let req = SearchRequest::new(LUMINOUS_BASE, "query", documents);
+    let json = serde_json::json!({
+        "model_version": "2022-04",
+        "results": [{"index": 1, "section": "second", "score": 0.9}]
+    });
+
+    // When
+    let response: SearchResponse = serde_json::from_value(json).unwrap();
+
+    // Then
+    let document = response.results[0].document(&req).unwrap();
+    assert_eq!(document.as_text(), Some("second"));
+}
+
+#[test]
+fn load_docx_document() {
+    // Given
+    let docx = b"PK\x03\x04rest of the archive";
+
+    // When
+    let document = Document::from_docx_bytes(docx, MAX_DOCX_BYTES).unwrap();
+    let not_docx = Document::from_docx_bytes(b"plain text", MAX_DOCX_BYTES);
+    let too_large = Document::from_docx_bytes(docx, 8);
+
+    // Then
+    assert_eq!(
+        serde_json::to_value(&document).unwrap(),
+        serde_json::json!({"docx": BASE64_STANDARD.encode(docx)})
+    );
+    assert!(matches!(not_docx, Err(LoadDocumentError::NotDocx)));
+    assert!(matches!(
+        too_large,
+        Err(LoadDocumentError::TooLarge { limit: 8, .. })
+    ));
+}
+
+#[tokio::test]
+async fn create_and_delete_api_token() {
+    let client = Client::new(AA_API_TOKEN.clone()).expect("failed to create client");
+
+    let created = client
+        .create_api_token(&CreateApiTokenRequest::new("integration test"))
+        .await
+        .unwrap();
+    let listed = client.list_api_tokens().await.unwrap();
+    client
+        .delete_api_token(created.metadata.token_id)
+        .await
+        .unwrap();
+
+    assert!(!created.token.is_empty());
+    assert!(listed.contains(&created.metadata));
+}
+
+#[tokio::test]
+async fn change_user_settings() {
+    let client = Client::new(AA_API_TOKEN.clone()).expect("failed to create client");
+    let before = client.get_user_settings().await.unwrap();
+
+    let change = UserChange::new().out_of_credits_threshold(before.out_of_credits_threshold);
+    let after = client.change_user_settings(&change).await.unwrap();
+
+    assert_eq!(
+        after.out_of_credits_threshold,
+        before.out_of_credits_threshold
+    );
+    assert_eq!(after.email, before.email);
+}
+
+#[test]
+fn model_names() {
+    // Given
+    let names = ["pharia-1-llm-7b-control", "my-finetune"];
+
+    // When
+    let models: Vec<Model> = names.into_iter().map(Model::from).collect();
+
+    // Then
+    assert_eq!(models[0], Model::Pharia1Llm7BControl);
+    assert_eq!(models[1], Model::Custom("my-finetune".to_owned()));
+    assert_eq!(models[1].to_string(), "my-finetune");
+    let req = EvaluationRequest::from_text(Model::LuminousSupreme, "prompt", "completion");
+    assert_eq!(req.model, LUMINOUS_SUPREME);
+}
+
+#[test]
+fn parse_api_version() {
+    // Given
+    let versions = ["1.16.2", "v1.4", "1.4.0-rc1", "2.0.0+build7", "1.x"];
+
+    // When
+    let parsed: Vec<_> = versions.iter().map(|v| v.parse::<ApiVersion>()).collect();
+
+    // Then
+    assert_eq!(parsed[0], Ok(ApiVersion::new(1, 16, 2)));
+    assert_eq!(parsed[1], Ok(ApiVersion::new(1, 4, 0)));
+    assert_eq!(parsed[2].as_ref().unwrap().to_string(), "1.4.0-rc1");
+    assert!(parsed[2].as_ref().unwrap() < parsed[1].as_ref().unwrap());
+    assert_eq!(parsed[3], Ok(ApiVersion::new(2, 0, 0)));
+    assert!(parsed[4].is_err());
+}
+
+/// Response of a probed endpoint with `status`, see [`Client::capabilities`].
+fn probe_response(status: &str) -> Vec<u8> {
+    format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").into_bytes()
+}
+
+/// Responses of a deployment of `version` serving all probed endpoints.
+fn current_deployment(version: &str) -> Vec<Vec<u8>> {
+    let mut responses = vec![http_response("text/plain", version.as_bytes())];
+    responses.extend((0..3).map(|_| probe_response("422 Unprocessable Entity")));
+    responses
+}
+
+#[tokio::test]
+async fn capabilities_of_old_deployment() {
+    // Given a deployment without the batch semantic embedding endpoint
+    let (url, requests) = serve_in_order(vec![
+        http_response("text/plain", b"1.7.3"),
+        probe_response("400 Bad Request"),
+        probe_response("404 Not Found"),
+        probe_response("422 Unprocessable Entity"),
+    ]);
+    let base_url = url.trim_end_matches("/image").to_owned();
+    let client = Client::new_with_base_url(base_url, "token".to_owned())
+        .unwrap()
+        .with_minimum_version(Feature::Steering, ApiVersion::new(1, 18, 0))
+        .with_minimum_version(Feature::RawCompletion, ApiVersion::new(1, 7, 0));
+
+    // When
+    let capabilities = client.capabilities().await.unwrap();
+
+    // Then
+    assert!(requests.recv().unwrap().starts_with("GET /version"));
+    assert!(requests.recv().unwrap().starts_with("POST /explain"));
+    assert!(requests
+        .recv()
+        .unwrap()
+        .starts_with("POST /batch_semantic_embed"));
+    assert_eq!(capabilities.version, ApiVersion::new(1, 7, 3));
+    assert_eq!(capabilities.supports(Feature::Explanation), Some(true));
+    assert_eq!(
+        capabilities.supports(Feature::BatchSemanticEmbed),
+        Some(false)
+    );
+    assert_eq!(
+        capabilities.supports(Feature::InstructableEmbed),
+        Some(true)
+    );
+    assert_eq!(capabilities.supports(Feature::RawCompletion), Some(true));
+    assert_eq!(capabilities.supports(Feature::Steering), Some(false));
+    assert_eq!(capabilities.supports(Feature::LogitBias), None);
+    assert_eq!(
+        capabilities.minimum_version(Feature::Steering),
+        Some(&ApiVersion::new(1, 18, 0))
+    );
+    assert_eq!(
+        capabilities.features(),
+        [
+            Feature::Explanation,
+            Feature::InstructableEmbed,
+            Feature::RawCompletion
+        ]
+    );
+}
+
+#[tokio::test]
+async fn strict_mode_rejects_unsupported_parameters() {
+    // Given
+    let (url, _) = serve_in_order(current_deployment("1.8.0"));
+    let base_url = url.trim_end_matches("/image").to_owned();
+    let client = Client::new_with_base_url(base_url, "token".to_owned())
+        .unwrap()
+        .with_minimum_version(Feature::RawCompletion, ApiVersion::new(1, 9, 0))
+        .with_minimum_version(Feature::LogitBias, ApiVersion::new(1, 14, 0));
+    let req = CompletionRequest::from_text(LUMINOUS_BASE, "Hello", 10)
+        .sequence_penalty(0.5)
+        .raw_completion(true)
+        .logit_bias(HashMap::from([(1, 0.5)]));
+    let options = RequestOptions::default().strict(true);
+
+    // When
+    let warnings = client.unsupported_parameters(&req).await.unwrap();
+    let error = client.completion_with_options(&req, &options).await;
+    let unknown = req.unsupported_parameters(&ApiCapabilities::new(ApiVersion::new(1, 0, 0)));
+
+    // Then
+    let parameters: Vec<_> = warnings.iter().map(|w| w.parameter).collect();
+    assert_eq!(
+        parameters,
+        ["sequence_penalty", "raw_completion", "logit_bias"]
+    );
+    assert_eq!(
+        warnings[0].to_string(),
+        "support of sequence_penalty is unknown"
+    );
+    assert_eq!(
+        warnings[2].to_string(),
+        "logit_bias requires API version 1.14.0"
+    );
+    assert_eq!(unknown.len(), 3);
+    assert!(unknown.iter().all(|parameter| parameter.since.is_none()));
+    assert!(matches!(
+        error,
+        Err(ApiError::Validation(ValidationError::Unsupported { parameters, .. }))
+            if parameters == warnings
+    ));
+}
+
+/// Counts the occurrences of "one" and "two", so texts are similar if they share words.
+struct WordCountEmbedder;
+
+impl rag::Embedder for WordCountEmbedder {
+    async fn embed_documents(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, ApiError> {
+        let mut embeddings = Vec::new();
+        for text in texts {
+            embeddings.push(self.embed_query(text).await?);
+        }
+        Ok(embeddings)
+    }
+
+    async fn embed_query(&self, text: &str) -> Result<Vec<f32>, ApiError> {
+        let count = |word| text.split_whitespace().filter(|w| *w == word).count() as f32;
+        Ok(vec![count("one"), count("two")])
+    }
+}
+
+#[tokio::test]
+async fn rag_pipeline_with_custom_embedder() {
+    // Given
+    let tokenizer = std::sync::Arc::new(word_level_tokenizer());
+    let mut pipeline = rag::RagPipeline::new(
+        rag::TokenChunker::new(tokenizer, 2, 0),
+        WordCountEmbedder,
+        VectorIndex::new(),
+        rag::GroundedPromptAssembler::default(),
+    );
+
+    // When the pipeline runs in a spawned task
+    let (chunks, grounded) = tokio::spawn(async move {
+        let chunks = pipeline
+            .add_document("numbers.txt", "one one two two")
+            .await
+            .unwrap();
+        (chunks, pipeline.prompt("two", 1).await.unwrap())
+    })
+    .await
+    .unwrap();
+
+    // Then
+    assert_eq!(chunks, 2);
+    assert_eq!(grounded.sources.len(), 1);
+    assert_eq!(grounded.sources[0].chunk.text, "two two");
+    assert_eq!(grounded.sources[0].chunk.range, 8..15);
+    let Modality::Text { data, .. } = &grounded.prompt.items()[0] else {
+        panic!("expected a text prompt");
+    };
+    assert!(data.contains("[1] (numbers.txt) two two\n"));
+    assert!(data.ends_with("Question: two\nAnswer:"));
+}
+
+#[test]
+fn estimate_and_actual_cost() {
+    // Given
+    let mut pricing = pricing::PricingTable::new();
+    pricing.set(LUMINOUS_BASE, pricing::ModelPrice::new(1.0, 2.0));
+    let req = CompletionRequest::from_text(LUMINOUS_BASE, "one two one", 100).best_of(2);
+    let response: CompletionResponse = serde_json::from_str(
+        r#"{"model_version": "2022-04", "completions": [],
+            "num_tokens_prompt_total": 6, "num_tokens_generated": 50}"#,
+    )
+    .unwrap();
+
+    // When
+    let estimate = pricing
+        .estimate_cost(&req, &word_level_tokenizer())
+        .unwrap();
+    let actual = pricing.cost_of(LUMINOUS_BASE, &response).unwrap();
+    let unknown = pricing.cost_of(LUMINOUS_EXTENDED, &response);
+
+    // Then
+    assert_eq!((estimate.input_tokens, estimate.output_tokens), (6, 200));
+    assert!((estimate.credits - 0.406).abs() < 1e-9);
+    assert!((actual.credits - 0.106).abs() < 1e-9);
+    assert!((([estimate, actual].into_iter().sum::<pricing::Cost>()).credits - 0.512).abs() < 1e-9);
+    assert!(matches!(
+        unknown,
+        Err(pricing::PricingError::UnknownModel(_))
+    ));
+}
+
+#[tokio::test]
+async fn budget_stops_requests_once_used_up() {
+    // Given
+    let body = br#"{"model_version": "2022-04", "completions": [],
+        "num_tokens_prompt_total": 10, "num_tokens_generated": 50}"#;
+    let (url, _) = serve_once(http_response("application/json", body));
+    let base_url = url.trim_end_matches("/image").to_owned();
+    let client = Client::new_with_base_url(base_url, "token".to_owned())
+        .unwrap()
+        .with_budget(pricing::Budget::Tokens(50));
+    let req = CompletionRequest::from_text(LUMINOUS_BASE, "Hello", 50);
+
+    // When
+    let first = client.completion(&req, None).await;
+    let second = client.completion(&req, None).await;
+
+    // Then
+    assert!(first.is_ok());
+    assert_eq!(client.spent(), Some(60.0));
+    assert!(matches!(
+        second,
+        Err(ApiError::BudgetExceeded { spent, limit }) if spent == 60.0 && limit == 50.0
+    ));
+}
+
+#[tokio::test]
+async fn budget_estimates_usage_missing_in_response() {
+    // Given a client which has loaded the tokenizer of the model
+    let body = br#"{"model_version": "2022-04", "completions": []}"#;
+    let (url, requests) = serve_in_order(vec![
+        http_response("application/json", WORD_LEVEL_VOCABULARY.as_bytes()),
+        http_response("application/json", body),
+    ]);
+    let base_url = url.trim_end_matches("/image").to_owned();
+    let client = Client::new_with_base_url(base_url, "token".to_owned())
+        .unwrap()
+        .with_budget(pricing::Budget::Tokens(1000));
+    client.get_tokenizer(LUMINOUS_BASE).await.unwrap();
+    let req = CompletionRequest::from_text(LUMINOUS_BASE, "one two", 10);
+
+    // When
+    let response = client.completion(&req, None).await;
+
+    // Then the prompt is counted with the tokenizer
+    assert!(response.is_ok());
+    assert!(requests
+        .recv()
+        .unwrap()
+        .starts_with("GET /models/luminous-base/tokenizer"));
+    assert!(requests.recv().unwrap().starts_with("POST /complete"));
+    assert_eq!(client.spent(), Some(12.0));
+}
+
+#[tokio::test]
+async fn budget_estimates_usage_without_tokenizer() {
+    // Given
+    let body = br#"{"model_version": "2022-04", "completions": []}"#;
+    let (url, requests) = serve_in_order(vec![
+        http_response("application/json", body),
+        http_response("application/json", WORD_LEVEL_VOCABULARY.as_bytes()),
+    ]);
+    let base_url = url.trim_end_matches("/image").to_owned();
+    let client = Client::new_with_base_url(base_url, "token".to_owned())
+        .unwrap()
+        .with_budget(pricing::Budget::Tokens(1000));
+    let req = CompletionRequest::from_text(LUMINOUS_BASE, "one two", 10);
+
+    // When
+    let response = client.completion(&req, None).await;
+
+    // Then the tokenizer is not downloaded and every byte of the prompt counts as a token
+    assert!(response.is_ok());
+    assert!(requests.recv().unwrap().starts_with("POST /complete"));
+    assert!(requests.try_recv().is_err());
+    assert_eq!(client.spent(), Some(17.0));
+}
+
+#[tokio::test]
+async fn watch_credits_warns_when_low() {
+    // Given
+    let body = br#"{"id": 1, "email": "a@b.c", "role": "Client", "credits_remaining": "1.5",
+        "invoice_allowed": false, "out_of_credits_threshold": 0}"#;
+    let (url, request) = serve_once(http_response("application/json", body));
+    let base_url = url.trim_end_matches("/image").to_owned();
+    let client = Client::new_with_base_url(base_url, "token".to_owned()).unwrap();
+    let (sender, mut warnings) = tokio::sync::mpsc::unbounded_channel();
+
+    // When
+    let warning = tokio::select! {
+        _ = client.watch_credits(5.0, Duration::from_millis(10), |credits| {
+            sender.send(credits).unwrap();
+        }) => None,
+        warning = warnings.recv() => warning,
+    };
+
+    // Then
+    assert!(request.recv().unwrap().starts_with("GET /users/me"));
+    assert_eq!(warning, Some(1.5));
+}
+
+#[tokio::test]
+async fn usage_report_per_model() {
+    // Given
+    let body = br#"{"model_version": "2022-04", "completions": [],
+        "num_tokens_prompt_total": 10, "num_tokens_generated": 5}"#;
+    let (url, _) = serve_once(http_response("application/json", body));
+    let base_url = url.trim_end_matches("/image").to_owned();
+    let client = Client::new_with_base_url(base_url, "token".to_owned())
+        .unwrap()
+        .with_usage_tracking();
+    let req = CompletionRequest::from_text(LUMINOUS_BASE, "Hello", 5);
+
+    // When
+    client.completion(&req, None).await.unwrap();
+    // The test server only answers once, so this request fails
+    client.completion(&req, None).await.unwrap_err();
+    let report = client.usage_report().unwrap();
+
+    // Then
+    let usage = &report.models[LUMINOUS_BASE];
+    assert_eq!((usage.requests, usage.failed_requests), (2, 1));
+    assert_eq!((usage.prompt_tokens, usage.completion_tokens), (10, 5));
+    assert!(usage.max_latency >= usage.mean_latency());
+    assert_eq!(report.total(), *usage);
+}
+
+#[tokio::test]
+async fn identical_requests_are_coalesced() {
+    // Given
+    let body = br#"{"model_version": "2022-04", "completions": [
+        {"completion": " doctor", "finish_reason": "maximum_tokens"}]}"#;
+    let (url, _) = serve_once(http_response("application/json", body));
+    let base_url = url.trim_end_matches("/image").to_owned();
+    let client = Client::new_with_base_url(base_url, "token".to_owned())
+        .unwrap()
+        .with_request_coalescing()
+        .with_usage_tracking();
+    let req = CompletionRequest::from_text(LUMINOUS_BASE, "An apple a day keeps the", 5);
+
+    // When
+    // The test server only answers once, so the second request must share the first response
+    let (first, second) =
+        tokio::join!(client.completion(&req, None), client.completion(&req, None));
+
+    // Then
+    assert_eq!(first.unwrap().best_text(), " doctor");
+    assert_eq!(second.unwrap().best_text(), " doctor");
+    assert_eq!(client.usage_report().unwrap().total().requests, 1);
+}
+
+#[tokio::test]
+async fn requests_with_equal_logit_bias_are_coalesced() {
+    // Given two requests whose logit bias maps are filled separately, so their entries are
+    // likely iterated in different orders
+    let body = br#"{"model_version": "2022-04", "completions": [
+        {"completion": " doctor", "finish_reason": "maximum_tokens"}]}"#;
+    let (url, _) = serve_once(http_response("application/json", body));
+    let base_url = url.trim_end_matches("/image").to_owned();
+    let client = Client::new_with_base_url(base_url, "token".to_owned())
+        .unwrap()
+        .with_request_coalescing();
+    let logit_bias = || {
+        (0..32)
+            .map(|token| (token, 0.5))
+            .collect::<HashMap<i32, f32>>()
+    };
+    let first_req = CompletionRequest::from_text(LUMINOUS_BASE, "An apple a day keeps the", 5)
+        .logit_bias(logit_bias());
+    let second_req = CompletionRequest::from_text(LUMINOUS_BASE, "An apple a day keeps the", 5)
+        .logit_bias(logit_bias());
+
+    // When
+    let (first, second) = tokio::join!(
+        client.completion(&first_req, None),
+        client.completion(&second_req, None)
+    );
+
+    // Then
+    assert_eq!(first.unwrap().best_text(), " doctor");
+    assert_eq!(second.unwrap().best_text(), " doctor");
+}
+
+#[tokio::test]
+async fn batch_job_resumes_after_checkpoint() {
+    // Given
+    let path = std::env::temp_dir().join("aleph_alpha_api_batch_job.jsonl");
+    let _ = std::fs::remove_file(&path);
+    let inputs = [1, 2, 3, 4, 5];
+    let job = jobs::BatchJob::new()
+        .batch_size(2)
+        .retry(RetryPolicy::none());
+    let calls = std::sync::atomic::AtomicUsize::new(0);
+    let double = |input: &i32| {
+        calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let input = *input;
+        async move {
+            match input {
+                4 => Err(ApiError::UnexpectedResponse("four".to_owned())),
+                input => Ok(input * 2),
+            }
+        }
+    };
+
+    // When
+    // First run is interrupted after two batches, simulated by passing fewer inputs, and leaves
+    // a partially written line behind
+    let mut store = jobs::JsonLinesStore::new(&path);
+    job.run(&inputs[..4], &mut store, double).await.unwrap();
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap();
+    std::io::Write::write_all(&mut file, b"{\"Ok\":").unwrap();
+    let report = job.run(&inputs, &mut store, double).await.unwrap();
+
+    // Then
+    assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 5);
+    assert_eq!(report.resumed_from, 4);
+    assert_eq!(report.failed(), 1);
+    assert_eq!(report.results[0], Ok(2));
+    assert_eq!(report.results[4], Ok(10));
+    assert!(report.results[3].as_ref().unwrap_err().contains("four"));
+    let stored: Vec<jobs::JobResult<i32>> = jobs::CheckpointStore::load(&mut store).unwrap();
+    assert_eq!(stored, report.results);
+}
+
+#[test]
+fn latency_percentiles() {
+    // Given
+    let latencies: Vec<Duration> = (1..=100).rev().map(Duration::from_millis).collect();
+
+    // When
+    let stats = bench::LatencyStats::from_latencies(&latencies);
+
+    // Then
+    assert_eq!(stats.p50, Duration::from_millis(50));
+    assert_eq!(stats.p95, Duration::from_millis(95));
+    assert_eq!(stats.p99, Duration::from_millis(99));
+    assert_eq!(stats.max, Duration::from_millis(100));
+    assert_eq!(stats.mean, Duration::from_micros(50_500));
+}
+
+#[tokio::test]
+async fn measure_counts_errors_and_tokens() {
+    // When
+    let result = bench::measure("fake", 10, 3, |call| async move {
+        match call % 5 {
+            0 => Err(ApiError::Busy),
+            _ => Ok(7),
+        }
+    })
+    .await;
+
+    // Then
+    assert_eq!(result.scenario, "fake");
+    assert_eq!((result.requests, result.errors), (10, 2));
+    assert_eq!(result.transient_errors, 2);
+    assert_eq!(result.generated_tokens, 56);
+    assert!((result.error_rate() - 0.2).abs() < 1e-9);
+}
+
+#[test]
+fn sampling_configuration_with_defaults() {
+    // Given
+    let configurations: report::NamedConfigurations = serde_json::from_str(
+        r#"{"default": {"generate_args": {"max_new_tokens": 50, "top_k": 10},
+                        "user_name": "User:", "assistant_name": "Assistant:"},
+            "hot": {"generate_args": {"temperature": 1.5, "presence_penalty": 0.5},
+                    "system_prompt": "Be creative."}}"#,
+    )
+    .unwrap();
+
+    // When
+    let hot = configurations["hot"].merge_with_default(configurations.get("default"));
+    let req = hot
+        .completion_request(LUMINOUS_BASE, "Tell a story.")
+        .unwrap();
+
+    // Then
+    assert_eq!(hot.generate_args.top_k, Some(10));
+    let Modality::Text { data, .. } = &req.prompt.items()[0] else {
+        panic!("expected a text prompt");
+    };
+    assert_eq!(data, "Be creative.\nUser: Tell a story.\nAssistant:");
+    assert_eq!(req.maximum_tokens, 50);
+    assert_eq!(req.temperature, Some(1.5));
+    assert_eq!(req.repetition_penalties_include_completion, Some(true));
+    assert_eq!(req.stop_sequences, Some(vec!["User:".to_owned()]));
+    assert!(configurations["hot"].format_prompt("Hi").is_none());
+}